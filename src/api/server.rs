@@ -0,0 +1,539 @@
+use crate::configuration::config::Class;
+use crate::events::{Event, EventBus};
+use crate::log_error;
+use crate::log_info;
+use crate::log_warn;
+use crate::simulation::cancellation::CancellationToken;
+use crate::simulation::char_state::CharState;
+use crate::simulation::maze_export::MapFormat;
+use crate::simulation::recorder::RecordedAction;
+use crate::simulation::simulation_state::SimulationState;
+use crate::simulation::skill_tracker::TrackerSnapshot;
+use colored::Colorize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::Error;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{io, thread};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+const DASHBOARD: &str = include_str!("dashboard.html");
+const WATCH_INTERVAL_MS: u64 = 250;
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CharacterStatus {
+    pub id: usize,
+    pub title: Option<String>,
+    pub class: Class,
+    pub level: Option<u32>,
+    pub state: CharState,
+    pub enabled: bool,
+    #[serde(flatten)]
+    pub tracker: TrackerSnapshot,
+    pub last_action: Option<RecordedAction>,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Command {
+    Enable(usize),
+    Disable(usize),
+    // None means the whole party
+    Reset(Option<usize>),
+    Reload(Option<usize>),
+    Stop,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum Route {
+    Dashboard,
+    Status,
+    Events,
+    Map(usize, MapFormat),
+    Command(Command),
+}
+
+fn parse_route(method: &Method, url: &str) -> Option<Route> {
+    let path = url.split('?').next().unwrap_or_default();
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match (method, segments.as_slice()) {
+        (Method::Get, []) => Some(Route::Dashboard),
+        (Method::Get, ["status"]) => Some(Route::Status),
+        (Method::Get, ["events"]) => Some(Route::Events),
+        (Method::Get, ["characters", id, file]) => {
+            let format = match *file {
+                "map" | "map.json" => MapFormat::Json,
+                "map.txt" => MapFormat::Ascii,
+                "map.svg" => MapFormat::Svg,
+                _ => return None,
+            };
+            Some(Route::Map(id.parse().ok()?, format))
+        }
+        (Method::Post, ["reset"]) => Some(Route::Command(Command::Reset(None))),
+        (Method::Post, ["reload"]) => Some(Route::Command(Command::Reload(None))),
+        (Method::Post, ["stop"]) => Some(Route::Command(Command::Stop)),
+        (Method::Post, ["characters", id, action]) => {
+            let id = id.parse().ok()?;
+            let command = match *action {
+                "enable" => Command::Enable(id),
+                "disable" => Command::Disable(id),
+                "reset" => Command::Reset(Some(id)),
+                "reload" => Command::Reload(Some(id)),
+                _ => return None,
+            };
+            Some(Route::Command(command))
+        }
+        _ => None,
+    }
+}
+
+// None when the request may go on, otherwise the status code to refuse it with
+fn refusal(token: Option<&str>, url: &str, headers: &[Header]) -> Option<u16> {
+    let value = |name: &'static str| {
+        headers
+            .iter()
+            .find(|h| h.field.equiv(name))
+            .map(|h| h.value.as_str())
+    };
+    // browsers send the Origin of the page, only the dashboard itself may call us
+    if let Some(origin) = value("Origin") {
+        let host = value("Host").unwrap_or_default();
+        let from = origin
+            .strip_prefix("http://")
+            .or_else(|| origin.strip_prefix("https://"));
+        if from != Some(host) {
+            return Some(403);
+        }
+    }
+    let token = token?;
+    // websockets cannot have headers in a browser, so the dashboard sends it in the query
+    let in_query = url
+        .split_once('?')
+        .into_iter()
+        .flat_map(|(_, query)| query.split('&'))
+        .find_map(|param| param.strip_prefix("token="));
+    let in_header = value("Authorization").and_then(|v| v.strip_prefix("Bearer "));
+    if in_query == Some(token) || in_header == Some(token) {
+        None
+    } else {
+        Some(401)
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("Invalid header")
+}
+
+fn json_response(body: &impl Serialize) -> Response<io::Cursor<Vec<u8>>> {
+    let body = serde_json::to_string(body).unwrap_or_else(|e| format!(r#"{{"error":"{}"}}"#, e));
+    Response::from_string(body).with_header(header("Content-Type", "application/json"))
+}
+
+// status and control of the characters for a dashboard on the LAN
+pub struct Api {
+    simulations: Vec<Arc<SimulationState>>,
+    // required from every request when set
+    token: Option<String>,
+    events: Arc<EventBus>,
+    last_actions: Mutex<HashMap<usize, RecordedAction>>,
+    on_stop: Box<dyn Fn() + Send + Sync>,
+    cancellation: CancellationToken,
+}
+
+impl Api {
+    pub fn new(
+        simulations: Vec<Arc<SimulationState>>,
+        token: Option<String>,
+        events: Arc<EventBus>,
+        on_stop: Box<dyn Fn() + Send + Sync>,
+    ) -> Api {
+        Api {
+            simulations,
+            token,
+            events,
+            last_actions: Mutex::new(HashMap::new()),
+            on_stop,
+            cancellation: CancellationToken::new(),
+        }
+    }
+
+    // no more requests and state watching, open event streams just end with the process
+    pub fn stop(&self) {
+        self.cancellation.cancel();
+    }
+
+    fn remember(&self, event: &Event) {
+        if let Event::Action(record) = event {
+            self.last_actions
+                .lock()
+                .unwrap()
+                .insert(record.window, record.clone());
+        }
+    }
+
+    fn states(&self) -> HashMap<usize, CharState> {
+        self.simulations
+            .first()
+            .and_then(|sim| sim.shared_state.get_member_states().ok())
+            .map(|states| states.into_iter().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn status(&self) -> Vec<CharacterStatus> {
+        let states = self.states();
+        let last_actions = self.last_actions.lock().unwrap();
+        self.simulations
+            .iter()
+            .map(|sim| {
+                let class_config = &sim.window_config.class_config;
+                let skills = sim.rotation.read().unwrap().skills.clone();
+                CharacterStatus {
+                    id: sim.id,
+                    title: sim.window_config.title.clone(),
+                    class: class_config.class,
+                    level: *sim.level.lock().unwrap(),
+                    state: states.get(&sim.id).copied().unwrap_or(CharState::Unknown),
+                    enabled: sim.is_enabled.load(Ordering::SeqCst),
+                    tracker: sim
+                        .skill_tracker
+                        .snapshot(&skills, class_config.cd_reductions.as_ref()),
+                    last_action: last_actions.get(&sim.id).cloned(),
+                }
+            })
+            .collect()
+    }
+
+    // the solver's map of the character, None if the character does not exist
+    pub fn map(&self, id: usize, format: MapFormat) -> Option<String> {
+        self.selected(Some(id))
+            .first()
+            .map(|sim| sim.maze_solver.snapshot().render(format))
+    }
+
+    fn selected(&self, id: Option<usize>) -> Vec<&Arc<SimulationState>> {
+        self.simulations
+            .iter()
+            .filter(|sim| id.is_none_or(|id| sim.id == id))
+            .collect()
+    }
+
+    // false if the character does not exist or the command failed
+    pub fn execute(&self, command: Command) -> bool {
+        log_info!("{}", format!("API command: {:?}", command).cyan());
+        let sims = match command {
+            Command::Enable(id) | Command::Disable(id) => self.selected(Some(id)),
+            Command::Reset(id) | Command::Reload(id) => self.selected(id),
+            Command::Stop => self.selected(None),
+        };
+        if sims.is_empty() {
+            return false;
+        }
+        let done = match command {
+            Command::Enable(_) | Command::Disable(_) => {
+                let enabled = matches!(command, Command::Enable(_));
+                sims.iter().for_each(|sim| sim.set_enabled(enabled));
+                true
+            }
+            Command::Reset(None) => {
+                sims.iter().for_each(|sim| sim.reset());
+                true
+            }
+            Command::Reset(Some(_)) => {
+                sims.iter().for_each(|sim| sim.reset_window());
+                true
+            }
+            Command::Reload(_) => {
+                // reload the rest even if one of them fails
+                let failed = sims
+                    .iter()
+                    .filter_map(|sim| sim.reload_rotation().err())
+                    .inspect(|e| log_error!("{}", format!("Reload failed: {}", e).red()))
+                    .count();
+                failed == 0
+            }
+            Command::Stop => {
+                (self.on_stop)();
+                true
+            }
+        };
+        self.events.publish(Event::Command {
+            command: format!("{:?}", command),
+        });
+        done
+    }
+
+    pub fn start(self: Arc<Self>, address: &str) -> io::Result<()> {
+        let server = Server::http(address).map_err(Error::other)?;
+        log_info!("API listening on http://{}", address);
+        let loopback = server
+            .server_addr()
+            .to_ip()
+            .is_some_and(|addr| addr.ip().is_loopback());
+        if !loopback && self.token.is_none() {
+            log_warn!(
+                "{}",
+                "The API is reachable from the network without a token, set api_token".yellow()
+            );
+        }
+        thread::spawn({
+            let api = self.clone();
+            move || api.watch()
+        });
+        thread::spawn({
+            let api = self.clone();
+            let events = self.events.subscribe();
+            move || events.iter().for_each(|event| api.remember(&event))
+        });
+        thread::spawn(move || {
+            while !self.cancellation.is_cancelled() {
+                match server.recv_timeout(Duration::from_millis(WATCH_INTERVAL_MS)) {
+                    Ok(Some(request)) => {
+                        let api = self.clone();
+                        // websockets stay open, let's not block the other requests
+                        thread::spawn(move || api.handle(request));
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        log_error!("API stopped receiving requests: {}", e);
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    // turns state and enabled flag changes into events
+    fn watch(&self) {
+        let mut previous: HashMap<usize, (CharState, bool)> = HashMap::new();
+        while !self.cancellation.is_cancelled() {
+            let states = self.states();
+            for sim in &self.simulations {
+                let state = states.get(&sim.id).copied().unwrap_or(CharState::Unknown);
+                let enabled = sim.is_enabled.load(Ordering::SeqCst);
+                let (previous_state, previous_enabled) = previous
+                    .insert(sim.id, (state, enabled))
+                    .unwrap_or((state, enabled));
+                if previous_state != state {
+                    self.events.publish(Event::State {
+                        window: sim.id,
+                        state,
+                    });
+                }
+                if previous_enabled != enabled {
+                    self.events.publish(Event::Enabled {
+                        window: sim.id,
+                        enabled,
+                    });
+                }
+            }
+            self.cancellation.sleep_ms(WATCH_INTERVAL_MS);
+        }
+    }
+
+    fn handle(&self, request: Request) {
+        if let Some(code) = refusal(self.token.as_deref(), request.url(), request.headers()) {
+            log_warn!("API refused {} {}", request.method(), request.url());
+            let _ = request.respond(Response::from_string("Forbidden").with_status_code(code));
+            return;
+        }
+        let route = parse_route(request.method(), request.url());
+        let result = match route {
+            Some(Route::Dashboard) => request.respond(
+                Response::from_string(DASHBOARD)
+                    .with_header(header("Content-Type", "text/html; charset=utf-8")),
+            ),
+            Some(Route::Status) => request.respond(json_response(&self.status())),
+            Some(Route::Events) => {
+                self.stream_events(request);
+                Ok(())
+            }
+            Some(Route::Map(id, format)) => match self.map(id, format) {
+                Some(map) => request.respond(
+                    Response::from_string(map)
+                        .with_header(header("Content-Type", format.content_type())),
+                ),
+                None => request.respond(Response::from_string("Not found").with_status_code(404)),
+            },
+            Some(Route::Command(command)) => {
+                let done = self.execute(command);
+                request.respond(json_response(&done).with_status_code(if done { 200 } else { 400 }))
+            }
+            None => request.respond(Response::from_string("Not found").with_status_code(404)),
+        };
+        if let Err(e) = result {
+            log_error!("API response failed: {}", e);
+        }
+    }
+
+    fn stream_events(&self, request: Request) {
+        let Some(key) = request
+            .headers()
+            .iter()
+            .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+            .map(|h| h.value.to_string())
+        else {
+            let _ = request
+                .respond(Response::from_string("Expected a websocket").with_status_code(400));
+            return;
+        };
+        let response = Response::empty(StatusCode(101))
+            .with_header(header("Upgrade", "websocket"))
+            .with_header(header("Connection", "Upgrade"))
+            .with_header(header(
+                "Sec-WebSocket-Accept",
+                &derive_accept_key(key.as_bytes()),
+            ));
+        let stream = request.upgrade("websocket", response);
+        let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+        let events = self.events.subscribe();
+        log_info!(
+            "Event stream connected ({} listening)",
+            self.events.subscriber_count()
+        );
+        // ends with the client or the bus
+        while let Ok(event) = events.recv() {
+            let Ok(text) = serde_json::to_string(&event) else {
+                continue;
+            };
+            if socket.send(Message::text(text)).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::server::{Api, Command, Route, header, parse_route, refusal};
+    use crate::configuration::config::Config;
+    use crate::events::{Event, EventBus};
+    use crate::simulation::char_state::CharState;
+    use crate::simulation::maze_export::MapFormat;
+    use crate::simulation::recorder::{Action, RecordedAction};
+    use crate::simulation::rotation::Rotation;
+    use crate::simulation::shared_state::SharedStateHandle;
+    use crate::simulation::simulation_state::SimulationState;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tiny_http::Method;
+
+    #[test]
+    fn test_parse_route() {
+        assert_eq!(parse_route(&Method::Get, "/"), Some(Route::Dashboard));
+        assert_eq!(
+            parse_route(&Method::Get, "/status?x=1"),
+            Some(Route::Status)
+        );
+        assert_eq!(parse_route(&Method::Get, "/events"), Some(Route::Events));
+        assert_eq!(
+            parse_route(&Method::Post, "/characters/2/disable"),
+            Some(Route::Command(Command::Disable(2)))
+        );
+        assert_eq!(
+            parse_route(&Method::Post, "/reload"),
+            Some(Route::Command(Command::Reload(None)))
+        );
+        assert_eq!(
+            parse_route(&Method::Get, "/characters/0/map.svg"),
+            Some(Route::Map(0, MapFormat::Svg))
+        );
+        assert_eq!(parse_route(&Method::Get, "/characters/0/map.png"), None);
+        assert_eq!(parse_route(&Method::Get, "/stop"), None);
+        assert_eq!(parse_route(&Method::Post, "/characters/x/enable"), None);
+        assert_eq!(parse_route(&Method::Post, "/characters/1/dance"), None);
+    }
+
+    #[test]
+    fn test_refusal() {
+        let headers = |extra: &[(&str, &str)]| {
+            let mut headers = vec![header("Host", "192.168.1.10:8080")];
+            headers.extend(extra.iter().map(|(name, value)| header(name, value)));
+            headers
+        };
+        // no browser, no token configured
+        assert_eq!(refusal(None, "/status", &headers(&[])), None);
+        // the dashboard itself
+        let dashboard = headers(&[("Origin", "http://192.168.1.10:8080")]);
+        assert_eq!(refusal(None, "/stop", &dashboard), None);
+        // some other page the browser has open
+        let foreign = headers(&[("Origin", "http://evil.example")]);
+        assert_eq!(refusal(None, "/stop", &foreign), Some(403));
+
+        let token = Some("secret");
+        assert_eq!(refusal(token, "/status", &headers(&[])), Some(401));
+        assert_eq!(
+            refusal(token, "/status?token=guess", &headers(&[])),
+            Some(401)
+        );
+        assert_eq!(refusal(token, "/events?token=secret", &headers(&[])), None);
+        let bearer = headers(&[("Authorization", "Bearer secret")]);
+        assert_eq!(refusal(token, "/reset", &bearer), None);
+    }
+
+    #[test]
+    fn test_status_and_commands() {
+        let cfg = Config::default();
+        let shared_state = Arc::new(SharedStateHandle::new(0.0, 0.0));
+        let sim = Arc::new(SimulationState::for_test(
+            0,
+            cfg.windows.first().unwrap().clone(),
+            Rotation {
+                skills: vec![],
+                rules: vec![],
+            },
+            shared_state.clone(),
+        ));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let events = Arc::new(EventBus::default());
+        let api = Api::new(
+            vec![sim.clone()],
+            None,
+            events.clone(),
+            Box::new({
+                let stopped = stopped.clone();
+                move || stopped.store(true, Ordering::SeqCst)
+            }),
+        );
+        let received = events.subscribe();
+
+        shared_state
+            .set_member_state(0, CharState::Looting)
+            .unwrap();
+        api.remember(&Event::Action(RecordedAction {
+            window: 0,
+            timestamp_ms: 0,
+            action: Action::Loot,
+            inputs: vec![],
+            result: true,
+        }));
+
+        assert!(api.execute(Command::Enable(0)));
+        assert!(!api.execute(Command::Enable(1)));
+        let status = api.status();
+        assert_eq!(status.len(), 1);
+        assert_eq!(status[0].state, CharState::Looting);
+        assert!(status[0].enabled);
+        assert_eq!(status[0].last_action.as_ref().unwrap().action, Action::Loot);
+        assert!(api.map(0, MapFormat::Ascii).unwrap().starts_with('@'));
+        assert_eq!(api.map(1, MapFormat::Json), None);
+
+        assert!(api.execute(Command::Disable(0)));
+        assert!(!sim.is_enabled.load(Ordering::SeqCst));
+        // one window keeps the party-wide flags
+        shared_state.set_frenzy_applied(true);
+        assert!(api.execute(Command::Reset(Some(0))));
+        assert!(shared_state.get_frenzy_applied());
+        assert!(api.execute(Command::Reset(None)));
+        assert!(!shared_state.get_frenzy_applied());
+        assert!(api.execute(Command::Stop));
+        assert!(stopped.load(Ordering::SeqCst));
+        assert!(matches!(received.recv().unwrap(), Event::Command { .. }));
+
+        shared_state.stop();
+    }
+}
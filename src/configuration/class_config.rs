@@ -1,4 +1,6 @@
 use crate::configuration::config::Class;
+use crate::simulation::inventory::InventoryRule;
+use crate::simulation::level_up::LevelUpPlan;
 use crate::simulation::loot::{LootQuality, LootTier};
 use serde::{Deserialize, Serialize};
 
@@ -10,6 +12,10 @@ pub struct ClassConfig {
     pub cd_reductions: Option<Vec<(String, f32)>>,
     pub cast_time_reductions: Option<Vec<(String, f32)>>,
     pub loot_filter: Vec<LootFilterItem>,
+    #[serde(default)]
+    pub inventory_rules: Vec<InventoryRule>,
+    #[serde(default)]
+    pub level_up_plan: LevelUpPlan,
     pub auto_attack: AutoAttack,
 }
 
@@ -23,6 +29,7 @@ pub enum AutoAttack {
 pub struct LootFilterItem(pub LootQuality, pub LootTier);
 
 impl ClassConfig {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         class: Class,
         cast_all_skills: Option<Vec<String>>,
@@ -30,6 +37,7 @@ impl ClassConfig {
         cd_reductions: Option<Vec<(String, f32)>>,
         cast_time_reductions: Option<Vec<(String, f32)>>,
         loot_filter: Vec<LootFilterItem>,
+        inventory_rules: Vec<InventoryRule>,
         auto_attack: AutoAttack,
     ) -> ClassConfig {
         ClassConfig {
@@ -39,6 +47,8 @@ impl ClassConfig {
             cd_reductions,
             cast_time_reductions,
             loot_filter,
+            inventory_rules,
+            level_up_plan: LevelUpPlan::default(),
             auto_attack,
         }
     }
@@ -1,8 +1,15 @@
 use crate::configuration::class_config::{AutoAttack, ClassConfig, LootFilterItem};
 use crate::configuration::hex_hwnd;
+use crate::hotkeys::{HotkeyBinding, get_default_hotkeys};
+use crate::simulation::death::DeathConfig;
+use crate::simulation::exploration::Exploration;
+use crate::simulation::inventory::InventoryAction::*;
+use crate::simulation::inventory::InventoryRule;
+use crate::simulation::loot::LootQuality;
 use crate::simulation::loot::LootQuality::*;
 use crate::simulation::loot::LootTier;
 use crate::simulation::loot::LootTier::*;
+use crate::simulation::readiness::ReadinessConfig;
 use clap::Parser;
 use serde::{Deserialize, Serialize};
 use std::fmt::{Display, Formatter};
@@ -25,6 +32,15 @@ pub struct Args {
     pub debug_sim: bool,
     #[arg(short = 'c', long = "debug-checker", default_value = "false")]
     pub debug_checker: bool,
+    // records every action sent to the windows as JSON lines
+    #[arg(long = "record")]
+    pub record: Option<String>,
+    // replays a recording against the configured windows (or simulated ones with --debug-sim)
+    #[arg(long = "replay")]
+    pub replay: Option<String>,
+    // one panel per character instead of the scrolling log
+    #[arg(long = "tui", default_value = "false")]
+    pub tui: bool,
 }
 
 #[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone)]
@@ -52,12 +68,32 @@ pub struct WindowConfig {
     pub position_y: i32,
     pub active: bool,
     pub master: bool,
+    // current character level, used to leave out skills not learned yet
+    #[serde(default)]
+    pub level: Option<u32>,
+    // file name of a script in the scripts folder
+    #[serde(default)]
+    pub script: Option<String>,
+    // walks the steps of the master, for followers the game's own party follow leaves behind
+    #[serde(default)]
+    pub follow: bool,
     pub class_config: ClassConfig,
 }
 
 unsafe impl Send for WindowConfig {}
 unsafe impl Sync for WindowConfig {}
 
+// what the characters do on their own
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct Automation {
+    pub leave_when_full: bool,
+    pub auto_explore: bool,
+    #[serde(default)]
+    pub auto_town_cycle: bool,
+    #[serde(default)]
+    pub manage_inventory: bool,
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Config {
     pub skill_haste_percent: f32,
@@ -65,8 +101,27 @@ pub struct Config {
     pub sync_interval_ms: u64,
     pub cast_leeway_ms: u64,
     pub start_offset_ms: u64,
-    pub leave_when_full: bool,
-    pub auto_explore: bool,
+    // the switches stay at the top level of the file
+    #[serde(flatten)]
+    pub automation: Automation,
+    // DepthFirst, Frontier, WallFollower or e.g. { TimeBoxed = { strategy = "Frontier", rooms = 30 } }
+    #[serde(default)]
+    pub exploration: Exploration,
+    // e.g. "127.0.0.1:8080", or "0.0.0.0:8080" for a dashboard on the LAN, no API when not set
+    #[serde(default)]
+    pub api_address: Option<String>,
+    // required from every API request when set, the dashboard takes it as /?token=...
+    #[serde(default)]
+    pub api_token: Option<String>,
+    // DELETE, BACKSPACE and ESC for the whole party plus Ctrl+1..5 for single windows when not set
+    #[serde(default = "get_default_hotkeys")]
+    pub hotkeys: Vec<HotkeyBinding>,
+    // what the master waits for before each exploration step
+    #[serde(default)]
+    pub readiness: ReadinessConfig,
+    // what happens when a party member dies
+    #[serde(default)]
+    pub death: DeathConfig,
     pub windows: Vec<WindowConfig>,
 }
 
@@ -78,8 +133,13 @@ impl Default for Config {
             sync_interval_ms: 500,
             cast_leeway_ms: 0,
             start_offset_ms: 100,
-            leave_when_full: false,
-            auto_explore: false,
+            automation: Automation::default(),
+            exploration: Exploration::default(),
+            api_address: None,
+            api_token: None,
+            hotkeys: get_default_hotkeys(),
+            readiness: ReadinessConfig::default(),
+            death: DeathConfig::default(),
             windows: vec![
                 WindowConfig {
                     title: Some("[#] [Steam1] Nevergrind [#]".into()),
@@ -90,6 +150,9 @@ impl Default for Config {
                     position_y: 0,
                     active: true,
                     master: false,
+                    level: None,
+                    script: None,
+                    follow: false,
                     class_config: ClassConfig::new(
                         Class::Enchanter,
                         Some(vec!["Clarity".to_string()]),
@@ -97,6 +160,7 @@ impl Default for Config {
                         Some(vec![("Enthrall".to_string(), 57.0)]),
                         None,
                         get_default_loot_filter(),
+                        get_default_inventory_rules(),
                         AutoAttack::Primary,
                     ),
                 },
@@ -109,6 +173,9 @@ impl Default for Config {
                     position_y: 0,
                     active: true,
                     master: false,
+                    level: None,
+                    script: None,
+                    follow: false,
                     class_config: ClassConfig::new(
                         Class::Warlock,
                         None,
@@ -116,6 +183,7 @@ impl Default for Config {
                         Some(vec![("Engulfing Darkness".to_string(), 87.3), ("Icing Death".to_string(), 57.1)]),
                         Some(vec![("Icing Death".to_string(), 32.4)]),
                         get_default_loot_filter(),
+                        get_default_inventory_rules(),
                         AutoAttack::Primary,
                     ),
                 },
@@ -128,6 +196,9 @@ impl Default for Config {
                     position_y: 0,
                     active: true,
                     master: true,
+                    level: None,
+                    script: None,
+                    follow: false,
                     class_config: ClassConfig::new(
                         Class::Warrior,
                         None,
@@ -139,6 +210,7 @@ impl Default for Config {
                         None,
                         None,
                         get_default_loot_filter(),
+                        get_default_inventory_rules(),
                         AutoAttack::Primary,
                     ),
                 },
@@ -165,6 +237,19 @@ fn get_default_loot_filter() -> Vec<LootFilterItem> {
     ]
 }
 
+fn get_default_inventory_rules() -> Vec<InventoryRule> {
+    vec![
+        InventoryRule(LootFilterItem(LootQuality::Normal, LootTier::Normal), Sell),
+        InventoryRule(LootFilterItem(LootQuality::Normal, Exceptional), Sell),
+        InventoryRule(LootFilterItem(Magic, LootTier::Normal), Sell),
+        InventoryRule(LootFilterItem(Magic, Exceptional), Sell),
+        InventoryRule(LootFilterItem(Rare, LootTier::Normal), Salvage),
+        InventoryRule(LootFilterItem(Set, Elite), Stash),
+        InventoryRule(LootFilterItem(Legendary, Elite), Stash),
+        InventoryRule(LootFilterItem(Rune, LootTier::Normal), Stash),
+    ]
+}
+
 const CFG_FILENAME: &str = "config.toml";
 fn get_config_path(filename: &str) -> Option<PathBuf> {
     let exe_path = env::current_exe().ok()?;
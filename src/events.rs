@@ -0,0 +1,89 @@
+use crate::logger::Level;
+use crate::simulation::char_state::CharState;
+use crate::simulation::recorder::RecordedAction;
+use serde::Serialize;
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, LazyLock, Mutex};
+
+// logs, actions and state changes for the API and the TUI
+pub static EVENTS: LazyLock<Arc<EventBus>> = LazyLock::new(|| Arc::new(EventBus::default()));
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum Event {
+    State {
+        window: usize,
+        state: CharState,
+    },
+    Enabled {
+        window: usize,
+        enabled: bool,
+    },
+    Action(RecordedAction),
+    Command {
+        command: String,
+    },
+    Log {
+        window: Option<usize>,
+        level: Level,
+        message: String,
+    },
+}
+
+// fans the events out to every subscriber (websocket clients)
+#[derive(Debug, Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<Event>>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (tx, rx) = channel();
+        self.subscribers.lock().unwrap().push(tx);
+        rx
+    }
+
+    pub fn publish(&self, event: Event) {
+        // a failed send means the subscriber is gone
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{Event, EventBus};
+    use crate::simulation::char_state::CharState;
+
+    #[test]
+    fn test_event_bus() {
+        let bus = EventBus::default();
+        let first = bus.subscribe();
+        let second = bus.subscribe();
+        let event = Event::State {
+            window: 1,
+            state: CharState::Fighting,
+        };
+
+        bus.publish(event.clone());
+        assert_eq!(first.recv().unwrap(), event);
+        assert_eq!(second.recv().unwrap(), event);
+
+        drop(second);
+        bus.publish(Event::Enabled {
+            window: 1,
+            enabled: false,
+        });
+        assert_eq!(bus.subscriber_count(), 1);
+        assert_eq!(
+            serde_json::to_string(&first.recv().unwrap()).unwrap(),
+            r#"{"type":"Enabled","window":1,"enabled":false}"#
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use crate::win_util::PixelColor;
+#[cfg(test)]
+use std::path::Path;
+#[cfg(test)]
+use std::{fs, io};
+
+// a single captured image of a window client area (top-down, row by row)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Frame {
+    pub width: i32,
+    pub height: i32,
+    pub pixels: Vec<PixelColor>,
+}
+
+impl Frame {
+    pub fn new(width: i32, height: i32, pixels: Vec<PixelColor>) -> Frame {
+        assert_eq!(
+            pixels.len(),
+            (width.max(0) * height.max(0)) as usize,
+            "Frame size does not match the pixel count"
+        );
+        Frame {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    // a frame filled with a single color
+    #[cfg(test)]
+    pub fn filled(width: i32, height: i32, color: PixelColor) -> Frame {
+        Frame::new(
+            width,
+            height,
+            vec![color; (width.max(0) * height.max(0)) as usize],
+        )
+    }
+
+    // the same BGRA layout as returned by GetDIBits
+    pub fn from_bgra(width: i32, height: i32, buffer: &[u8]) -> Frame {
+        let pixels = buffer
+            .chunks_exact(4)
+            .take((width.max(0) * height.max(0)) as usize)
+            .map(|px| {
+                let blue = px[0];
+                let green = px[1];
+                let red = px[2];
+                PixelColor((red as u32) | ((green as u32) << 8) | ((blue as u32) << 16))
+            })
+            .collect();
+        Frame::new(width, height, pixels)
+    }
+
+    // screenshots saved by debug_screen (32 bit) or by an image editor (24 bit), used as test fixtures
+    #[cfg(test)]
+    pub fn load_bmp<P: AsRef<Path>>(path: P) -> io::Result<Frame> {
+        let bytes = fs::read(path)?;
+        Frame::from_bmp(&bytes)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unsupported bitmap"))
+    }
+
+    #[cfg(test)]
+    pub fn from_bmp(bytes: &[u8]) -> Option<Frame> {
+        let read_u16 = |at: usize| -> Option<u16> {
+            Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+        };
+        let read_i32 = |at: usize| -> Option<i32> {
+            Some(i32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+        };
+        if bytes.get(0..2)? != b"BM" {
+            return None;
+        }
+        let offset = read_i32(10)? as usize;
+        let width = read_i32(18)?;
+        let height = read_i32(22)?;
+        let bytes_per_pixel = match read_u16(28)? {
+            32 => 4,
+            24 => 3,
+            _ => return None,
+        };
+        if width <= 0 || height == 0 {
+            return None;
+        }
+        // negative height means top-down, rows are padded to 4 bytes
+        let top_down = height < 0;
+        let height = height.abs();
+        let stride = (width as usize * bytes_per_pixel).div_ceil(4) * 4;
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height as usize {
+            let row = if top_down { y } else { height as usize - 1 - y };
+            let start = offset + row * stride;
+            let data = bytes.get(start..start + width as usize * bytes_per_pixel)?;
+            pixels.extend(data.chunks_exact(bytes_per_pixel).map(|px| {
+                PixelColor((px[2] as u32) | ((px[1] as u32) << 8) | ((px[0] as u32) << 16))
+            }));
+        }
+        Some(Frame::new(width, height, pixels))
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Option<PixelColor> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        self.pixels.get((y * self.width + x) as usize).copied()
+    }
+
+    #[cfg(test)]
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: PixelColor) {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    // same clamping as scan_line
+    pub fn line(&self, x1: i32, x2: i32, y: i32) -> Vec<PixelColor> {
+        if self.width <= 0 || self.height <= 0 {
+            return vec![];
+        }
+        let px1 = x1.clamp(0, self.width - 1);
+        let px2 = x2.clamp(0, self.width - 1);
+        let py = y.clamp(0, self.height - 1);
+        (px1..=px2).filter_map(|x| self.pixel(x, py)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::Frame;
+    use crate::win_util::PixelColor;
+
+    #[test]
+    fn test_frame_from_bgra() {
+        // 2x1 frame: blue pixel, then red pixel
+        let buffer = [0xFF, 0x00, 0x00, 0x00, 0x00, 0x00, 0xFF, 0x00];
+        let frame = Frame::from_bgra(2, 1, &buffer);
+        assert_eq!(frame.pixel(0, 0), Some(PixelColor(0xFF0000)));
+        assert_eq!(frame.pixel(1, 0), Some(PixelColor(0x0000FF)));
+        assert_eq!(frame.pixel(2, 0), None);
+    }
+
+    #[test]
+    fn test_frame_line() {
+        let mut frame = Frame::filled(10, 5, PixelColor(0x000000));
+        frame.set_pixel(3, 2, PixelColor(0xFFFFFF));
+        let line = frame.line(-5, 4, 2);
+        assert_eq!(line.len(), 5);
+        assert_eq!(line[3], PixelColor(0xFFFFFF));
+        assert_eq!(frame.line(0, 100, 100).len(), 10);
+    }
+
+    #[test]
+    fn test_frame_from_bmp() {
+        // 2x2 bottom-up 24 bit bitmap, each row padded to 8 bytes
+        let mut bmp = vec![0u8; 54];
+        bmp[0..2].copy_from_slice(b"BM");
+        bmp[10..14].copy_from_slice(&54i32.to_le_bytes());
+        bmp[18..22].copy_from_slice(&2i32.to_le_bytes());
+        bmp[22..26].copy_from_slice(&2i32.to_le_bytes());
+        bmp[28..30].copy_from_slice(&24u16.to_le_bytes());
+        // bottom row: red, black
+        bmp.extend([0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        // top row: black, blue
+        bmp.extend([0x00, 0x00, 0x00, 0xFF, 0x00, 0x00, 0x00, 0x00]);
+
+        let frame = Frame::from_bmp(&bmp).expect("Could not parse bitmap");
+        assert_eq!(frame.width, 2);
+        assert_eq!(frame.height, 2);
+        assert_eq!(frame.pixel(1, 0), Some(PixelColor(0xFF0000)));
+        assert_eq!(frame.pixel(0, 1), Some(PixelColor(0x0000FF)));
+        assert_eq!(Frame::from_bmp(&bmp[..60]), None);
+    }
+}
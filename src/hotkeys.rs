@@ -0,0 +1,419 @@
+use crate::log_info;
+use crate::simulation::simulation_state::SimulationState;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_SHIFT, MOD_WIN, VIRTUAL_KEY, VK_0, VK_1, VK_2,
+    VK_3, VK_4, VK_5, VK_6, VK_7, VK_8, VK_9, VK_A, VK_B, VK_BACK, VK_C, VK_D, VK_DELETE, VK_DOWN,
+    VK_E, VK_END, VK_ESCAPE, VK_F, VK_F1, VK_F2, VK_F3, VK_F4, VK_F5, VK_F6, VK_F7, VK_F8, VK_F9,
+    VK_F10, VK_F11, VK_F12, VK_G, VK_H, VK_HOME, VK_I, VK_INSERT, VK_J, VK_K, VK_L, VK_LEFT, VK_M,
+    VK_N, VK_NEXT, VK_NUMPAD0, VK_NUMPAD1, VK_NUMPAD2, VK_NUMPAD3, VK_NUMPAD4, VK_NUMPAD5,
+    VK_NUMPAD6, VK_NUMPAD7, VK_NUMPAD8, VK_NUMPAD9, VK_O, VK_P, VK_PAUSE, VK_PRIOR, VK_Q, VK_R,
+    VK_RETURN, VK_RIGHT, VK_S, VK_SPACE, VK_T, VK_TAB, VK_U, VK_UP, VK_V, VK_W, VK_X, VK_Y, VK_Z,
+};
+
+// a key combination like "Ctrl+Shift+R"
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Hotkey {
+    pub modifiers: HOT_KEY_MODIFIERS,
+    pub key: VIRTUAL_KEY,
+}
+
+impl FromStr for Hotkey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut modifiers = HOT_KEY_MODIFIERS::default();
+        let mut key = None;
+        for part in s.split('+').map(str::trim) {
+            match part.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => modifiers |= MOD_CONTROL,
+                "shift" => modifiers |= MOD_SHIFT,
+                "alt" => modifiers |= MOD_ALT,
+                "win" => modifiers |= MOD_WIN,
+                name => {
+                    if key.is_some() {
+                        return Err(format!("'{}' has more than one key", s));
+                    }
+                    key =
+                        Some(parse_key(name).ok_or(format!("Unknown key '{}' in '{}'", part, s))?);
+                }
+            }
+        }
+        key.map(|key| Hotkey { modifiers, key })
+            .ok_or(format!("'{}' has no key", s))
+    }
+}
+
+fn parse_key(name: &str) -> Option<VIRTUAL_KEY> {
+    const LETTERS: [VIRTUAL_KEY; 26] = [
+        VK_A, VK_B, VK_C, VK_D, VK_E, VK_F, VK_G, VK_H, VK_I, VK_J, VK_K, VK_L, VK_M, VK_N, VK_O,
+        VK_P, VK_Q, VK_R, VK_S, VK_T, VK_U, VK_V, VK_W, VK_X, VK_Y, VK_Z,
+    ];
+    const DIGITS: [VIRTUAL_KEY; 10] = [VK_0, VK_1, VK_2, VK_3, VK_4, VK_5, VK_6, VK_7, VK_8, VK_9];
+    const NUMPAD: [VIRTUAL_KEY; 10] = [
+        VK_NUMPAD0, VK_NUMPAD1, VK_NUMPAD2, VK_NUMPAD3, VK_NUMPAD4, VK_NUMPAD5, VK_NUMPAD6,
+        VK_NUMPAD7, VK_NUMPAD8, VK_NUMPAD9,
+    ];
+    const FUNCTION: [VIRTUAL_KEY; 12] = [
+        VK_F1, VK_F2, VK_F3, VK_F4, VK_F5, VK_F6, VK_F7, VK_F8, VK_F9, VK_F10, VK_F11, VK_F12,
+    ];
+    let key = match name {
+        "delete" | "del" => VK_DELETE,
+        "backspace" | "back" => VK_BACK,
+        "escape" | "esc" => VK_ESCAPE,
+        "insert" | "ins" => VK_INSERT,
+        "home" => VK_HOME,
+        "end" => VK_END,
+        "pageup" | "pgup" => VK_PRIOR,
+        "pagedown" | "pgdn" => VK_NEXT,
+        "space" => VK_SPACE,
+        "enter" | "return" => VK_RETURN,
+        "tab" => VK_TAB,
+        "pause" => VK_PAUSE,
+        "up" => VK_UP,
+        "down" => VK_DOWN,
+        "left" => VK_LEFT,
+        "right" => VK_RIGHT,
+        _ => {
+            if let Some(number) = name.strip_prefix("numpad") {
+                return number
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|n| NUMPAD.get(n))
+                    .copied();
+            }
+            if let Some(number) = name.strip_prefix('f')
+                && let Ok(n) = number.parse::<usize>()
+            {
+                return n.checked_sub(1).and_then(|n| FUNCTION.get(n)).copied();
+            }
+            let mut chars = name.chars();
+            return match (chars.next(), chars.next()) {
+                (Some(c @ 'a'..='z'), None) => Some(LETTERS[(c as u8 - b'a') as usize]),
+                (Some(c @ '0'..='9'), None) => Some(DIGITS[(c as u8 - b'0') as usize]),
+                _ => None,
+            };
+        }
+    };
+    Some(key)
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone)]
+pub enum HotkeyAction {
+    // enables or disables the simulation
+    Toggle,
+    // forgets the cooldowns and the shared buffs
+    Reset,
+    // the master stops (or resumes) auto-exploring, fighting goes on
+    PauseExploration,
+    // the master clicks the town portal right away
+    LeaveToTown,
+    // saves the master's map of the dungeon to the maps folder
+    ExportMap,
+    Quit,
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone, Default)]
+pub enum Target {
+    #[default]
+    All,
+    // the character whose game window has the focus
+    Focused,
+    Window(usize),
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Clone)]
+pub struct HotkeyBinding {
+    pub keys: String,
+    pub action: HotkeyAction,
+    #[serde(default)]
+    pub target: Target,
+}
+
+impl HotkeyBinding {
+    pub fn new(keys: &str, action: HotkeyAction, target: Target) -> HotkeyBinding {
+        HotkeyBinding {
+            keys: keys.to_string(),
+            action,
+            target,
+        }
+    }
+}
+
+pub fn get_default_hotkeys() -> Vec<HotkeyBinding> {
+    let mut bindings = vec![
+        HotkeyBinding::new("Delete", HotkeyAction::Toggle, Target::All),
+        HotkeyBinding::new("Backspace", HotkeyAction::Reset, Target::All),
+        HotkeyBinding::new("Escape", HotkeyAction::Quit, Target::All),
+        HotkeyBinding::new("Ctrl+Shift+R", HotkeyAction::Reset, Target::Focused),
+        HotkeyBinding::new("Ctrl+Shift+P", HotkeyAction::PauseExploration, Target::All),
+        HotkeyBinding::new("Ctrl+Shift+T", HotkeyAction::LeaveToTown, Target::All),
+        HotkeyBinding::new("Ctrl+Shift+M", HotkeyAction::ExportMap, Target::All),
+    ];
+    bindings.extend((0..5).map(|window| {
+        HotkeyBinding::new(
+            &format!("Ctrl+{}", window + 1),
+            HotkeyAction::Toggle,
+            Target::Window(window),
+        )
+    }));
+    bindings
+}
+
+// the parsed bindings, a binding's id for RegisterHotKey is its index + 1
+#[derive(Debug)]
+pub struct Hotkeys {
+    bindings: Vec<(Hotkey, HotkeyBinding)>,
+}
+
+impl Hotkeys {
+    pub fn parse(bindings: &[HotkeyBinding]) -> Result<Hotkeys, String> {
+        let mut parsed: Vec<(Hotkey, HotkeyBinding)> = vec![];
+        for binding in bindings {
+            let hotkey = binding.keys.parse::<Hotkey>()?;
+            if let Some((_, other)) = parsed.iter().find(|(h, _)| *h == hotkey) {
+                return Err(format!(
+                    "'{}' and '{}' are the same hotkey",
+                    other.keys, binding.keys
+                ));
+            }
+            parsed.push((hotkey, binding.clone()));
+        }
+        Ok(Hotkeys { bindings: parsed })
+    }
+
+    pub fn registrations(&self) -> impl Iterator<Item = (i32, Hotkey)> + '_ {
+        self.bindings
+            .iter()
+            .enumerate()
+            .map(|(i, (hotkey, _))| (i as i32 + 1, *hotkey))
+    }
+
+    pub fn get(&self, id: i32) -> Option<&HotkeyBinding> {
+        usize::try_from(id - 1)
+            .ok()
+            .and_then(|i| self.bindings.get(i))
+            .map(|(_, binding)| binding)
+    }
+
+    pub fn describe(&self) -> Vec<String> {
+        self.bindings
+            .iter()
+            .map(|(_, binding)| match binding.target {
+                _ if binding.action == HotkeyAction::Quit => {
+                    format!("{}: {:?}", binding.keys, binding.action)
+                }
+                Target::All => format!("{}: {:?} all", binding.keys, binding.action),
+                Target::Focused => format!("{}: {:?} focused", binding.keys, binding.action),
+                Target::Window(window) => {
+                    format!("{}: {:?} #{}", binding.keys, binding.action, window)
+                }
+            })
+            .collect()
+    }
+}
+
+fn targets(
+    target: Target,
+    simulations: &[Arc<SimulationState>],
+    focused: Option<usize>,
+) -> Vec<&Arc<SimulationState>> {
+    simulations
+        .iter()
+        .filter(|sim| match target {
+            Target::All => true,
+            Target::Focused => focused == Some(sim.id),
+            Target::Window(window) => sim.id == window,
+        })
+        .collect()
+}
+
+// runs the action of a pressed hotkey, false means the application should quit
+pub fn dispatch(
+    binding: &HotkeyBinding,
+    simulations: &[Arc<SimulationState>],
+    focused: Option<usize>,
+    start_offset_ms: u64,
+) -> bool {
+    let targets = targets(binding.target, simulations, focused);
+    match binding.action {
+        HotkeyAction::Toggle => targets.iter().for_each(|sim| {
+            sim.enable_toggle();
+            if binding.target == Target::All {
+                thread::sleep(Duration::from_millis(start_offset_ms));
+            }
+        }),
+        HotkeyAction::Reset => targets.iter().for_each(|sim| match binding.target {
+            Target::All => sim.reset(),
+            Target::Focused | Target::Window(_) => sim.reset_window(),
+        }),
+        HotkeyAction::PauseExploration => targets
+            .iter()
+            .filter(|sim| sim.window_config.master)
+            .for_each(|sim| sim.toggle_exploration_pause()),
+        HotkeyAction::LeaveToTown => targets
+            .iter()
+            // the rest of the party follows the master
+            .filter(|sim| binding.target != Target::All || sim.window_config.master)
+            .for_each(|sim| {
+                log_info!("Leaving to town");
+                sim.leave_to_town();
+            }),
+        HotkeyAction::ExportMap => targets
+            .iter()
+            .filter(|sim| sim.window_config.master)
+            .for_each(|sim| sim.export_map()),
+        HotkeyAction::Quit => {
+            simulations.iter().for_each(|sim| sim.stop());
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::config::Config;
+    use crate::hotkeys::{
+        Hotkey, HotkeyAction, HotkeyBinding, Hotkeys, Target, dispatch, get_default_hotkeys,
+    };
+    use crate::simulation::rotation::Rotation;
+    use crate::simulation::shared_state::SharedStateHandle;
+    use crate::simulation::simulation_state::SimulationState;
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{
+        HOT_KEY_MODIFIERS, MOD_ALT, MOD_CONTROL, MOD_SHIFT, VK_1, VK_DELETE, VK_F12, VK_NUMPAD7,
+        VK_R,
+    };
+
+    #[test]
+    fn test_parse_hotkey() {
+        assert_eq!(
+            "Ctrl+Shift+R".parse::<Hotkey>(),
+            Ok(Hotkey {
+                modifiers: MOD_CONTROL | MOD_SHIFT,
+                key: VK_R
+            })
+        );
+        assert_eq!(
+            "delete".parse::<Hotkey>(),
+            Ok(Hotkey {
+                modifiers: HOT_KEY_MODIFIERS::default(),
+                key: VK_DELETE
+            })
+        );
+        assert_eq!("ctrl + 1".parse::<Hotkey>().unwrap().key, VK_1);
+        assert_eq!("Alt+F12".parse::<Hotkey>().unwrap().modifiers, MOD_ALT);
+        assert_eq!("Alt+F12".parse::<Hotkey>().unwrap().key, VK_F12);
+        assert_eq!("Numpad7".parse::<Hotkey>().unwrap().key, VK_NUMPAD7);
+        assert!("Ctrl+Shift".parse::<Hotkey>().is_err());
+        assert!("Ctrl+A+B".parse::<Hotkey>().is_err());
+        assert!("Ctrl+Hyper".parse::<Hotkey>().is_err());
+        assert!("F13".parse::<Hotkey>().is_err());
+    }
+
+    #[test]
+    fn test_bindings() {
+        let hotkeys = Hotkeys::parse(&get_default_hotkeys()).unwrap();
+        let ids = hotkeys
+            .registrations()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(ids, (1..=12).collect::<Vec<_>>());
+        assert_eq!(hotkeys.get(0), None);
+        assert_eq!(hotkeys.get(3).unwrap().action, HotkeyAction::Quit);
+        assert_eq!(
+            hotkeys.get(8).unwrap().target,
+            Target::Window(0),
+            "Ctrl+1 toggles the first window"
+        );
+        assert_eq!(hotkeys.describe()[7], "Ctrl+1: Toggle #0");
+
+        let duplicate = vec![
+            HotkeyBinding::new("Ctrl+R", HotkeyAction::Reset, Target::All),
+            HotkeyBinding::new("control+r", HotkeyAction::Toggle, Target::All),
+        ];
+        assert!(Hotkeys::parse(&duplicate).is_err());
+
+        // one binding per table in the config file
+        let binding: HotkeyBinding =
+            toml::from_str("keys = \"Ctrl+2\"\naction = \"Toggle\"\ntarget = { Window = 1 }")
+                .unwrap();
+        assert_eq!(binding.target, Target::Window(1));
+        let binding: HotkeyBinding =
+            toml::from_str("keys = \"Ctrl+Shift+T\"\naction = \"LeaveToTown\"").unwrap();
+        assert_eq!(binding.target, Target::All);
+    }
+
+    #[test]
+    fn test_dispatch() {
+        let cfg = Config::default();
+        let shared_state = Arc::new(SharedStateHandle::new(0.0, 0.0));
+        let simulations = cfg
+            .windows
+            .iter()
+            .take(2)
+            .enumerate()
+            .map(|(id, window)| {
+                let mut window = window.clone();
+                window.master = id == 0;
+                let mut sim = SimulationState::for_test(
+                    id,
+                    window,
+                    Rotation {
+                        skills: vec![],
+                        rules: vec![],
+                    },
+                    shared_state.clone(),
+                );
+                sim.num_active_characters = 2;
+                sim.automation.auto_explore = true;
+                Arc::new(sim)
+            })
+            .collect::<Vec<_>>();
+        let enabled = |id: usize| simulations[id].is_enabled.load(Ordering::SeqCst);
+
+        let toggle_all = HotkeyBinding::new("Delete", HotkeyAction::Toggle, Target::All);
+        assert!(dispatch(&toggle_all, &simulations, None, 0));
+        assert!(enabled(0) && enabled(1));
+        let toggle_second = HotkeyBinding::new("Ctrl+2", HotkeyAction::Toggle, Target::Window(1));
+        assert!(dispatch(&toggle_second, &simulations, None, 0));
+        assert!(enabled(0) && !enabled(1));
+        let toggle_focused = HotkeyBinding::new("Ctrl+T", HotkeyAction::Toggle, Target::Focused);
+        assert!(dispatch(&toggle_focused, &simulations, Some(0), 0));
+        assert!(!enabled(0) && !enabled(1));
+        // no game window in focus
+        assert!(dispatch(&toggle_focused, &simulations, None, 0));
+        assert!(!enabled(0) && !enabled(1));
+
+        // a single window keeps the party-wide flags
+        shared_state.set_skill_haste_applied(true);
+        let reset_second = HotkeyBinding::new("Ctrl+R", HotkeyAction::Reset, Target::Window(1));
+        assert!(dispatch(&reset_second, &simulations, None, 0));
+        assert!(shared_state.get_skill_haste_applied());
+        let reset_all = HotkeyBinding::new("R", HotkeyAction::Reset, Target::All);
+        assert!(dispatch(&reset_all, &simulations, None, 0));
+        assert!(!shared_state.get_skill_haste_applied());
+
+        let pause = HotkeyBinding::new("P", HotkeyAction::PauseExploration, Target::All);
+        assert!(dispatch(&pause, &simulations, None, 0));
+        assert!(simulations[0].is_exploration_paused());
+        assert!(!simulations[1].is_exploration_paused());
+        assert!(dispatch(&pause, &simulations, None, 0));
+        assert!(!simulations[0].is_exploration_paused());
+
+        let leave = HotkeyBinding::new("T", HotkeyAction::LeaveToTown, Target::All);
+        assert!(dispatch(&leave, &simulations, None, 0));
+        assert!(!simulations[0].is_exploring.load(Ordering::SeqCst));
+        assert!(simulations[1].is_exploring.load(Ordering::SeqCst));
+
+        let quit = HotkeyBinding::new("Escape", HotkeyAction::Quit, Target::Window(1));
+        assert!(!dispatch(&quit, &simulations, None, 0));
+        shared_state.stop();
+    }
+}
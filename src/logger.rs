@@ -0,0 +1,83 @@
+use crate::events::{EVENTS, Event};
+use serde::Serialize;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// the TUI owns the terminal, so the lines only go to the event stream
+static CONSOLE_MUTED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // the character the current thread works for
+    static WINDOW: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+#[derive(Serialize, Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+pub fn set_window(window: usize) {
+    WINDOW.set(Some(window));
+}
+
+pub fn current_window() -> Option<usize> {
+    WINDOW.get()
+}
+
+pub fn mute_console() {
+    CONSOLE_MUTED.store(true, Ordering::SeqCst);
+}
+
+pub fn log(level: Level, message: String) {
+    if !CONSOLE_MUTED.load(Ordering::SeqCst) {
+        match level {
+            Level::Info | Level::Warn => println!("{}", message),
+            Level::Error => eprintln!("{}", message),
+        }
+    }
+    EVENTS.publish(Event::Log {
+        window: current_window(),
+        level,
+        message,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::{EVENTS, Event};
+    use crate::log_warn;
+    use crate::logger::{Level, set_window};
+
+    #[test]
+    fn test_log_event() {
+        let events = EVENTS.subscribe();
+        let logged = std::thread::spawn(|| {
+            set_window(2);
+            log_warn!("Slow {}", "input");
+        });
+        logged.join().unwrap();
+        // other tests may log in parallel
+        let event = events
+            .iter()
+            .find(|e| {
+                matches!(
+                    e,
+                    Event::Log {
+                        window: Some(2),
+                        ..
+                    }
+                )
+            })
+            .unwrap();
+        assert_eq!(
+            event,
+            Event::Log {
+                window: Some(2),
+                level: Level::Warn,
+                message: "Slow input".to_string()
+            }
+        );
+    }
+}
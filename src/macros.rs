@@ -1,15 +1,9 @@
+// queues the focus + input action with the input scheduler and waits for it to go through
 #[macro_export]
-#[allow(clippy::redundant_closure_call)]
-macro_rules! with_critical_section {
-    ($wait_ms:expr, $body:block) => {{
-        let _lock = CRITICAL_SECTION.lock().unwrap();
-
-        #[allow(clippy::redundant_closure_call)]
-        let result = (|| $body)();
-
-        std::thread::sleep(std::time::Duration::from_millis($wait_ms));
-        drop(_lock);
-        result
+macro_rules! with_input {
+    ($hwnd:expr, $priority:expr, $wait_ms:expr, $body:block) => {{
+        $crate::simulation::input_scheduler::INPUT_SCHEDULER
+            .run($hwnd, $priority, $wait_ms, || $body)
     }};
 }
 
@@ -19,3 +13,26 @@ macro_rules! amtx {
         std::sync::Arc::new(std::sync::Mutex::new($value))
     };
 }
+
+// println! that also goes to the event stream (API, TUI)
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::logger::log($crate::logger::Level::Info, format!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => {
+        $crate::logger::log($crate::logger::Level::Warn, format!($($arg)*))
+    };
+}
+
+// eprintln! that also goes to the event stream
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::logger::log($crate::logger::Level::Error, format!($($arg)*))
+    };
+}
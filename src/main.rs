@@ -1,36 +1,74 @@
+mod api;
 mod configuration;
+mod events;
+mod frame;
+mod hotkeys;
+mod logger;
 mod macros;
 mod simulation;
+mod tui;
 mod win_util;
 
-use crate::configuration::config::{Args, load_config};
+use crate::api::server::Api;
+use crate::configuration::config::{Args, WindowConfig, load_config};
+use crate::events::{EVENTS, Event};
+use crate::hotkeys::{Hotkeys, dispatch};
+use crate::simulation::cancellation::{CancellationToken, join_all};
 use crate::simulation::char_state::CharState;
+use crate::simulation::input_scheduler::INPUT_SCHEDULER;
+use crate::simulation::interactor::Interactor;
 use crate::simulation::maze_solver::Solver;
+use crate::simulation::recorder::{Recorder, RecordingInteractor, diff, load_recording, replay};
 use crate::simulation::rotation::Rotation;
 use crate::simulation::shared_state::SharedStateHandle;
 use crate::simulation::simulation_state::{DebugObj, SimulationState, WindowObj};
+use crate::tui::Tui;
 use crate::win_util::{
     debug_mouse, debug_mouse_color, debug_scanline, enum_windows, find_window_by_title,
-    make_dpi_aware, set_window,
+    get_foreground_window, make_dpi_aware, set_window,
 };
 use clap::Parser;
 use colored::Colorize;
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::thread;
 use std::thread::JoinHandle;
 use std::time::Duration;
-use windows::Win32::UI::Input::KeyboardAndMouse::VK_BACK;
 use windows::{
-    Win32::Foundation::HWND,
-    Win32::UI::Input::KeyboardAndMouse::{
-        MOD_NOREPEAT, RegisterHotKey, UnregisterHotKey, VK_DELETE, VK_ESCAPE,
-    },
+    Win32::Foundation::{HWND, LPARAM, WPARAM},
+    Win32::System::Console::SetConsoleCtrlHandler,
+    Win32::System::Threading::GetCurrentThreadId,
+    Win32::UI::Input::KeyboardAndMouse::{MOD_NOREPEAT, RegisterHotKey, UnregisterHotKey},
     Win32::UI::WindowsAndMessaging::{
-        DispatchMessageW, GetMessageW, MSG, TranslateMessage, WM_HOTKEY,
+        DispatchMessageW, GetMessageW, MSG, PostThreadMessageW, TranslateMessage, WM_HOTKEY,
     },
 };
 
+// posted by the API and Ctrl+C, configured hotkeys start at 1
+const QUIT_ID: i32 = 0;
+// threads still running after this are left behind
+const SHUTDOWN_TIMEOUT_MS: u64 = 5000;
+
+static MAIN_THREAD: AtomicU32 = AtomicU32::new(0);
+
+// the message loop quits the same way as on a quit hotkey
+fn post_quit() -> bool {
+    unsafe {
+        PostThreadMessageW(
+            MAIN_THREAD.load(Ordering::SeqCst),
+            WM_HOTKEY,
+            WPARAM(QUIT_ID as usize),
+            LPARAM(0),
+        )
+    }
+    .is_ok()
+}
+
+unsafe extern "system" fn on_console_ctrl(_ctrl_type: u32) -> windows::core::BOOL {
+    post_quit().into()
+}
+
 fn main() -> windows::core::Result<()> {
     std::panic::set_hook(Box::new(|panic_info| {
         eprintln!("{}", format!("Panic occurred: {:?}", panic_info).red());
@@ -84,29 +122,90 @@ fn main() -> windows::core::Result<()> {
         return Ok(());
     }
 
-    const HOTKEY_DEL_ID: i32 = 1;
-    const HOTKEY_ESC_ID: i32 = 2;
-    const HOTKEY_BACKSPACE_ID: i32 = 3;
+    if let Some(file) = &args.replay {
+        let recording = load_recording(file).expect("Failed to load the recording");
+        let interactors = cfg
+            .windows
+            .iter()
+            .filter(|w| w.active)
+            .map(|w| -> Box<dyn Interactor + Send + Sync> {
+                if args.debug_sim {
+                    Box::new(DebugObj::new(
+                        CharState::Fighting,
+                        amtx!(HashMap::new()),
+                        0.into(),
+                        0.into(),
+                    ))
+                } else {
+                    Box::new(WindowObj::new(prepare_window(w), CancellationToken::new()))
+                }
+            })
+            .collect::<Vec<_>>();
+        println!("Replaying {} actions from {}", recording.len(), file);
+        let replayed = replay(&recording, &interactors, true);
+        let differences = diff(&recording, &replayed);
+        println!("Replay finished with {} differences", differences.len());
+        differences.iter().for_each(|d| println!("{}", d.yellow()));
+        return Ok(());
+    }
 
-    // hWnd = HWND(0) => message delivered to thread message queue
-    let hwnd_screen = Some(HWND::default());
+    let recorder = match &args.record {
+        Some(file) => {
+            println!("Recording actions to {}", file);
+            Some(Arc::new(
+                Recorder::create(file).expect("Failed to create the recording"),
+            ))
+        }
+        // the API and the TUI show the last action of everyone
+        None if cfg.api_address.is_some() || args.tui => Some(Arc::new(Recorder::default())),
+        None => None,
+    };
+    if let Some(recorder) = &recorder {
+        recorder.subscribe(|record| EVENTS.publish(Event::Action(record.clone())));
+    }
+    let recorded = |id: usize,
+                    interactor: Box<dyn Interactor + Send + Sync>|
+     -> Box<dyn Interactor + Send + Sync> {
+        match &recorder {
+            Some(recorder) => Box::new(RecordingInteractor::new(id, interactor, recorder.clone())),
+            None => interactor,
+        }
+    };
 
-    unsafe {
-        RegisterHotKey(hwnd_screen, HOTKEY_DEL_ID, MOD_NOREPEAT, VK_DELETE.0 as u32)?;
-        RegisterHotKey(hwnd_screen, HOTKEY_ESC_ID, MOD_NOREPEAT, VK_ESCAPE.0 as u32)?;
-        RegisterHotKey(
-            hwnd_screen,
-            HOTKEY_BACKSPACE_ID,
-            MOD_NOREPEAT,
-            VK_BACK.0 as u32,
-        )?;
+    MAIN_THREAD.store(unsafe { GetCurrentThreadId() }, Ordering::SeqCst);
+    if unsafe { SetConsoleCtrlHandler(Some(on_console_ctrl), true) }.is_err() {
+        eprintln!("Failed to handle Ctrl+C, quitting that way skips the cleanup");
     }
 
+    let hotkeys = Hotkeys::parse(&cfg.hotkeys)
+        .unwrap_or_else(|e| panic!("Invalid hotkey configuration: {}", e));
+
+    // hWnd = HWND(0) => message delivered to thread message queue
+    let hwnd_screen = Some(HWND::default());
+
     println!("BOXER v{}", env!("CARGO_PKG_VERSION"));
-    println!("Press DELETE to toggle on/off. BACKSPACE for cache reset. ESC or Ctrl+C to exit.");
+    for ((id, hotkey), description) in hotkeys.registrations().zip(hotkeys.describe()) {
+        // another application may already own the combination
+        match unsafe {
+            RegisterHotKey(
+                hwnd_screen,
+                id,
+                hotkey.modifiers | MOD_NOREPEAT,
+                hotkey.key.0 as u32,
+            )
+        } {
+            Ok(()) => println!("{}", description),
+            Err(e) => eprintln!(
+                "{}",
+                format!("Failed to register {}: {}", description, e).yellow()
+            ),
+        }
+    }
+    println!("Ctrl+C quits as well.");
 
     let mut handles: Vec<JoinHandle<()>> = vec![];
     let mut simulations: Vec<Arc<SimulationState>> = vec![];
+    let mut window_handles: Vec<Option<HWND>> = vec![];
     let shared_state = Arc::new(SharedStateHandle::new(
         cfg.skill_haste_percent,
         cfg.frenzy_haste_percent,
@@ -119,42 +218,35 @@ fn main() -> windows::core::Result<()> {
         .cloned()
         .collect::<Vec<_>>();
     let num_windows = active_windows.len();
-    for active_window in active_windows {
-        let mut hwnd_opt = match &active_window.title {
-            Some(title) => find_window_by_title(title),
-            _ => None,
-        };
-        if hwnd_opt.is_none() {
-            hwnd_opt = active_window.hwnd
-        }
-        if let Some(hwnd) = hwnd_opt {
-            set_window(
-                hwnd,
-                active_window.position_x,
-                active_window.position_y,
-                active_window.window_width,
-                active_window.window_height,
-            )
-            .expect("Failed to set window position");
-        }
+    for (id, active_window) in active_windows.into_iter().enumerate() {
+        let hwnd_opt = prepare_window(&active_window);
+        window_handles.push(hwnd_opt);
+        // the simulation and its window objects stop together
+        let cancellation = CancellationToken::new();
 
-        let rotation = Rotation::load_rotation(active_window.class_config.class);
+        let rotation = Rotation::load_rotation(active_window.class_config.class)
+            .for_level(active_window.level);
 
         let simulation = if args.debug_sim {
             Arc::new(SimulationState::new(
+                id,
                 cfg.sync_interval_ms,
                 cfg.cast_leeway_ms,
                 num_windows,
                 active_window,
                 rotation,
-                cfg.leave_when_full,
-                cfg.auto_explore,
-                Box::new(DebugObj::new(
-                    CharState::Fighting,
-                    amtx!(HashMap::new()),
-                    0.into(),
-                    0.into(),
-                )),
+                cfg.automation,
+                cfg.readiness.clone(),
+                cfg.death.clone(),
+                recorded(
+                    id,
+                    Box::new(DebugObj::new(
+                        CharState::Fighting,
+                        amtx!(HashMap::new()),
+                        0.into(),
+                        0.into(),
+                    )),
+                ),
                 Box::new(DebugObj::new(
                     CharState::Fighting,
                     amtx!(HashMap::new()),
@@ -162,26 +254,39 @@ fn main() -> windows::core::Result<()> {
                     0.into(),
                 )),
                 shared_state.clone(),
-                Solver::new(Box::new(DebugObj::new(
-                    CharState::Fighting,
-                    amtx!(HashMap::new()),
-                    0.into(),
-                    0.into(),
-                ))),
+                Solver::with_exploration(
+                    recorded(
+                        id,
+                        Box::new(DebugObj::new(
+                            CharState::Fighting,
+                            amtx!(HashMap::new()),
+                            0.into(),
+                            0.into(),
+                        )),
+                    ),
+                    &cfg.exploration,
+                ),
+                cancellation,
             ))
         } else {
             Arc::new(SimulationState::new(
+                id,
                 cfg.sync_interval_ms,
                 cfg.cast_leeway_ms,
                 num_windows,
                 active_window,
                 rotation,
-                cfg.leave_when_full,
-                cfg.auto_explore,
-                Box::new(WindowObj::new(hwnd_opt)),
-                Box::new(WindowObj::new(hwnd_opt)),
+                cfg.automation,
+                cfg.readiness.clone(),
+                cfg.death.clone(),
+                recorded(id, Box::new(WindowObj::new(hwnd_opt, cancellation.clone()))),
+                Box::new(WindowObj::new(hwnd_opt, cancellation.clone())),
                 shared_state.clone(),
-                Solver::new(Box::new(WindowObj::new(hwnd_opt))),
+                Solver::with_exploration(
+                    recorded(id, Box::new(WindowObj::new(hwnd_opt, cancellation.clone()))),
+                    &cfg.exploration,
+                ),
+                cancellation,
             ))
         };
 
@@ -199,47 +304,121 @@ fn main() -> windows::core::Result<()> {
         handles.push(handle);
     }
 
+    let api = cfg.api_address.as_ref().and_then(|address| {
+        let api = Arc::new(Api::new(
+            simulations.clone(),
+            cfg.api_token.clone(),
+            EVENTS.clone(),
+            Box::new(|| {
+                post_quit();
+            }),
+        ));
+        match api.clone().start(address) {
+            Ok(()) => Some(api),
+            Err(e) => {
+                eprintln!("{}", format!("Failed to start the API: {}", e).red());
+                None
+            }
+        }
+    });
+
+    // the TUI owns the terminal from now on
+    let tui = args.tui.then(|| {
+        logger::mute_console();
+        colored::control::set_override(false);
+        Tui::start(
+            simulations.clone(),
+            shared_state.clone(),
+            Box::new(|| {
+                post_quit();
+            }),
+        )
+    });
+
     let mut msg = MSG::default();
     unsafe {
         while GetMessageW(&mut msg, hwnd_screen, 0, 0).into() {
             if msg.message == WM_HOTKEY {
                 let id = msg.wParam.0 as i32;
-                match id {
-                    HOTKEY_DEL_ID => {
-                        simulations.iter().for_each(|sim| {
-                            sim.enable_toggle();
-                            thread::sleep(Duration::from_millis(cfg.start_offset_ms));
-                        });
+                let keep_running = match hotkeys.get(id) {
+                    Some(binding) => {
+                        let foreground = get_foreground_window();
+                        let focused = window_handles
+                            .iter()
+                            .position(|hwnd_opt| *hwnd_opt == Some(foreground));
+                        dispatch(binding, &simulations, focused, cfg.start_offset_ms)
                     }
-                    HOTKEY_ESC_ID => {
+                    None if id == QUIT_ID => {
                         simulations.iter().for_each(|sim| {
                             sim.stop();
                         });
-                        println!("Quitting application...");
-                        break;
-                    }
-                    HOTKEY_BACKSPACE_ID => {
-                        simulations.iter().for_each(|sim| {
-                            sim.reset();
-                        });
+                        false
                     }
-                    _ => {}
+                    None => true,
+                };
+                if !keep_running {
+                    println!("Quitting application...");
+                    break;
                 }
             }
             let _ = TranslateMessage(&msg);
             DispatchMessageW(&msg);
         }
 
-        UnregisterHotKey(hwnd_screen, HOTKEY_DEL_ID)?;
-        UnregisterHotKey(hwnd_screen, HOTKEY_ESC_ID)?;
-        UnregisterHotKey(hwnd_screen, HOTKEY_BACKSPACE_ID)?;
+        for (id, _) in hotkeys.registrations() {
+            let _ = UnregisterHotKey(hwnd_screen, id);
+        }
     }
 
-    for handle in handles {
-        handle.join().expect("Thread panicked");
+    if let Some(tui) = tui {
+        tui.stop();
+    }
+    if let Some(api) = api {
+        api.stop();
     }
 
+    // sleeping simulations wake up right away, a slow input can still hold one up for a bit
+    let stuck = join_all(handles, Duration::from_millis(SHUTDOWN_TIMEOUT_MS));
+    if stuck > 0 {
+        eprintln!(
+            "{}",
+            format!("{} simulation thread(s) did not stop in time", stuck).red()
+        );
+    }
+
+    for sim in &simulations {
+        println!(
+            "{:?} skill tracker: {}",
+            sim.window_config.class_config.class,
+            sim.skill_tracker.metrics()
+        );
+        sim.skill_tracker.stop();
+    }
+    println!("Shared state: {}", shared_state.metrics());
     shared_state.stop();
+    INPUT_SCHEDULER.report();
 
     Ok(())
 }
+
+// finds the game window and moves it to the configured position
+fn prepare_window(window: &WindowConfig) -> Option<HWND> {
+    let mut hwnd_opt = match &window.title {
+        Some(title) => find_window_by_title(title),
+        _ => None,
+    };
+    if hwnd_opt.is_none() {
+        hwnd_opt = window.hwnd
+    }
+    if let Some(hwnd) = hwnd_opt {
+        set_window(
+            hwnd,
+            window.position_x,
+            window.position_y,
+            window.window_width,
+            window.window_height,
+        )
+        .expect("Failed to set window position");
+    }
+    hwnd_opt
+}
@@ -0,0 +1,354 @@
+use crate::log_error;
+use crate::log_info;
+use crate::log_warn;
+use crate::logger;
+use colored::Colorize;
+use serde::Serialize;
+use std::any::{Any, type_name};
+use std::fmt::{Debug, Display, Formatter};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, mpsc};
+use std::thread;
+use std::time::Duration;
+
+// generous, the actors answer in microseconds unless something is really wrong
+pub const ASK_TIMEOUT_MS: u64 = 5000;
+
+// the state of an actor lives on its own thread, everyone else talks to it through messages
+pub trait Actor: Clone + Send + 'static {
+    type Message: Send + 'static;
+
+    // replies go back through the senders inside the messages
+    fn handle(&mut self, message: Self::Message);
+
+    // the state is not snapshotted for these, they cannot leave it half changed
+    fn is_read_only(_message: &Self::Message) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum ActorError {
+    // the actor was stopped
+    Stopped,
+    // the actor panicked on the message and dropped the reply
+    NoReply,
+    Timeout,
+}
+
+impl Display for ActorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActorError::Stopped => write!(f, "actor stopped"),
+            ActorError::NoReply => write!(f, "actor did not reply"),
+            ActorError::Timeout => write!(f, "actor did not reply in time"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone, Eq, PartialEq)]
+pub struct ActorMetrics {
+    pub mailbox_depth: usize,
+    pub max_mailbox_depth: usize,
+    pub handled: usize,
+    pub restarts: usize,
+    pub timeouts: usize,
+}
+
+impl Display for ActorMetrics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} handled, mailbox depth {} (max {}), {} restarts, {} timeouts",
+            self.handled, self.mailbox_depth, self.max_mailbox_depth, self.restarts, self.timeouts
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    mailbox_depth: AtomicUsize,
+    max_mailbox_depth: AtomicUsize,
+    handled: AtomicUsize,
+    restarts: AtomicUsize,
+    timeouts: AtomicUsize,
+}
+
+enum Envelope<M> {
+    Message(M),
+    Stop,
+}
+
+pub struct ActorRef<A: Actor> {
+    sender: Sender<Envelope<A::Message>>,
+    counters: Arc<Counters>,
+}
+
+impl<A: Actor> Clone for ActorRef<A> {
+    fn clone(&self) -> Self {
+        ActorRef {
+            sender: self.sender.clone(),
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+impl<A: Actor> Debug for ActorRef<A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ActorRef<{}>({:?})", type_name::<A>(), self.metrics())
+    }
+}
+
+impl<A: Actor> ActorRef<A> {
+    // window tags the log lines of the actor
+    pub fn spawn(actor: A, window: Option<usize>) -> ActorRef<A> {
+        let (tx, rx) = mpsc::channel();
+        let counters = Arc::new(Counters::default());
+        log_info!("Starting {}", type_name::<A>());
+        thread::spawn({
+            let counters = counters.clone();
+            move || {
+                if let Some(window) = window {
+                    logger::set_window(window);
+                }
+                supervise(actor, rx, &counters);
+            }
+        });
+        ActorRef {
+            sender: tx,
+            counters,
+        }
+    }
+
+    pub fn ask<T>(&self, message: impl FnOnce(Sender<T>) -> A::Message) -> Result<T, ActorError> {
+        self.ask_timeout(message, Duration::from_millis(ASK_TIMEOUT_MS))
+    }
+
+    // a failed ask is logged and answered with the fallback, so a caller can carry on
+    pub fn ask_or<T>(&self, message: impl FnOnce(Sender<T>) -> A::Message, fallback: T) -> T {
+        self.ask(message).unwrap_or_else(|e| {
+            log_warn!(
+                "{}",
+                format!("Asking {} failed: {}", type_name::<A>(), e).yellow()
+            );
+            fallback
+        })
+    }
+
+    pub fn ask_or_default<T: Default>(&self, message: impl FnOnce(Sender<T>) -> A::Message) -> T {
+        self.ask_or(message, T::default())
+    }
+
+    pub fn ask_timeout<T>(
+        &self,
+        message: impl FnOnce(Sender<T>) -> A::Message,
+        timeout: Duration,
+    ) -> Result<T, ActorError> {
+        let (tx, rx) = mpsc::channel();
+        self.send(Envelope::Message(message(tx)))?;
+        rx.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => {
+                self.counters.timeouts.fetch_add(1, Ordering::SeqCst);
+                ActorError::Timeout
+            }
+            RecvTimeoutError::Disconnected => ActorError::NoReply,
+        })
+    }
+
+    // the messages already in the mailbox are handled first
+    pub fn stop(&self) {
+        let _ = self.send(Envelope::Stop);
+    }
+
+    pub fn metrics(&self) -> ActorMetrics {
+        let c = &self.counters;
+        ActorMetrics {
+            mailbox_depth: c.mailbox_depth.load(Ordering::SeqCst),
+            max_mailbox_depth: c.max_mailbox_depth.load(Ordering::SeqCst),
+            handled: c.handled.load(Ordering::SeqCst),
+            restarts: c.restarts.load(Ordering::SeqCst),
+            timeouts: c.timeouts.load(Ordering::SeqCst),
+        }
+    }
+
+    fn send(&self, envelope: Envelope<A::Message>) -> Result<(), ActorError> {
+        let depth = self.counters.mailbox_depth.fetch_add(1, Ordering::SeqCst) + 1;
+        self.counters
+            .max_mailbox_depth
+            .fetch_max(depth, Ordering::SeqCst);
+        self.sender.send(envelope).map_err(|_| {
+            self.counters.mailbox_depth.fetch_sub(1, Ordering::SeqCst);
+            ActorError::Stopped
+        })
+    }
+}
+
+// a panicking message must not take the whole actor down, so the state before it is restored
+fn supervise<A: Actor>(
+    mut actor: A,
+    receiver: Receiver<Envelope<A::Message>>,
+    counters: &Counters,
+) {
+    for envelope in receiver {
+        counters.mailbox_depth.fetch_sub(1, Ordering::SeqCst);
+        let message = match envelope {
+            Envelope::Message(message) => message,
+            Envelope::Stop => break,
+        };
+        // counted up front, the reply may reach the asker before the handler returns
+        counters.handled.fetch_add(1, Ordering::SeqCst);
+        // cloning the whole state for every query would cost more than the query itself
+        let snapshot = (!A::is_read_only(&message)).then(|| actor.clone());
+        if let Err(e) = catch_unwind(AssertUnwindSafe(|| actor.handle(message))) {
+            counters.restarts.fetch_add(1, Ordering::SeqCst);
+            log_error!(
+                "{}",
+                format!(
+                    "{} panicked: {}, restarting from the previous state",
+                    type_name::<A>(),
+                    panic_message(&e)
+                )
+                .red()
+            );
+            if let Some(snapshot) = snapshot {
+                actor = snapshot;
+            }
+        }
+    }
+    log_info!("Shutting down {}", type_name::<A>());
+}
+
+fn panic_message(e: &Box<dyn Any + Send>) -> String {
+    e.downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| e.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulation::actor::{Actor, ActorError, ActorRef};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::Sender;
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct Counter {
+        count: u32,
+        // how many snapshots were taken
+        clones: Arc<AtomicUsize>,
+    }
+
+    impl Clone for Counter {
+        fn clone(&self) -> Self {
+            self.clones.fetch_add(1, Ordering::SeqCst);
+            Counter {
+                count: self.count,
+                clones: self.clones.clone(),
+            }
+        }
+    }
+
+    enum CounterMessage {
+        Get(Sender<u32>),
+        Add(u32, Sender<u32>),
+        // panics after it already changed the state, but before the reply
+        AddAndPanic(u32, Sender<u32>),
+        Sleep(u64, Sender<()>),
+    }
+
+    impl Actor for Counter {
+        type Message = CounterMessage;
+
+        fn handle(&mut self, message: Self::Message) {
+            match message {
+                CounterMessage::Get(sender) => {
+                    let _ = sender.send(self.count);
+                }
+                CounterMessage::Add(n, sender) => {
+                    self.count += n;
+                    let _ = sender.send(self.count);
+                }
+                CounterMessage::AddAndPanic(n, sender) => {
+                    self.count += n;
+                    if self.count > 0 {
+                        panic!("boom");
+                    }
+                    let _ = sender.send(self.count);
+                }
+                CounterMessage::Sleep(ms, sender) => {
+                    thread::sleep(Duration::from_millis(ms));
+                    let _ = sender.send(());
+                }
+            }
+        }
+
+        fn is_read_only(message: &Self::Message) -> bool {
+            matches!(message, CounterMessage::Get(_))
+        }
+    }
+
+    #[test]
+    fn test_ask_and_restart() {
+        let actor = ActorRef::spawn(Counter::default(), None);
+        assert_eq!(actor.ask(|tx| CounterMessage::Add(2, tx)), Ok(2));
+        assert_eq!(
+            actor.ask(|tx| CounterMessage::AddAndPanic(5, tx)),
+            Err(ActorError::NoReply)
+        );
+        // the state from before the panic
+        assert_eq!(actor.ask(|tx| CounterMessage::Add(1, tx)), Ok(3));
+
+        let metrics = actor.metrics();
+        assert_eq!((metrics.handled, metrics.restarts), (3, 1));
+        assert_eq!(metrics.mailbox_depth, 0);
+
+        actor.stop();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(
+            actor.ask(|tx| CounterMessage::Add(1, tx)),
+            Err(ActorError::Stopped)
+        );
+    }
+
+    #[test]
+    fn test_timeout_and_mailbox_depth() {
+        let actor = ActorRef::spawn(Counter::default(), None);
+        let sleeping = thread::spawn({
+            let actor = actor.clone();
+            move || actor.ask(|tx| CounterMessage::Sleep(300, tx))
+        });
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(
+            actor.ask_timeout(|tx| CounterMessage::Add(1, tx), Duration::from_millis(10)),
+            Err(ActorError::Timeout)
+        );
+        assert_eq!(sleeping.join().unwrap(), Ok(()));
+
+        let metrics = actor.metrics();
+        assert_eq!(metrics.timeouts, 1);
+        assert_eq!(metrics.max_mailbox_depth, 1);
+        // the late message was still handled
+        assert_eq!(actor.ask(|tx| CounterMessage::Add(1, tx)), Ok(2));
+        actor.stop();
+    }
+
+    #[test]
+    fn test_read_only_skips_snapshot() {
+        let counter = Counter::default();
+        let clones = counter.clones.clone();
+        let actor = ActorRef::spawn(counter, None);
+        assert_eq!(actor.ask(CounterMessage::Get), Ok(0));
+        assert_eq!(actor.ask(|tx| CounterMessage::Add(1, tx)), Ok(1));
+        assert_eq!(actor.ask(CounterMessage::Get), Ok(1));
+        assert_eq!(clones.load(Ordering::SeqCst), 1);
+        assert_eq!(actor.ask_or(|tx| CounterMessage::Add(1, tx), 0), 2);
+        actor.stop();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(actor.ask_or(CounterMessage::Get, 42), 42);
+    }
+}
@@ -0,0 +1,492 @@
+use crate::log_info;
+use crate::log_warn;
+use crate::simulation::char_state::CharState;
+use crate::simulation::script::Phase;
+use crate::simulation::simulation_state::SimulationState;
+use colored::Colorize;
+use std::time::Instant;
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Flow {
+    // wait for the sync interval before the next tick
+    Sleep,
+    // something happened (looting, walking...), let's check the state right away
+    Continue,
+}
+
+// memory shared by the behaviors between ticks
+#[derive(Debug)]
+pub struct Blackboard {
+    pub state: CharState,
+    pub state_check_at: Instant,
+    pub auto_attacking: bool,
+}
+
+impl Default for Blackboard {
+    fn default() -> Self {
+        Blackboard {
+            state: CharState::Unknown,
+            state_check_at: Instant::now(),
+            auto_attacking: false,
+        }
+    }
+}
+
+pub trait Behavior {
+    fn name(&self) -> &'static str;
+    // the character states the behavior takes care of
+    fn states(&self) -> Vec<CharState>;
+    fn on_enter(&self, _sim: &SimulationState, _board: &mut Blackboard) {}
+    fn on_exit(&self, _sim: &SimulationState, _board: &mut Blackboard) {}
+    fn tick(&self, sim: &SimulationState, board: &mut Blackboard) -> Flow;
+}
+
+// picks a behavior by the character state, the first registered one wins
+pub struct StateMachine {
+    behaviors: Vec<Box<dyn Behavior + Send + Sync>>,
+    current: Option<usize>,
+    pub board: Blackboard,
+}
+
+impl StateMachine {
+    pub fn new(behaviors: Vec<Box<dyn Behavior + Send + Sync>>) -> StateMachine {
+        StateMachine {
+            behaviors,
+            current: None,
+            board: Blackboard::default(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn current(&self) -> Option<&'static str> {
+        self.current.map(|i| self.behaviors[i].name())
+    }
+
+    pub fn tick(&mut self, sim: &SimulationState, state: CharState) -> Flow {
+        self.board.state = state;
+        self.board.state_check_at = Instant::now();
+        let next = self
+            .behaviors
+            .iter()
+            .position(|b| b.states().contains(&state));
+        if next != self.current {
+            if let Some(i) = self.current {
+                self.behaviors[i].on_exit(sim, &mut self.board);
+            }
+            if let Some(i) = next {
+                log_info!("Behavior: {}", self.behaviors[i].name().cyan());
+                self.behaviors[i].on_enter(sim, &mut self.board);
+            }
+            self.current = next;
+        }
+        match next {
+            Some(i) => self.behaviors[i].tick(sim, &mut self.board),
+            None => Flow::Sleep,
+        }
+    }
+}
+
+impl Default for StateMachine {
+    fn default() -> Self {
+        StateMachine::new(vec![
+            Box::new(Idle),
+            Box::new(LevelUp),
+            Box::new(Town),
+            Box::new(Dead),
+            Box::new(Explore),
+            Box::new(Combat),
+            Box::new(Looting),
+            Box::new(Shrine),
+        ])
+    }
+}
+
+fn movement(sim: &SimulationState, board: &Blackboard) -> Flow {
+    if sim.run_script(Phase::Movement, board.state) || sim.process_movement() {
+        Flow::Continue
+    } else {
+        Flow::Sleep
+    }
+}
+
+// coming back from a town or a death - nothing we tracked is valid anymore
+fn reset_tracking(sim: &SimulationState, board: &mut Blackboard) {
+    sim.skill_tracker.reset();
+    sim.maze_solver.reset();
+    if sim.window_config.master
+        && let Err(e) = sim.shared_state.reset_master_trail()
+    {
+        log_warn!("Couldn't reset the master trail: {}", e);
+    }
+    // a new dungeon to explore, however we got here
+    sim.resume_exploring();
+    board.auto_attacking = false;
+}
+
+pub struct Idle;
+
+impl Behavior for Idle {
+    fn name(&self) -> &'static str {
+        "Idle"
+    }
+
+    fn states(&self) -> Vec<CharState> {
+        vec![CharState::Unknown]
+    }
+
+    fn tick(&self, _: &SimulationState, _: &mut Blackboard) -> Flow {
+        Flow::Sleep
+    }
+}
+
+pub struct LevelUp;
+
+impl Behavior for LevelUp {
+    fn name(&self) -> &'static str {
+        "LevelUp"
+    }
+
+    fn states(&self) -> Vec<CharState> {
+        vec![CharState::LevelUp]
+    }
+
+    fn on_enter(&self, sim: &SimulationState, _: &mut Blackboard) {
+        sim.level_up_routine();
+    }
+
+    fn on_exit(&self, sim: &SimulationState, _: &mut Blackboard) {
+        sim.finish_level_up();
+    }
+
+    // waiting for the dialog to go away
+    fn tick(&self, _: &SimulationState, _: &mut Blackboard) -> Flow {
+        Flow::Sleep
+    }
+}
+
+pub struct Town;
+
+impl Behavior for Town {
+    fn name(&self) -> &'static str {
+        "Town"
+    }
+
+    fn states(&self) -> Vec<CharState> {
+        vec![CharState::InTown]
+    }
+
+    fn on_exit(&self, sim: &SimulationState, board: &mut Blackboard) {
+        if let Err(e) = sim.shared_state.set_town_routine_done(sim.id, false) {
+            log_warn!("Couldn't reset the town routine: {}", e);
+        }
+        reset_tracking(sim, board);
+    }
+
+    fn tick(&self, sim: &SimulationState, _: &mut Blackboard) -> Flow {
+        if sim.automation.manage_inventory && !sim.is_town_routine_done() {
+            sim.inventory_routine();
+            // not marked as done, the routine runs again on the next tick
+            if let Err(e) = sim.shared_state.set_town_routine_done(sim.id, true) {
+                log_warn!("Couldn't mark the town routine as done: {}", e);
+            }
+        }
+        if sim.can_run_town_cycle() {
+            sim.town_routine();
+        }
+        Flow::Sleep
+    }
+}
+
+pub struct Dead;
+
+impl Behavior for Dead {
+    fn name(&self) -> &'static str {
+        "Dead"
+    }
+
+    fn states(&self) -> Vec<CharState> {
+        vec![CharState::Dead]
+    }
+
+    fn on_exit(&self, sim: &SimulationState, board: &mut Blackboard) {
+        reset_tracking(sim, board);
+    }
+
+    fn tick(&self, sim: &SimulationState, board: &mut Blackboard) -> Flow {
+        movement(sim, board)
+    }
+}
+
+pub struct Explore;
+
+impl Behavior for Explore {
+    fn name(&self) -> &'static str {
+        "Explore"
+    }
+
+    fn states(&self) -> Vec<CharState> {
+        vec![CharState::InDungeon]
+    }
+
+    fn tick(&self, sim: &SimulationState, board: &mut Blackboard) -> Flow {
+        sim.try_resurrect();
+        if sim.state_checker.is_inventory_opened()
+            && sim.state_checker.is_inventory_full()
+            && let Err(e) = sim.shared_state.set_inventory_full(sim.id, true)
+        {
+            log_warn!("Couldn't report a full inventory: {}", e);
+        }
+
+        //leave to town if any of the party has full inventory... no point of farming
+        if sim.shared_state.get_full_inventory()
+            //only master can leave dungeon
+            && sim.window_config.master
+            && sim.automation.leave_when_full
+            && sim.leave_to_town()
+        {
+            // let's assume we clear the inventory in a town... so wait and clear shared state
+            sim.cancellation.sleep_ms(1000);
+            if let Err(e) = sim.shared_state.clear_full_inventory() {
+                log_warn!("Couldn't clear the full inventory: {}", e);
+            }
+        }
+
+        sim.do_rotation(board.state, board.state_check_at, false);
+        movement(sim, board)
+    }
+}
+
+pub struct Combat;
+
+impl Behavior for Combat {
+    fn name(&self) -> &'static str {
+        "Combat"
+    }
+
+    fn states(&self) -> Vec<CharState> {
+        vec![CharState::Fighting]
+    }
+
+    fn on_enter(&self, sim: &SimulationState, _: &mut Blackboard) {
+        //wait if we have just started fighting... otherwise the first cast may not go off
+        sim.cancellation.sleep_ms(500);
+    }
+
+    fn on_exit(&self, sim: &SimulationState, _: &mut Blackboard) {
+        //we should track debuffs only during fight, otherwise it would block possible casts
+        sim.skill_tracker.reset_debuffs();
+    }
+
+    fn tick(&self, sim: &SimulationState, board: &mut Blackboard) -> Flow {
+        if !board.auto_attacking {
+            // auto-attack just once
+            board.auto_attacking = sim
+                .interactor
+                .auto_attack(sim.window_config.class_config.auto_attack);
+        }
+        // the script may cast on its own, or leave it to the rotation
+        if !sim.run_script(Phase::Combat, board.state) {
+            sim.do_rotation(board.state, board.state_check_at, false);
+        }
+        movement(sim, board)
+    }
+}
+
+pub struct Looting;
+
+impl Behavior for Looting {
+    fn name(&self) -> &'static str {
+        "Looting"
+    }
+
+    fn states(&self) -> Vec<CharState> {
+        vec![CharState::Looting]
+    }
+
+    fn tick(&self, sim: &SimulationState, board: &mut Blackboard) -> Flow {
+        if sim.run_script(Phase::Looting, board.state) {
+            movement(sim, board);
+            return Flow::Continue;
+        }
+        if sim.state_checker.is_inventory_full() {
+            return movement(sim, board);
+        }
+        log_info!("Initiate looting...");
+        let mut loot_counter = 0;
+        loop {
+            // a long loot keeps the party from counting us out
+            sim.heartbeat(CharState::Looting);
+            //keep looting until the state changes, or we failed to loot (needs manual intervention)
+            let looted = sim.loot_cycle();
+            loot_counter += 1;
+            let new_state = sim.state_checker.get_state(sim.num_active_characters);
+            //let's break if we go over 10 attempts - we might be hung-up because of unknown loot quality check
+            if !looted
+                || new_state != CharState::Looting
+                || sim.state_checker.is_inventory_full()
+                || loot_counter > 10
+                || sim.cancellation.is_cancelled()
+            {
+                log_info!("Looting ended");
+                break;
+            }
+        }
+        movement(sim, board);
+        Flow::Continue
+    }
+}
+
+pub struct Shrine;
+
+impl Behavior for Shrine {
+    fn name(&self) -> &'static str {
+        "Shrine"
+    }
+
+    fn states(&self) -> Vec<CharState> {
+        vec![CharState::AtShrine]
+    }
+
+    fn tick(&self, sim: &SimulationState, board: &mut Blackboard) -> Flow {
+        sim.loot_shrine(CharState::AtShrine);
+        movement(sim, board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::config::Config;
+    use crate::simulation::behavior::{Flow, StateMachine};
+    use crate::simulation::char_state::CharState;
+    use crate::simulation::rotation::Rotation;
+    use crate::simulation::shared_state::SharedStateHandle;
+    use crate::simulation::simulation_state::SimulationState;
+    use std::sync::Arc;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn test_state_machine_transitions() {
+        let cfg = Config::default();
+        let shared_state = Arc::new(SharedStateHandle::new(0.0, 0.0));
+        let sim = SimulationState::for_test(
+            0,
+            cfg.windows.first().unwrap().clone(),
+            Rotation {
+                skills: vec![],
+                rules: vec![],
+            },
+            shared_state.clone(),
+        );
+        let mut machine = StateMachine::default();
+        assert_eq!(machine.current(), None);
+
+        assert_eq!(machine.tick(&sim, CharState::Unknown), Flow::Sleep);
+        assert_eq!(machine.current(), Some("Idle"));
+
+        machine.tick(&sim, CharState::Fighting);
+        assert_eq!(machine.current(), Some("Combat"));
+        assert!(machine.board.auto_attacking);
+
+        // auto-attack keeps going after the fight
+        machine.tick(&sim, CharState::InDungeon);
+        assert_eq!(machine.current(), Some("Explore"));
+        assert!(machine.board.auto_attacking);
+
+        // but not after coming back from a town
+        machine.tick(&sim, CharState::InTown);
+        assert_eq!(machine.current(), Some("Town"));
+        shared_state.set_town_routine_done(0, true).unwrap();
+        machine.tick(&sim, CharState::InDungeon);
+        assert!(!machine.board.auto_attacking);
+        assert!(shared_state.get_town_routine_done().unwrap().is_empty());
+
+        shared_state.stop();
+    }
+
+    #[test]
+    fn test_level_up_once() {
+        let cfg = Config::default();
+        let shared_state = Arc::new(SharedStateHandle::new(0.0, 0.0));
+        let mut window = cfg.windows.first().unwrap().clone();
+        window.level = Some(5);
+        let sim = SimulationState::for_test(
+            0,
+            window,
+            Rotation {
+                skills: vec![],
+                rules: vec![],
+            },
+            shared_state.clone(),
+        );
+        let mut machine = StateMachine::default();
+
+        // the dialog stays up for a couple of ticks
+        machine.tick(&sim, CharState::LevelUp);
+        machine.tick(&sim, CharState::LevelUp);
+        assert_eq!(*sim.level.lock().unwrap(), Some(5));
+        machine.tick(&sim, CharState::Unknown);
+        assert_eq!(*sim.level.lock().unwrap(), Some(6));
+
+        shared_state.stop();
+    }
+
+    #[test]
+    fn test_exploring_resumes_in_next_dungeon() {
+        let cfg = Config::default();
+        let shared_state = Arc::new(SharedStateHandle::new(0.0, 0.0));
+        let mut window = cfg.windows.first().unwrap().clone();
+        window.master = true;
+        let mut sim = SimulationState::for_test(
+            0,
+            window,
+            Rotation {
+                skills: vec![],
+                rules: vec![],
+            },
+            shared_state.clone(),
+        );
+        // no town cycle, the player takes the party back
+        sim.automation.auto_explore = true;
+        let mut machine = StateMachine::default();
+        let exploring = || sim.is_exploring.load(Ordering::SeqCst);
+
+        machine.tick(&sim, CharState::InDungeon);
+        assert!(exploring());
+        sim.leave_to_town();
+        assert!(!exploring());
+        machine.tick(&sim, CharState::InTown);
+        assert!(!exploring());
+        machine.tick(&sim, CharState::InDungeon);
+        assert!(exploring());
+
+        shared_state.stop();
+    }
+
+    #[test]
+    fn test_looting_publishes_heartbeat() {
+        let cfg = Config::default();
+        let shared_state = Arc::new(SharedStateHandle::new(0.0, 0.0));
+        let sim = SimulationState::for_test(
+            0,
+            cfg.windows.first().unwrap().clone(),
+            Rotation {
+                skills: vec![],
+                rules: vec![],
+            },
+            shared_state.clone(),
+        );
+        let mut machine = StateMachine::default();
+        shared_state
+            .set_member_state(0, CharState::InDungeon)
+            .unwrap();
+
+        // the run loop did not publish the new state, the loot loop did
+        machine.tick(&sim, CharState::Looting);
+        assert_eq!(
+            shared_state.get_members().unwrap()[0].state,
+            CharState::Looting
+        );
+
+        shared_state.stop();
+    }
+}
@@ -0,0 +1,103 @@
+use crate::log_warn;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const JOIN_POLL_MS: u64 = 20;
+
+// shared by everything that has to stop on quit, sleeps wake up as soon as it is cancelled
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        let (cancelled, condvar) = &*self.inner;
+        *cancelled.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.inner.0.lock().unwrap()
+    }
+
+    // false when cancelled before the time was up
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let (cancelled, condvar) = &*self.inner;
+        let guard = cancelled.lock().unwrap();
+        let (guard, _) = condvar
+            .wait_timeout_while(guard, duration, |cancelled| !*cancelled)
+            .unwrap();
+        !*guard
+    }
+
+    pub fn sleep_ms(&self, ms: u64) -> bool {
+        self.sleep(Duration::from_millis(ms))
+    }
+}
+
+// joins the threads that finish in time, returns how many are still running
+pub fn join_all(handles: Vec<JoinHandle<()>>, timeout: Duration) -> usize {
+    let deadline = Instant::now() + timeout;
+    let mut pending = handles;
+    while !pending.is_empty() && Instant::now() < deadline {
+        let (finished, running): (Vec<_>, Vec<_>) =
+            pending.into_iter().partition(|handle| handle.is_finished());
+        for handle in finished {
+            if handle.join().is_err() {
+                log_warn!("Thread panicked before shutdown");
+            }
+        }
+        pending = running;
+        std::thread::sleep(Duration::from_millis(JOIN_POLL_MS));
+    }
+    pending.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulation::cancellation::{CancellationToken, join_all};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_cancellation() {
+        let token = CancellationToken::new();
+        assert!(token.sleep_ms(10));
+
+        let started = Instant::now();
+        let sleeper = thread::spawn({
+            let token = token.clone();
+            move || token.sleep(Duration::from_secs(10))
+        });
+        thread::sleep(Duration::from_millis(50));
+        token.cancel();
+        assert!(!sleeper.join().unwrap());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(token.is_cancelled());
+        // stays cancelled
+        assert!(!token.sleep_ms(10));
+    }
+
+    #[test]
+    fn test_join_all() {
+        let token = CancellationToken::new();
+        let quick = thread::spawn({
+            let token = token.clone();
+            move || {
+                token.sleep_ms(10_000);
+            }
+        });
+        let stuck = thread::spawn(|| thread::sleep(Duration::from_millis(1000)));
+        token.cancel();
+
+        let started = Instant::now();
+        assert_eq!(join_all(vec![quick, stuck], Duration::from_millis(200)), 1);
+        assert!(started.elapsed() < Duration::from_millis(900));
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+use serde::Serialize;
+
+#[derive(Serialize, Debug, Eq, PartialEq, Copy, Clone)]
 pub enum CharState {
     InTown,
     InDungeon,
@@ -6,5 +8,6 @@ pub enum CharState {
     Looting,
     AtShrine,
     Dead,
+    LevelUp,
     Unknown,
 }
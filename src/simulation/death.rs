@@ -0,0 +1,101 @@
+use crate::simulation::shared_state::{MEMBER_HEARTBEAT_TIMEOUT_MS, MemberStatus};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Deserialize, Serialize, Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub enum Recovery {
+    // the dead wait for the player, only a wipe sends the party to town
+    Wait,
+    // members with the resurrect skill bring the dead back, the party regroups in town when it takes too long
+    Resurrect,
+    // the party regroups in town once a member is dead for a while
+    #[default]
+    Release,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct DeathConfig {
+    pub recovery: Recovery,
+    // a skill from the rotation, cast on the dead party member
+    pub resurrect_skill: Option<String>,
+    pub release_after_ms: u64,
+}
+
+impl Default for DeathConfig {
+    fn default() -> Self {
+        DeathConfig {
+            recovery: Recovery::default(),
+            resurrect_skill: None,
+            release_after_ms: 30_000,
+        }
+    }
+}
+
+impl DeathConfig {
+    // dead members that still publish, the ones that quit are not waited for
+    pub fn casualties(members: &[MemberStatus]) -> Vec<&MemberStatus> {
+        let max_age = Duration::from_millis(MEMBER_HEARTBEAT_TIMEOUT_MS);
+        members
+            .iter()
+            .filter(|m| !m.is_alive() && !m.is_stale(max_age))
+            .collect()
+    }
+
+    // on a wipe, or when a member could not be brought back, the whole party starts over from town
+    pub fn should_regroup(&self, members: &[MemberStatus]) -> bool {
+        let max_age = Duration::from_millis(MEMBER_HEARTBEAT_TIMEOUT_MS);
+        let publishing = members.iter().filter(|m| !m.is_stale(max_age)).count();
+        let casualties = Self::casualties(members);
+        if casualties.is_empty() {
+            return false;
+        }
+        if casualties.len() == publishing {
+            return true;
+        }
+        self.recovery != Recovery::Wait
+            && casualties.iter().any(|m| {
+                m.dead_for()
+                    .is_some_and(|d| d >= Duration::from_millis(self.release_after_ms))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulation::char_state::CharState;
+    use crate::simulation::death::{DeathConfig, Recovery};
+    use crate::simulation::shared_state::SharedStateHandle;
+
+    #[test]
+    fn test_should_regroup() {
+        let state = SharedStateHandle::new(0.0, 0.0);
+        let wait = DeathConfig {
+            recovery: Recovery::Wait,
+            release_after_ms: 0,
+            ..DeathConfig::default()
+        };
+        let release = DeathConfig {
+            release_after_ms: 0,
+            ..DeathConfig::default()
+        };
+        let patient = DeathConfig::default();
+
+        state.set_member_state(0, CharState::InDungeon).unwrap();
+        state.set_member_state(1, CharState::InDungeon).unwrap();
+        assert!(!release.should_regroup(&state.get_members().unwrap()));
+
+        state.set_member_state(1, CharState::Dead).unwrap();
+        let members = state.get_members().unwrap();
+        assert_eq!(DeathConfig::casualties(&members).len(), 1);
+        assert!(!wait.should_regroup(&members));
+        assert!(!patient.should_regroup(&members));
+        assert!(release.should_regroup(&members));
+
+        // a wipe
+        state.set_member_state(0, CharState::Dead).unwrap();
+        assert!(wait.should_regroup(&state.get_members().unwrap()));
+
+        state.stop();
+    }
+}
@@ -0,0 +1,387 @@
+use crate::log_info;
+use crate::simulation::interactor::Interactor;
+use crate::simulation::maze_solver::{Direction, Node, Pos};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+#[derive(Deserialize, Serialize, Debug, Default, Clone, PartialEq)]
+pub enum Exploration {
+    // tries every direction and backtracks one tile per step
+    #[default]
+    DepthFirst,
+    // walks the shortest known path to the nearest unexplored tile, one tile per step
+    Frontier,
+    // keeps its right hand on the wall, then picks up the rooms it did not pass, good for long corridors without loops
+    WallFollower,
+    // explores with the inner strategy until it saw enough rooms or ran out of time
+    TimeBoxed {
+        #[serde(default)]
+        strategy: Box<Exploration>,
+        rooms: usize,
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
+}
+
+impl Exploration {
+    pub fn strategy(&self) -> Box<dyn ExplorationStrategy + Send + Sync> {
+        match self {
+            Exploration::DepthFirst => Box::new(DepthFirst::default()),
+            Exploration::Frontier => Box::new(Frontier::default()),
+            Exploration::WallFollower => Box::new(WallFollower::default()),
+            Exploration::TimeBoxed {
+                strategy,
+                rooms,
+                timeout_ms,
+            } => Box::new(TimeBoxed::new(
+                strategy.strategy(),
+                *rooms,
+                timeout_ms.map(Duration::from_millis),
+            )),
+        }
+    }
+}
+
+// the known part of the dungeon and the way to move around in it
+pub struct Maze<'a> {
+    pub map: &'a mut HashMap<Pos, Node>,
+    pub pos: Pos,
+    interactor: &'a (dyn Interactor + Send + Sync),
+    pub moves: Vec<Direction>,
+}
+
+impl<'a> Maze<'a> {
+    pub fn new(
+        map: &'a mut HashMap<Pos, Node>,
+        pos: Pos,
+        interactor: &'a (dyn Interactor + Send + Sync),
+    ) -> Maze<'a> {
+        Maze {
+            map,
+            pos,
+            interactor,
+            moves: vec![],
+        }
+    }
+
+    pub fn is_visited(&self, pos: Pos) -> bool {
+        self.map.get(&pos).is_some_and(|n| n.visited)
+    }
+
+    pub fn visit(&mut self) {
+        self.map.entry(self.pos).or_default().visited = true;
+    }
+
+    // remembers the connection when there is a way
+    pub fn try_direction(&mut self, dir: Direction) -> bool {
+        if !self.interactor.try_direction(dir) {
+            return false;
+        }
+        let (pos, next_pos) = (self.pos, next(self.pos, dir));
+        self.map
+            .entry(pos)
+            .or_default()
+            .neighbors
+            .insert(dir, next_pos);
+        self.map
+            .entry(next_pos)
+            .or_default()
+            .neighbors
+            .insert(dir.opposite(), pos);
+        true
+    }
+
+    // on the first visit find out where we can go from here, without moving
+    pub fn look_around(&mut self) {
+        if self.is_visited(self.pos) {
+            return;
+        }
+        self.visit();
+        for dir in Direction::ALL {
+            // a visited tile already told us if there is a way from there
+            if !self.is_visited(next(self.pos, dir)) {
+                self.try_direction(dir);
+            }
+        }
+    }
+
+    pub fn walk(&mut self, dir: Direction) {
+        self.interactor.walk(Some(dir));
+        self.pos = next(self.pos, dir);
+        self.moves.push(dir);
+    }
+
+    pub fn rooms(&self) -> usize {
+        self.map.values().filter(|n| n.visited).count()
+    }
+}
+
+pub fn next(pos: Pos, dir: Direction) -> Pos {
+    let delta = dir.delta();
+    (pos.0 + delta.0, pos.1 + delta.1)
+}
+
+pub trait ExplorationStrategy {
+    fn name(&self) -> &'static str;
+    // walks one tile, true when there is nothing left to explore
+    fn step(&mut self, maze: &mut Maze) -> bool;
+    // back to the start of a new dungeon
+    fn reset(&mut self);
+    // the tiles still to backtrack through, for the map export
+    fn stack(&self) -> Vec<Pos> {
+        vec![]
+    }
+}
+
+pub struct DepthFirst {
+    stack: Vec<(Pos, Vec<Direction>)>,
+}
+
+impl Default for DepthFirst {
+    fn default() -> Self {
+        DepthFirst {
+            stack: vec![((0, 0), Direction::ALL.to_vec())],
+        }
+    }
+}
+
+impl ExplorationStrategy for DepthFirst {
+    fn name(&self) -> &'static str {
+        "DepthFirst"
+    }
+
+    fn step(&mut self, maze: &mut Maze) -> bool {
+        let Some((pos, directions)) = self.stack.last_mut() else {
+            return true; // Exploration done
+        };
+
+        // Mark current node visited
+        maze.map.entry(*pos).or_default().visited = true;
+
+        let mut moved_to = None;
+        while let Some(dir) = directions.pop() {
+            let next_pos = next(*pos, dir);
+            if maze.is_visited(next_pos) {
+                continue; // Already visited
+            }
+
+            // Try to move in this direction
+            if maze.try_direction(dir) {
+                maze.walk(dir);
+                moved_to = Some(next_pos);
+                break; // only 1 move per step
+            }
+        }
+        if let Some(next_pos) = moved_to {
+            // Push new node onto the stack with all 4 directions
+            self.stack.push((next_pos, Direction::ALL.to_vec()));
+            return false;
+        }
+
+        // No more directions to try — backtrack
+        self.stack.pop();
+        if let Some((parent_pos, _)) = self.stack.last()
+            && let Some(dir) = Direction::ALL
+                .into_iter()
+                .find(|d| next(maze.pos, *d) == *parent_pos)
+        {
+            maze.walk(dir);
+        }
+
+        false // Backtracked, still not done
+    }
+
+    fn reset(&mut self) {
+        *self = DepthFirst::default();
+    }
+
+    fn stack(&self) -> Vec<Pos> {
+        self.stack.iter().map(|(pos, _)| *pos).collect()
+    }
+}
+
+#[derive(Default)]
+pub struct Frontier {
+    // the rest of the way to the frontier, a tile per step so the fights on the way come in between
+    path: VecDeque<Direction>,
+}
+
+impl ExplorationStrategy for Frontier {
+    fn name(&self) -> &'static str {
+        "Frontier"
+    }
+
+    fn step(&mut self, maze: &mut Maze) -> bool {
+        maze.look_around();
+        if self.path.is_empty() {
+            // head to the nearest tile we know the way to, but did not visit yet
+            let Some(path) = shortest_path(maze.map, maze.pos, |p| {
+                maze.map.get(p).is_some_and(|n| !n.visited)
+            }) else {
+                return true;
+            };
+            if path.len() > 1 {
+                log_info!("Walking {} tiles to the nearest frontier", path.len());
+            }
+            self.path = path.into();
+        }
+        if let Some(dir) = self.path.pop_front() {
+            maze.walk(dir);
+        }
+        false
+    }
+
+    fn reset(&mut self) {
+        self.path.clear();
+    }
+}
+
+pub struct WallFollower {
+    heading: Direction,
+    // a tile left the same way twice means we went around the whole wall
+    seen: HashSet<(Pos, Direction)>,
+    // the rooms the wall did not lead to, e.g. around pillars, are picked up afterwards
+    around: bool,
+    rest: Frontier,
+}
+
+impl Default for WallFollower {
+    fn default() -> Self {
+        WallFollower {
+            heading: Direction::Up,
+            seen: HashSet::new(),
+            around: false,
+            rest: Frontier::default(),
+        }
+    }
+}
+
+impl ExplorationStrategy for WallFollower {
+    fn name(&self) -> &'static str {
+        "WallFollower"
+    }
+
+    fn step(&mut self, maze: &mut Maze) -> bool {
+        maze.look_around();
+        if self.around {
+            return self.rest.step(maze);
+        }
+        // right, straight, left and back as the last resort
+        let heading = self.heading;
+        let options = [
+            heading.clockwise(),
+            heading,
+            heading.counter_clockwise(),
+            heading.opposite(),
+        ];
+        let neighbors = &maze.map[&maze.pos].neighbors;
+        let dir = options.into_iter().find(|d| neighbors.contains_key(d));
+        match dir {
+            Some(dir) if self.seen.insert((maze.pos, dir)) => {
+                maze.walk(dir);
+                self.heading = dir;
+                false
+            }
+            // walled in, or back where we started
+            _ => {
+                log_info!("Went around the wall, {} rooms so far", maze.rooms());
+                self.around = true;
+                self.rest.step(maze)
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = WallFollower::default();
+    }
+}
+
+// "explore N rooms then leave", the strategy underneath decides where to go
+pub struct TimeBoxed {
+    strategy: Box<dyn ExplorationStrategy + Send + Sync>,
+    rooms: usize,
+    timeout: Option<Duration>,
+    started: Option<Instant>,
+}
+
+impl TimeBoxed {
+    pub fn new(
+        strategy: Box<dyn ExplorationStrategy + Send + Sync>,
+        rooms: usize,
+        timeout: Option<Duration>,
+    ) -> TimeBoxed {
+        TimeBoxed {
+            strategy,
+            rooms,
+            timeout,
+            started: None,
+        }
+    }
+}
+
+impl ExplorationStrategy for TimeBoxed {
+    fn name(&self) -> &'static str {
+        "TimeBoxed"
+    }
+
+    fn step(&mut self, maze: &mut Maze) -> bool {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        if maze.rooms() >= self.rooms {
+            log_info!("Explored {} rooms, that's enough", maze.rooms());
+            return true;
+        }
+        if self
+            .timeout
+            .is_some_and(|timeout| started.elapsed() >= timeout)
+        {
+            log_info!("Out of time after {} rooms", maze.rooms());
+            return true;
+        }
+        self.strategy.step(maze)
+    }
+
+    fn reset(&mut self) {
+        self.started = None;
+        self.strategy.reset();
+    }
+
+    fn stack(&self) -> Vec<Pos> {
+        self.strategy.stack()
+    }
+}
+
+// BFS over the known connections, None when no tile matches
+fn shortest_path(
+    map: &HashMap<Pos, Node>,
+    from: Pos,
+    is_target: impl Fn(&Pos) -> bool,
+) -> Option<Vec<Direction>> {
+    let mut came_from: HashMap<Pos, (Pos, Direction)> = HashMap::new();
+    let mut queue = VecDeque::from([from]);
+    while let Some(pos) = queue.pop_front() {
+        if is_target(&pos) {
+            let mut path = vec![];
+            let mut at = pos;
+            while let Some((previous, dir)) = came_from.get(&at) {
+                path.push(*dir);
+                at = *previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        let Some(node) = map.get(&pos) else {
+            continue;
+        };
+        // sorted, so the same map is always walked the same way
+        let mut neighbors: Vec<_> = node.neighbors.iter().collect();
+        neighbors.sort_by_key(|(dir, _)| Direction::ALL.iter().position(|d| d == *dir));
+        for (dir, next) in neighbors {
+            if *next != from && !came_from.contains_key(next) {
+                came_from.insert(*next, (pos, *dir));
+                queue.push_back(*next);
+            }
+        }
+    }
+    None
+}
@@ -0,0 +1,90 @@
+use crate::simulation::maze_solver::Direction;
+use serde::Serialize;
+
+// walking is not always registered, so a step is retried a couple of times before it is given up
+pub const FOLLOW_STUCK_RETRIES: usize = 3;
+
+// every step the master took since it last entered a dungeon
+#[derive(Serialize, Debug, Default, Clone, Eq, PartialEq)]
+pub struct MasterTrail {
+    // bumped when the master starts over, so the followers do too
+    pub generation: usize,
+    pub moves: Vec<Direction>,
+}
+
+// how far a follower got on the master's trail
+#[derive(Debug, Default)]
+pub struct Follower {
+    generation: usize,
+    cursor: usize,
+    stuck_attempts: usize,
+}
+
+impl Follower {
+    // the next step to take, None when we caught up
+    pub fn next(&mut self, trail: &MasterTrail) -> Option<Direction> {
+        if trail.generation != self.generation {
+            *self = Follower {
+                generation: trail.generation,
+                ..Follower::default()
+            };
+        }
+        trail.moves.get(self.cursor).copied()
+    }
+
+    pub fn steps_behind(&self, trail: &MasterTrail) -> usize {
+        if trail.generation != self.generation {
+            return trail.moves.len();
+        }
+        trail.moves.len().saturating_sub(self.cursor)
+    }
+
+    pub fn moved(&mut self) {
+        self.cursor += 1;
+        self.stuck_attempts = 0;
+    }
+
+    // true when the step was given up
+    pub fn stuck(&mut self) -> bool {
+        self.stuck_attempts += 1;
+        if self.stuck_attempts >= FOLLOW_STUCK_RETRIES {
+            self.moved();
+            return true;
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulation::follow::{FOLLOW_STUCK_RETRIES, Follower, MasterTrail};
+    use crate::simulation::maze_solver::Direction;
+
+    #[test]
+    fn test_follower() {
+        let mut trail = MasterTrail {
+            generation: 0,
+            moves: vec![Direction::Up, Direction::Left],
+        };
+        let mut follower = Follower::default();
+        assert_eq!(follower.next(&trail), Some(Direction::Up));
+        follower.moved();
+        assert_eq!(follower.steps_behind(&trail), 1);
+
+        // stuck on the second step until it is given up
+        assert_eq!(follower.next(&trail), Some(Direction::Left));
+        for _ in 1..FOLLOW_STUCK_RETRIES {
+            assert!(!follower.stuck());
+        }
+        assert!(follower.stuck());
+        assert_eq!(follower.next(&trail), None);
+
+        // the master started over
+        trail = MasterTrail {
+            generation: 1,
+            moves: vec![Direction::Down],
+        };
+        assert_eq!(follower.steps_behind(&trail), 1);
+        assert_eq!(follower.next(&trail), Some(Direction::Down));
+    }
+}
@@ -1,3 +0,0 @@
-use std::sync::{LazyLock, Mutex};
-
-pub static CRITICAL_SECTION: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
@@ -0,0 +1,291 @@
+use crate::log_info;
+use crate::log_warn;
+use crate::simulation::skill::Skill;
+use crate::simulation::skill_type::SkillType;
+use colored::Colorize;
+use std::collections::HashMap;
+use std::sync::{Condvar, LazyLock, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+use windows::Win32::Foundation::HWND;
+
+// a request waiting longer than this is worth a warning
+const SLOW_REQUEST_MS: u128 = 500;
+// a waiting request moves up one priority this often, so the casts cannot starve the movement
+const AGING_MS: u128 = 1000;
+// aging never reaches the potions and interrupts, they have to stay on time
+const AGING_FLOOR: u128 = InputPriority::Interrupt as u128 + 1;
+
+pub static INPUT_SCHEDULER: LazyLock<InputScheduler> = LazyLock::new(InputScheduler::new);
+
+// the lower the more urgent
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone, Hash)]
+pub enum InputPriority {
+    Potion,
+    Interrupt,
+    Cast,
+    // screen reads need the window focused too, stale states are worse than late loot
+    Observe,
+    Loot,
+    Movement,
+}
+
+impl InputPriority {
+    pub fn for_skill(skill: &Skill) -> InputPriority {
+        if skill.skill_type == SkillType::Interrupt {
+            InputPriority::Interrupt
+        } else {
+            InputPriority::Cast
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl LatencyStats {
+    fn record(&mut self, latency: Duration) {
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn average(&self) -> Duration {
+        if self.count == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.count as u32
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Ticket {
+    id: u64,
+    priority: InputPriority,
+    window: isize,
+    requested_at: Instant,
+}
+
+impl Ticket {
+    fn aged_priority(&self, now: Instant) -> u128 {
+        let priority = self.priority as u128;
+        if priority < AGING_FLOOR {
+            return priority;
+        }
+        let waited_ms = now.saturating_duration_since(self.requested_at).as_millis();
+        priority
+            .saturating_sub(waited_ms / AGING_MS)
+            .max(AGING_FLOOR)
+    }
+}
+
+#[derive(Debug, Default)]
+struct SchedulerState {
+    busy: bool,
+    last_window: Option<isize>,
+    next_id: u64,
+    queue: Vec<Ticket>,
+    focus_switches: u64,
+    stats: HashMap<InputPriority, LatencyStats>,
+}
+
+impl SchedulerState {
+    fn push(&mut self, priority: InputPriority, window: isize, requested_at: Instant) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.queue.push(Ticket {
+            id,
+            priority,
+            window,
+            requested_at,
+        });
+        id
+    }
+
+    // the most urgent (after aging) first, the focused window wins a tie, then the oldest
+    fn next_ticket(&self, now: Instant) -> Option<u64> {
+        self.queue
+            .iter()
+            .min_by_key(|t| {
+                (
+                    t.aged_priority(now),
+                    Some(t.window) != self.last_window,
+                    t.id,
+                )
+            })
+            .map(|t| t.id)
+    }
+}
+
+// releases the input even if the action panics
+struct Grant<'a> {
+    scheduler: &'a InputScheduler,
+}
+
+impl Drop for Grant<'_> {
+    fn drop(&mut self) {
+        self.scheduler.lock().busy = false;
+        self.scheduler.released.notify_all();
+    }
+}
+
+// serializes focus + input of all the windows, one action at a time
+#[derive(Debug, Default)]
+pub struct InputScheduler {
+    state: Mutex<SchedulerState>,
+    released: Condvar,
+}
+
+impl InputScheduler {
+    pub fn new() -> InputScheduler {
+        InputScheduler::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, SchedulerState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // waits for the turn, runs the action and keeps the input for wait_ms so the game registers it
+    pub fn run<T>(
+        &self,
+        hwnd: Option<HWND>,
+        priority: InputPriority,
+        wait_ms: u64,
+        action: impl FnOnce() -> T,
+    ) -> T {
+        let window = hwnd.map_or(0, |h| h.0 as isize);
+        let requested_at = Instant::now();
+        let mut state = self.lock();
+        let id = state.push(priority, window, requested_at);
+        while state.busy || state.next_ticket(Instant::now()) != Some(id) {
+            state = self.released.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        state.queue.retain(|t| t.id != id);
+        state.busy = true;
+        if state.last_window.is_some_and(|w| w != window) {
+            state.focus_switches += 1;
+        }
+        state.last_window = Some(window);
+        let latency = requested_at.elapsed();
+        state.stats.entry(priority).or_default().record(latency);
+        drop(state);
+
+        if latency.as_millis() > SLOW_REQUEST_MS {
+            log_warn!(
+                "{}",
+                format!("{:?} input waited for {} ms", priority, latency.as_millis()).yellow()
+            );
+        }
+
+        let _grant = Grant { scheduler: self };
+        let result = action();
+        thread::sleep(Duration::from_millis(wait_ms));
+        result
+    }
+
+    pub fn stats(&self) -> Vec<(InputPriority, LatencyStats)> {
+        let mut stats: Vec<(InputPriority, LatencyStats)> =
+            self.lock().stats.iter().map(|(p, s)| (*p, *s)).collect();
+        stats.sort_by_key(|(priority, _)| *priority);
+        stats
+    }
+
+    pub fn report(&self) {
+        log_info!(
+            "Input queue latency ({} focus switches):",
+            self.lock().focus_switches
+        );
+        for (priority, stats) in self.stats() {
+            log_info!(
+                "  {:?}: {} requests, avg {} ms, max {} ms",
+                priority,
+                stats.count,
+                stats.average().as_millis(),
+                stats.max.as_millis()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulation::input_scheduler::{
+        AGING_MS, InputPriority, InputScheduler, SchedulerState,
+    };
+    use std::time::{Duration, Instant};
+    use windows::Win32::Foundation::HWND;
+
+    // hands the input out the way run() does, without any threads
+    fn drain(state: &mut SchedulerState, now: Instant) -> Vec<u64> {
+        let mut order = vec![];
+        while let Some(id) = state.next_ticket(now) {
+            let position = state.queue.iter().position(|t| t.id == id).unwrap();
+            state.last_window = Some(state.queue.remove(position).window);
+            order.push(id);
+        }
+        order
+    }
+
+    #[test]
+    fn test_ticket_order() {
+        let now = Instant::now();
+        let mut state = SchedulerState {
+            last_window: Some(1),
+            ..SchedulerState::default()
+        };
+        let movement = state.push(InputPriority::Movement, 2, now);
+        let cast_2 = state.push(InputPriority::Cast, 2, now);
+        let cast_1 = state.push(InputPriority::Cast, 1, now);
+        let potion = state.push(InputPriority::Potion, 3, now);
+        // potion first, neither cast window has the focus after it, so first come first served
+        assert_eq!(
+            drain(&mut state, now),
+            vec![potion, cast_2, cast_1, movement]
+        );
+
+        let other_window = state.push(InputPriority::Loot, 2, now);
+        let same_window = state.push(InputPriority::Loot, 1, now);
+        state.last_window = Some(1);
+        assert_eq!(drain(&mut state, now), vec![same_window, other_window]);
+    }
+
+    #[test]
+    fn test_aging() {
+        let now = Instant::now();
+        let mut state = SchedulerState::default();
+        let movement = state.push(InputPriority::Movement, 1, now);
+        // two levels up is not enough to pass a fresh cast
+        let later = now + Duration::from_millis(2 * AGING_MS as u64);
+        let cast = state.push(InputPriority::Cast, 1, later);
+        assert_eq!(state.next_ticket(later), Some(cast));
+        // waited long enough to go before a fresh cast, but never before a potion or an interrupt
+        let much_later = now + Duration::from_millis(5 * AGING_MS as u64);
+        let potion = state.push(InputPriority::Potion, 1, much_later);
+        let interrupt = state.push(InputPriority::Interrupt, 1, much_later);
+        let fresh_cast = state.push(InputPriority::Cast, 1, much_later);
+        assert_eq!(
+            drain(&mut state, much_later),
+            vec![potion, interrupt, movement, cast, fresh_cast]
+        );
+    }
+
+    #[test]
+    fn test_stats() {
+        let scheduler = InputScheduler::new();
+        let window = |id: usize| Some(HWND(id as *mut core::ffi::c_void));
+        scheduler.run(window(1), InputPriority::Cast, 0, || ());
+        scheduler.run(window(2), InputPriority::Potion, 0, || ());
+        assert_eq!(scheduler.run(window(1), InputPriority::Cast, 0, || 42), 42);
+
+        let stats = scheduler.stats();
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].0, InputPriority::Potion);
+        assert_eq!(stats[1].1.count, 2);
+        assert_eq!(scheduler.lock().focus_switches, 2);
+    }
+}
@@ -1,18 +1,21 @@
 use crate::configuration::class_config::AutoAttack;
-use crate::simulation::global_lock::CRITICAL_SECTION;
+use crate::log_error;
+use crate::log_info;
+use crate::simulation::input_scheduler::InputPriority;
+use crate::simulation::inventory::{Vendor, get_inventory_cell_center};
 use crate::simulation::keys::{
-    AUTO_ATTACK, AUTO_RANGED_ATTACK, AUTO_WALK, DISCARD, HEALTH_POT, INVENTORY, Key, LOOT_INTERACT,
+    AUTO_ATTACK, AUTO_RANGED_ATTACK, AUTO_WALK, CLOSE_WINDOWS, DISCARD, HEALTH_POT, INVENTORY, Key,
+    LOOT_INTERACT, RESOURCE_POT, TARGET_ENEMY,
 };
+use crate::simulation::level_up::Attribute;
 use crate::simulation::maze_solver::Direction;
 use crate::simulation::simulation_state::{DebugObj, WindowObj};
 use crate::simulation::skill::Skill;
 use crate::simulation::state_checker::get_move_pixel;
-use crate::win_util::{focus_window, send_key_vk, set_mouse};
-use crate::with_critical_section;
+use crate::win_util::{focus_window, right_click, send_key_vk, set_mouse};
+use crate::with_input;
 use colored::Colorize;
 use std::sync::atomic::Ordering;
-use std::thread;
-use std::time::Duration;
 
 pub trait Interactor {
     fn cast_skill(&self, skill: &Skill) -> bool;
@@ -20,10 +23,21 @@ pub trait Interactor {
     fn interact(&self) -> bool;
     fn discard(&self) -> bool;
     fn target_player(&self, player_index: usize) -> bool;
+    fn target_enemy(&self) -> bool;
     fn auto_attack(&self, auto_attack: AutoAttack) -> bool;
     fn use_hp_pot(&self) -> bool;
+    fn use_resource_pot(&self) -> bool;
     fn inventory_toggle(&self) -> bool;
     fn leave_to_town(&self) -> bool;
+    fn go_to_dungeon(&self) -> bool;
+    fn open_vendor(&self, vendor: Vendor) -> bool;
+    // with a vendor window opened this sells, stashes or salvages the item
+    fn move_inventory_item(&self, slot: usize) -> bool;
+    fn close_windows(&self) -> bool;
+    fn allocate_attribute(&self, attribute: Attribute) -> bool;
+    // skill rows of the level-up dialog follow the hotbar
+    fn allocate_skill_rank(&self, slot: usize) -> bool;
+    fn confirm_level_up(&self) -> bool;
     fn try_direction(&self, direction: Direction) -> bool;
     fn walk(&self, direction: Option<Direction>) -> bool;
     fn reset_position(&self);
@@ -31,48 +45,95 @@ pub trait Interactor {
 
 impl Interactor for DebugObj {
     fn cast_skill(&self, skill: &Skill) -> bool {
-        print!("Casting ");
-        print!("{}", format!("'{}'", skill.name).bright_magenta());
+        log_info!("Casting {}", format!("'{}'", skill.name).bright_magenta());
         true
     }
 
     fn loot(&self) -> bool {
-        println!("{}", "Looting an item".green());
+        log_info!("{}", "Looting an item".green());
         true
     }
 
     fn interact(&self) -> bool {
-        println!("{}", "Interacting".green());
+        log_info!("{}", "Interacting".green());
         true
     }
 
     fn discard(&self) -> bool {
-        println!("{}", "Discarding an item".red());
+        log_info!("{}", "Discarding an item".red());
         true
     }
 
     fn target_player(&self, player_index: usize) -> bool {
-        println!("Targeting player {}", player_index + 1);
+        log_info!("Targeting player {}", player_index + 1);
+        true
+    }
+
+    fn target_enemy(&self) -> bool {
+        log_info!("Targeting an enemy");
         true
     }
 
     fn auto_attack(&self, auto_attack: AutoAttack) -> bool {
-        println!("{}", format!("Auto-attacking {:?}", auto_attack).magenta());
+        log_info!("{}", format!("Auto-attacking {:?}", auto_attack).magenta());
         true
     }
 
     fn use_hp_pot(&self) -> bool {
-        println!("{}", "Using a HP potion".red());
+        log_info!("{}", "Using a HP potion".red());
+        true
+    }
+
+    fn use_resource_pot(&self) -> bool {
+        log_info!("{}", "Using a resource potion".blue());
         true
     }
 
     fn inventory_toggle(&self) -> bool {
-        println!("{}", "Toggling an inventory".bright_purple());
+        log_info!("{}", "Toggling an inventory".bright_purple());
         true
     }
 
     fn leave_to_town(&self) -> bool {
-        println!("{}", "Leaving to town".red());
+        log_info!("{}", "Leaving to town".red());
+        true
+    }
+
+    fn go_to_dungeon(&self) -> bool {
+        log_info!("{}", "Going to dungeon".green());
+        true
+    }
+
+    fn open_vendor(&self, vendor: Vendor) -> bool {
+        log_info!("{}", format!("Opening {:?}", vendor).yellow());
+        true
+    }
+
+    fn move_inventory_item(&self, slot: usize) -> bool {
+        log_info!("{}", format!("Moving inventory item {}", slot).yellow());
+        true
+    }
+
+    fn close_windows(&self) -> bool {
+        log_info!("{}", "Closing windows".yellow());
+        true
+    }
+
+    fn allocate_attribute(&self, attribute: Attribute) -> bool {
+        log_info!("{}", format!("Allocating {:?}", attribute).bright_green());
+        true
+    }
+
+    fn allocate_skill_rank(&self, slot: usize) -> bool {
+        log_info!(
+            "{}",
+            format!("Ranking up skill {}", slot + 1).bright_green()
+        );
+        true
+    }
+
+    fn confirm_level_up(&self) -> bool {
+        log_info!("{}", "Confirming level-up".bright_green());
         true
     }
 
@@ -81,17 +142,16 @@ impl Interactor for DebugObj {
             self.position_x.load(Ordering::SeqCst),
             self.position_y.load(Ordering::SeqCst),
         );
-        print!(
-            "{}",
-            format!("At {:?} and trying to go: {:?}. ", position, direction).bright_yellow()
-        );
+        self.probes.fetch_add(1, Ordering::SeqCst);
         let map = self.test_map.clone();
         let map = map.lock().unwrap();
         let result = map
             .get(&position)
-            .is_some_and(|node| node.neighbors.contains_key(&direction));
-        println!(
-            "{}: {}",
+            .is_some_and(|node| node.neighbors.contains_key(&direction))
+            && !self.probe_fails();
+        log_info!(
+            "{}{}: {}",
+            format!("At {:?} and trying to go: {:?}. ", position, direction).bright_yellow(),
             format!("Can go {:?}", direction).white(),
             if result {
                 format!("{:?}", result).green()
@@ -105,14 +165,37 @@ impl Interactor for DebugObj {
     fn walk(&self, direction: Option<Direction>) -> bool {
         match direction {
             Some(direction) => {
-                println!("{}", format!("Walking... {:?}", direction).bright_yellow());
+                log_info!("{}", format!("Walking... {:?}", direction).bright_yellow());
             }
             None => {
-                println!("{}", "Walking...".bright_yellow());
+                log_info!("{}", "Walking...".bright_yellow());
             }
         }
 
-        let delta = direction.unwrap().delta();
+        let direction = direction.unwrap();
+        let position = (
+            self.position_x.load(Ordering::SeqCst),
+            self.position_y.load(Ordering::SeqCst),
+        );
+        let through_wall = !self
+            .test_map
+            .lock()
+            .unwrap()
+            .get(&position)
+            .is_some_and(|node| node.neighbors.contains_key(&direction));
+        if through_wall {
+            log_error!(
+                "{}",
+                format!(
+                    "Walked through a wall at {:?} going {:?}",
+                    position, direction
+                )
+                .red()
+            );
+            self.wall_hits.fetch_add(1, Ordering::SeqCst);
+        }
+
+        let delta = direction.delta();
         self.position_x.store(
             self.position_x.load(Ordering::SeqCst) + delta.0,
             Ordering::SeqCst,
@@ -125,7 +208,7 @@ impl Interactor for DebugObj {
             self.position_x.load(Ordering::SeqCst),
             self.position_y.load(Ordering::SeqCst),
         );
-        println!("New position: {:?}", new_position);
+        log_info!("New position: {:?}", new_position);
         let map = self.test_map.clone();
         let mut map = map.lock().unwrap();
 
@@ -142,38 +225,40 @@ impl Interactor for DebugObj {
 const WAIT_TO_REGISTER_MS: u64 = 200;
 impl Interactor for WindowObj {
     fn cast_skill(&self, skill: &Skill) -> bool {
-        print!("Casting ");
-        print!("{}", format!("'{}'", skill.name).bright_magenta());
-        with_critical_section!(WAIT_TO_REGISTER_MS, {
-            focus_window(self.hwnd).as_bool() && send_key_vk(skill.key).is_ok()
-        })
+        log_info!("Casting {}", format!("'{}'", skill.name).bright_magenta());
+        with_input!(
+            self.hwnd,
+            InputPriority::for_skill(skill),
+            WAIT_TO_REGISTER_MS,
+            { focus_window(self.hwnd).as_bool() && send_key_vk(skill.key).is_ok() }
+        )
     }
 
     fn loot(&self) -> bool {
-        println!("{}", "Looting an item".green());
-        with_critical_section!(WAIT_TO_REGISTER_MS, {
+        log_info!("{}", "Looting an item".green());
+        with_input!(self.hwnd, InputPriority::Loot, WAIT_TO_REGISTER_MS, {
             focus_window(self.hwnd).as_bool() && send_key_vk(LOOT_INTERACT).is_ok()
         })
     }
 
     fn interact(&self) -> bool {
-        println!("{}", "Interacting".green());
-        with_critical_section!(WAIT_TO_REGISTER_MS, {
+        log_info!("{}", "Interacting".green());
+        with_input!(self.hwnd, InputPriority::Loot, WAIT_TO_REGISTER_MS, {
             focus_window(self.hwnd).as_bool() && send_key_vk(LOOT_INTERACT).is_ok()
         })
     }
 
     fn discard(&self) -> bool {
-        println!("{}", "Discarding an item".red());
-        with_critical_section!(WAIT_TO_REGISTER_MS, {
+        log_info!("{}", "Discarding an item".red());
+        with_input!(self.hwnd, InputPriority::Loot, WAIT_TO_REGISTER_MS, {
             focus_window(self.hwnd).as_bool() && send_key_vk(DISCARD).is_ok()
         })
     }
 
     fn target_player(&self, player_index: usize) -> bool {
-        println!("Targeting player {}", player_index + 1);
+        log_info!("Targeting player {}", player_index + 1);
         if let Some(key) = Key::get_party_keys().get(player_index) {
-            with_critical_section!(WAIT_TO_REGISTER_MS, {
+            with_input!(self.hwnd, InputPriority::Cast, WAIT_TO_REGISTER_MS, {
                 focus_window(self.hwnd).as_bool() && send_key_vk(*key).is_ok()
             })
         } else {
@@ -181,58 +266,128 @@ impl Interactor for WindowObj {
         }
     }
 
+    fn target_enemy(&self) -> bool {
+        log_info!("Targeting an enemy");
+        with_input!(self.hwnd, InputPriority::Cast, WAIT_TO_REGISTER_MS, {
+            focus_window(self.hwnd).as_bool() && send_key_vk(TARGET_ENEMY).is_ok()
+        })
+    }
+
     fn auto_attack(&self, auto_attack: AutoAttack) -> bool {
-        println!("{}", format!("Auto-attacking {:?}", auto_attack).magenta());
-        with_critical_section!(WAIT_TO_REGISTER_MS, {
-            let key = match auto_attack {
-                AutoAttack::Primary => AUTO_ATTACK,
-                AutoAttack::Ranged => AUTO_RANGED_ATTACK,
-            };
-            focus_window(self.hwnd).as_bool() && send_key_vk(key).is_ok()
+        log_info!("{}", format!("Auto-attacking {:?}", auto_attack).magenta());
+        with_input!(self.hwnd, InputPriority::Cast, WAIT_TO_REGISTER_MS, {
+            focus_window(self.hwnd).as_bool()
+                && send_key_vk(get_auto_attack_key(auto_attack)).is_ok()
         })
     }
 
     fn use_hp_pot(&self) -> bool {
-        println!("{}", "Using a HP potion".red());
-        with_critical_section!(WAIT_TO_REGISTER_MS, {
+        log_info!("{}", "Using a HP potion".red());
+        with_input!(self.hwnd, InputPriority::Potion, WAIT_TO_REGISTER_MS, {
             focus_window(self.hwnd).as_bool() && send_key_vk(HEALTH_POT).is_ok()
         })
     }
 
+    fn use_resource_pot(&self) -> bool {
+        log_info!("{}", "Using a resource potion".blue());
+        with_input!(self.hwnd, InputPriority::Potion, WAIT_TO_REGISTER_MS, {
+            focus_window(self.hwnd).as_bool() && send_key_vk(RESOURCE_POT).is_ok()
+        })
+    }
+
     fn inventory_toggle(&self) -> bool {
-        println!("{}", "Toggling an inventory".bright_purple());
-        with_critical_section!(WAIT_TO_REGISTER_MS, {
+        log_info!("{}", "Toggling an inventory".bright_purple());
+        with_input!(self.hwnd, InputPriority::Loot, WAIT_TO_REGISTER_MS, {
             focus_window(self.hwnd).as_bool() && send_key_vk(INVENTORY).is_ok()
         })
     }
 
     fn leave_to_town(&self) -> bool {
-        println!("{}", "Leaving to town".red());
-        let _lock = CRITICAL_SECTION.lock().unwrap();
-        let result1 = focus_window(self.hwnd).as_bool() && set_mouse(self.hwnd, 948, 304, true);
-        thread::sleep(Duration::from_millis(WAIT_TO_REGISTER_MS));
-        let result2 = focus_window(self.hwnd).as_bool() && set_mouse(self.hwnd, 1022, 432, true);
-        thread::sleep(Duration::from_millis(WAIT_TO_REGISTER_MS));
-        drop(_lock);
-        result1 && result2
+        log_info!("{}", "Leaving to town".red());
+        with_input!(self.hwnd, InputPriority::Movement, WAIT_TO_REGISTER_MS, {
+            let [(x1, y1), (x2, y2)] = LEAVE_TO_TOWN_CLICKS;
+            let result1 = focus_window(self.hwnd).as_bool() && set_mouse(self.hwnd, x1, y1, true);
+            self.cancellation.sleep_ms(WAIT_TO_REGISTER_MS);
+            let result2 = focus_window(self.hwnd).as_bool() && set_mouse(self.hwnd, x2, y2, true);
+            result1 && result2
+        })
     }
 
-    fn try_direction(&self, direction: Direction) -> bool {
-        print!(
+    fn go_to_dungeon(&self) -> bool {
+        log_info!("{}", "Going to dungeon".green());
+        with_input!(self.hwnd, InputPriority::Movement, WAIT_TO_REGISTER_MS, {
+            let [(x1, y1), (x2, y2), (x3, y3)] = GO_TO_DUNGEON_CLICKS;
+            let result1 = focus_window(self.hwnd).as_bool() && set_mouse(self.hwnd, x1, y1, true);
+            self.cancellation.sleep_ms(WAIT_TO_REGISTER_MS * 5);
+            let result2 = focus_window(self.hwnd).as_bool() && set_mouse(self.hwnd, x2, y2, true);
+            self.cancellation.sleep_ms(WAIT_TO_REGISTER_MS);
+            let result3 = focus_window(self.hwnd).as_bool() && set_mouse(self.hwnd, x3, y3, true);
+            result1 && result2 && result3
+        })
+    }
+
+    fn open_vendor(&self, vendor: Vendor) -> bool {
+        log_info!("{}", format!("Opening {:?}", vendor).yellow());
+        let (x, y) = get_vendor_position(vendor);
+        with_input!(self.hwnd, InputPriority::Loot, WAIT_TO_REGISTER_MS * 5, {
+            focus_window(self.hwnd).as_bool() && set_mouse(self.hwnd, x, y, true)
+        })
+    }
+
+    fn move_inventory_item(&self, slot: usize) -> bool {
+        log_info!("{}", format!("Moving inventory item {}", slot).yellow());
+        let (x, y) = get_inventory_cell_center(slot);
+        with_input!(self.hwnd, InputPriority::Loot, WAIT_TO_REGISTER_MS, {
+            focus_window(self.hwnd).as_bool() && right_click(self.hwnd, x, y)
+        })
+    }
+
+    fn close_windows(&self) -> bool {
+        log_info!("{}", "Closing windows".yellow());
+        with_input!(self.hwnd, InputPriority::Loot, WAIT_TO_REGISTER_MS, {
+            focus_window(self.hwnd).as_bool() && send_key_vk(CLOSE_WINDOWS).is_ok()
+        })
+    }
+
+    fn allocate_attribute(&self, attribute: Attribute) -> bool {
+        log_info!("{}", format!("Allocating {:?}", attribute).bright_green());
+        let (x, y) = get_attribute_position(attribute);
+        with_input!(self.hwnd, InputPriority::Loot, WAIT_TO_REGISTER_MS, {
+            focus_window(self.hwnd).as_bool() && set_mouse(self.hwnd, x, y, true)
+        })
+    }
+
+    fn allocate_skill_rank(&self, slot: usize) -> bool {
+        log_info!(
             "{}",
-            format!("Trying to go: {:?}. ", direction).bright_yellow()
+            format!("Ranking up skill {}", slot + 1).bright_green()
         );
+        let (x, y) = get_skill_rank_position(slot);
+        with_input!(self.hwnd, InputPriority::Loot, WAIT_TO_REGISTER_MS, {
+            focus_window(self.hwnd).as_bool() && set_mouse(self.hwnd, x, y, true)
+        })
+    }
 
+    fn confirm_level_up(&self) -> bool {
+        log_info!("{}", "Confirming level-up".bright_green());
+        let (x, y) = CONFIRM_LEVEL_UP_POSITION;
+        with_input!(self.hwnd, InputPriority::Loot, WAIT_TO_REGISTER_MS, {
+            focus_window(self.hwnd).as_bool() && set_mouse(self.hwnd, x, y, true)
+        })
+    }
+
+    fn try_direction(&self, direction: Direction) -> bool {
         let px_before = get_move_pixel(self.hwnd);
-        with_critical_section!(WAIT_TO_REGISTER_MS, {
+        with_input!(self.hwnd, InputPriority::Movement, WAIT_TO_REGISTER_MS, {
             focus_window(self.hwnd).as_bool() && send_key_vk(direction.to_key()).is_ok()
         });
-        thread::sleep(Duration::from_millis(1000));
+        self.cancellation.sleep_ms(1000);
         let px_after = get_move_pixel(self.hwnd);
 
         let result = px_before != px_after;
-        println!(
-            "{}: {}",
+        log_info!(
+            "{}{}: {}",
+            format!("Trying to go: {:?}. ", direction).bright_yellow(),
             format!("Can go {:?}", direction).white(),
             if result {
                 format!("{:?}", result).green()
@@ -246,18 +401,18 @@ impl Interactor for WindowObj {
     fn walk(&self, direction: Option<Direction>) -> bool {
         match direction {
             Some(direction) => {
-                println!("{}", format!("Walking... {:?}", direction).bright_yellow());
+                log_info!("{}", format!("Walking... {:?}", direction).bright_yellow());
             }
             None => {
-                println!("{}", "Auto-walking".bright_yellow());
+                log_info!("{}", "Auto-walking".bright_yellow());
             }
         }
 
-        with_critical_section!(WAIT_TO_REGISTER_MS, {
+        with_input!(self.hwnd, InputPriority::Movement, WAIT_TO_REGISTER_MS, {
             let focused = focus_window(self.hwnd).as_bool();
             if let Some(direction) = direction {
                 _ = send_key_vk(direction.to_key());
-                thread::sleep(Duration::from_millis(WAIT_TO_REGISTER_MS));
+                self.cancellation.sleep_ms(WAIT_TO_REGISTER_MS);
             }
             focused && send_key_vk(AUTO_WALK).is_ok()
         })
@@ -265,3 +420,33 @@ impl Interactor for WindowObj {
 
     fn reset_position(&self) {}
 }
+
+// town portal, then the confirmation
+pub const LEAVE_TO_TOWN_CLICKS: [(i32, i32); 2] = [(948, 304), (1022, 432)];
+// dungeon entrance, the last selected dungeon, enter
+pub const GO_TO_DUNGEON_CLICKS: [(i32, i32); 3] = [(1164, 612), (640, 286), (640, 574)];
+pub const CONFIRM_LEVEL_UP_POSITION: (i32, i32) = (640, 596);
+
+pub fn get_auto_attack_key(auto_attack: AutoAttack) -> Key {
+    match auto_attack {
+        AutoAttack::Primary => AUTO_ATTACK,
+        AutoAttack::Ranged => AUTO_RANGED_ATTACK,
+    }
+}
+
+pub fn get_attribute_position(attribute: Attribute) -> (i32, i32) {
+    (492, 214 + attribute.index() as i32 * 28)
+}
+
+pub fn get_skill_rank_position(slot: usize) -> (i32, i32) {
+    (866, 214 + slot as i32 * 30)
+}
+
+// town NPCs
+pub fn get_vendor_position(vendor: Vendor) -> (i32, i32) {
+    match vendor {
+        Vendor::Merchant => (302, 418),
+        Vendor::Bank => (524, 396),
+        Vendor::Blacksmith => (758, 422),
+    }
+}
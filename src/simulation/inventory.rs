@@ -0,0 +1,141 @@
+use crate::configuration::class_config::LootFilterItem;
+use crate::simulation::loot::{LootQuality, LootTier};
+use serde::{Deserialize, Serialize};
+
+pub const INVENTORY_COLUMNS: usize = 8;
+pub const INVENTORY_ROWS: usize = 5;
+const INVENTORY_ORIGIN: (i32, i32) = (22, 258);
+const INVENTORY_CELL_SIZE: i32 = 31;
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum InventoryAction {
+    Keep,
+    Sell,
+    Stash,
+    Salvage,
+}
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone, Hash)]
+pub enum Vendor {
+    Merchant,
+    Bank,
+    Blacksmith,
+}
+
+impl InventoryAction {
+    pub fn vendor(self) -> Option<Vendor> {
+        match self {
+            InventoryAction::Keep => None,
+            InventoryAction::Sell => Some(Vendor::Merchant),
+            InventoryAction::Stash => Some(Vendor::Bank),
+            InventoryAction::Salvage => Some(Vendor::Blacksmith),
+        }
+    }
+}
+
+// what to do with an item matching the loot filter item in a town
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct InventoryRule(pub LootFilterItem, pub InventoryAction);
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct InventoryItem {
+    pub slot: usize,
+    pub quality: LootQuality,
+    pub tier: LootTier,
+}
+
+impl InventoryItem {
+    // first matching rule wins, anything unrecognized or not matching stays in the inventory
+    pub fn get_action(&self, rules: &[InventoryRule]) -> InventoryAction {
+        if self.quality == LootQuality::Unknown || self.tier == LootTier::Unknown {
+            return InventoryAction::Keep;
+        }
+        rules
+            .iter()
+            .find(|rule| rule.0 == LootFilterItem(self.quality, self.tier))
+            .map_or(InventoryAction::Keep, |rule| rule.1)
+    }
+}
+
+// top left corner of an inventory cell, slots go row by row
+pub fn get_inventory_cell(slot: usize) -> (i32, i32) {
+    let column = (slot % INVENTORY_COLUMNS) as i32;
+    let row = (slot / INVENTORY_COLUMNS) as i32;
+    (
+        INVENTORY_ORIGIN.0 + column * INVENTORY_CELL_SIZE,
+        INVENTORY_ORIGIN.1 + row * INVENTORY_CELL_SIZE,
+    )
+}
+
+pub fn get_inventory_cell_center(slot: usize) -> (i32, i32) {
+    let (x, y) = get_inventory_cell(slot);
+    (x + INVENTORY_CELL_SIZE / 2, y + INVENTORY_CELL_SIZE / 2)
+}
+
+pub fn get_inventory_cell_size() -> i32 {
+    INVENTORY_CELL_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::class_config::LootFilterItem;
+    use crate::simulation::inventory::{
+        InventoryAction, InventoryItem, InventoryRule, get_inventory_cell,
+    };
+    use crate::simulation::loot::{LootQuality, LootTier};
+
+    #[test]
+    fn test_inventory_rules() {
+        let rules = vec![
+            InventoryRule(
+                LootFilterItem(LootQuality::Magic, LootTier::Normal),
+                InventoryAction::Sell,
+            ),
+            InventoryRule(
+                LootFilterItem(LootQuality::Rare, LootTier::Normal),
+                InventoryAction::Salvage,
+            ),
+            InventoryRule(
+                LootFilterItem(LootQuality::Legendary, LootTier::Elite),
+                InventoryAction::Stash,
+            ),
+        ];
+        let item = |quality, tier| InventoryItem {
+            slot: 0,
+            quality,
+            tier,
+        };
+
+        assert_eq!(
+            item(LootQuality::Magic, LootTier::Normal).get_action(&rules),
+            InventoryAction::Sell
+        );
+        assert_eq!(
+            item(LootQuality::Rare, LootTier::Normal).get_action(&rules),
+            InventoryAction::Salvage
+        );
+        assert_eq!(
+            item(LootQuality::Legendary, LootTier::Elite).get_action(&rules),
+            InventoryAction::Stash
+        );
+        assert_eq!(
+            item(LootQuality::Epic, LootTier::Normal).get_action(&rules),
+            InventoryAction::Keep
+        );
+        assert_eq!(
+            item(LootQuality::Unknown, LootTier::Normal).get_action(&rules),
+            InventoryAction::Keep
+        );
+    }
+
+    #[test]
+    fn test_inventory_cells() {
+        let first = get_inventory_cell(0);
+        let second = get_inventory_cell(1);
+        let next_row = get_inventory_cell(8);
+        assert_eq!(second.1, first.1);
+        assert!(second.0 > first.0);
+        assert_eq!(next_row.0, first.0);
+        assert!(next_row.1 > first.1);
+    }
+}
@@ -4,8 +4,8 @@ use serde::de::Error;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use windows::Win32::UI::Input::KeyboardAndMouse::{
     VIRTUAL_KEY, VK_0, VK_1, VK_2, VK_3, VK_4, VK_5, VK_6, VK_7, VK_8, VK_9, VK_A, VK_B, VK_D,
-    VK_E, VK_F, VK_F1, VK_F2, VK_F3, VK_F4, VK_F5, VK_I, VK_OEM_MINUS, VK_OEM_PLUS, VK_Q, VK_S,
-    VK_T, VK_W, VK_Z,
+    VK_E, VK_ESCAPE, VK_F, VK_F1, VK_F2, VK_F3, VK_F4, VK_F5, VK_G, VK_I, VK_OEM_MINUS,
+    VK_OEM_PLUS, VK_Q, VK_S, VK_T, VK_TAB, VK_W, VK_Z,
 };
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -56,11 +56,32 @@ pub const SKILL_BUTTON_10: Key = Key(VK_0);
 pub const SKILL_BUTTON_11: Key = Key(VK_OEM_MINUS);
 pub const SKILL_BUTTON_12: Key = Key(VK_OEM_PLUS);
 pub const HEALTH_POT: Key = Key(VK_F);
+pub const RESOURCE_POT: Key = Key(VK_G);
+pub const CLOSE_WINDOWS: Key = Key(VK_ESCAPE);
+// the nearest enemy, e.g. back from a party member after a heal
+pub const TARGET_ENEMY: Key = Key(VK_TAB);
 
 impl Key {
     pub fn get_party_keys() -> Vec<Key> {
         vec![Key(VK_F1), Key(VK_F2), Key(VK_F3), Key(VK_F4), Key(VK_F5)]
     }
+
+    pub fn get_skill_keys() -> Vec<Key> {
+        vec![
+            SKILL_BUTTON_1,
+            SKILL_BUTTON_2,
+            SKILL_BUTTON_3,
+            SKILL_BUTTON_4,
+            SKILL_BUTTON_5,
+            SKILL_BUTTON_6,
+            SKILL_BUTTON_7,
+            SKILL_BUTTON_8,
+            SKILL_BUTTON_9,
+            SKILL_BUTTON_10,
+            SKILL_BUTTON_11,
+            SKILL_BUTTON_12,
+        ]
+    }
 }
 
 impl From<VIRTUAL_KEY> for Key {
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Attribute {
+    Strength,
+    Stamina,
+    Agility,
+    Dexterity,
+    Wisdom,
+    Intelligence,
+    Charisma,
+}
+
+impl Attribute {
+    // row of the attribute in the level-up dialog
+    pub fn index(self) -> usize {
+        match self {
+            Attribute::Strength => 0,
+            Attribute::Stamina => 1,
+            Attribute::Agility => 2,
+            Attribute::Dexterity => 3,
+            Attribute::Wisdom => 4,
+            Attribute::Intelligence => 5,
+            Attribute::Charisma => 6,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq)]
+pub struct LevelUpPlan {
+    // points put into an attribute on every level-up
+    #[serde(default)]
+    pub attributes: Vec<(Attribute, u32)>,
+    // skill ranks bought when reaching a level
+    #[serde(default)]
+    pub skills: Vec<(u32, String)>,
+}
+
+impl LevelUpPlan {
+    pub fn get_attribute_points(&self) -> Vec<Attribute> {
+        self.attributes
+            .iter()
+            .flat_map(|(attribute, points)| std::iter::repeat_n(*attribute, *points as usize))
+            .collect()
+    }
+
+    // without knowing the level we can't tell which ranks are due
+    pub fn get_skill_ranks(&self, level: Option<u32>) -> Vec<String> {
+        match level {
+            Some(level) => self
+                .skills
+                .iter()
+                .filter(|(skill_level, _)| *skill_level == level)
+                .map(|(_, name)| name.clone())
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulation::level_up::{Attribute, LevelUpPlan};
+
+    #[test]
+    fn test_level_up_plan() {
+        let plan = LevelUpPlan {
+            attributes: vec![(Attribute::Intelligence, 3), (Attribute::Stamina, 2)],
+            skills: vec![
+                (5, "Venom Bolt".to_string()),
+                (6, "Engulfing Darkness".to_string()),
+                (6, "Icing Death".to_string()),
+            ],
+        };
+
+        assert_eq!(
+            plan.get_attribute_points(),
+            vec![
+                Attribute::Intelligence,
+                Attribute::Intelligence,
+                Attribute::Intelligence,
+                Attribute::Stamina,
+                Attribute::Stamina,
+            ]
+        );
+        assert_eq!(
+            plan.get_skill_ranks(Some(6)),
+            vec!["Engulfing Darkness".to_string(), "Icing Death".to_string()]
+        );
+        assert!(plan.get_skill_ranks(Some(7)).is_empty());
+        assert!(plan.get_skill_ranks(None).is_empty());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::simulation::maze_solver::{Direction, Node, Pos};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+const MAPS_FOLDER: &str = "maps/";
+// pixels per tile in the SVG
+const TILE: i32 = 24;
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum MapFormat {
+    Json,
+    Ascii,
+    Svg,
+}
+
+impl MapFormat {
+    pub const ALL: [MapFormat; 3] = [MapFormat::Json, MapFormat::Ascii, MapFormat::Svg];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            MapFormat::Json => "json",
+            MapFormat::Ascii => "txt",
+            MapFormat::Svg => "svg",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            MapFormat::Json => "application/json",
+            MapFormat::Ascii => "text/plain; charset=utf-8",
+            MapFormat::Svg => "image/svg+xml",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct MapTile {
+    pub pos: Pos,
+    pub visited: bool,
+    // the ways out the solver knows about
+    pub neighbors: Vec<Direction>,
+}
+
+// what the solver thinks the dungeon looks like, (0, 0) is where it entered
+#[derive(Serialize, Debug, Clone, Eq, PartialEq)]
+pub struct MazeSnapshot {
+    pub strategy: String,
+    pub current_pos: Pos,
+    // the tiles a depth-first exploration still has to backtrack through
+    pub stack: Vec<Pos>,
+    pub steps: usize,
+    pub tiles: Vec<MapTile>,
+}
+
+impl MazeSnapshot {
+    pub fn new(
+        strategy: &str,
+        map: &HashMap<Pos, Node>,
+        current_pos: Pos,
+        stack: Vec<Pos>,
+        steps: usize,
+    ) -> MazeSnapshot {
+        let mut tiles: Vec<MapTile> = map
+            .iter()
+            .map(|(pos, node)| MapTile {
+                pos: *pos,
+                visited: node.visited,
+                neighbors: Direction::ALL
+                    .into_iter()
+                    .filter(|d| node.neighbors.contains_key(d))
+                    .collect(),
+            })
+            .collect();
+        // rows top to bottom, so two exports of the same map are the same file
+        tiles.sort_by_key(|tile| (tile.pos.1, tile.pos.0));
+        MazeSnapshot {
+            strategy: strategy.to_string(),
+            current_pos,
+            stack,
+            steps,
+            tiles,
+        }
+    }
+
+    pub fn render(&self, format: MapFormat) -> String {
+        match format {
+            MapFormat::Json => serde_json::to_string_pretty(self)
+                .unwrap_or_else(|e| format!(r#"{{"error":"{}"}}"#, e)),
+            MapFormat::Ascii => self.to_ascii(),
+            MapFormat::Svg => self.to_svg(),
+        }
+    }
+
+    // top left and bottom right corner, the current position counts too
+    fn bounds(&self) -> (Pos, Pos) {
+        let positions = self
+            .tiles
+            .iter()
+            .map(|tile| tile.pos)
+            .chain([self.current_pos]);
+        positions.fold(
+            (self.current_pos, self.current_pos),
+            |((min_x, min_y), (max_x, max_y)), (x, y)| {
+                ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)))
+            },
+        )
+    }
+
+    fn tile_char(&self, tile: &MapTile) -> char {
+        if tile.pos == self.current_pos {
+            '@'
+        } else if self.stack.contains(&tile.pos) {
+            '*'
+        } else if tile.visited {
+            'o'
+        } else {
+            '?'
+        }
+    }
+
+    // one character per tile with the ways between them, north is up like in the game
+    pub fn to_ascii(&self) -> String {
+        let ((min_x, min_y), (max_x, max_y)) = self.bounds();
+        let width = (2 * (max_x - min_x) + 1) as usize;
+        let height = (2 * (max_y - min_y) + 1) as usize;
+        let mut grid = vec![vec![' '; width]; height];
+        for tile in &self.tiles {
+            let column = (2 * (tile.pos.0 - min_x)) as usize;
+            let row = (2 * (tile.pos.1 - min_y)) as usize;
+            grid[row][column] = self.tile_char(tile);
+            // every way is drawn from its left or upper end
+            if tile.neighbors.contains(&Direction::Right) && column + 1 < width {
+                grid[row][column + 1] = '-';
+            }
+            if tile.neighbors.contains(&Direction::Down) && row + 1 < height {
+                grid[row + 1][column] = '|';
+            }
+        }
+        let mut ascii: String = grid
+            .into_iter()
+            .map(|row| row.into_iter().collect::<String>().trim_end().to_string() + "\n")
+            .collect();
+        ascii.push_str("@ here, * to backtrack through, o visited, ? not visited yet\n");
+        ascii
+    }
+
+    pub fn to_svg(&self) -> String {
+        let ((min_x, min_y), (max_x, max_y)) = self.bounds();
+        let center = |(x, y): Pos| ((x - min_x) * TILE + TILE / 2, (y - min_y) * TILE + TILE / 2);
+        let mut svg = format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">"#,
+            (max_x - min_x + 1) * TILE,
+            (max_y - min_y + 1) * TILE
+        );
+        svg.push('\n');
+        for tile in &self.tiles {
+            let (x, y) = center(tile.pos);
+            for dir in [Direction::Right, Direction::Down] {
+                if tile.neighbors.contains(&dir) {
+                    let delta = dir.delta();
+                    let _ = writeln!(
+                        svg,
+                        r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="gray" stroke-width="4"/>"#,
+                        x,
+                        y,
+                        x + delta.0 * TILE,
+                        y + delta.1 * TILE
+                    );
+                }
+            }
+        }
+        for tile in &self.tiles {
+            let (x, y) = center(tile.pos);
+            let (fill, stroke) = match self.tile_char(tile) {
+                '@' => ("red", "black"),
+                '*' => ("orange", "black"),
+                'o' => ("lightgray", "black"),
+                _ => ("white", "gray"),
+            };
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}" stroke="{}"><title>{:?}</title></rect>"#,
+                x - TILE / 3,
+                y - TILE / 3,
+                2 * TILE / 3,
+                2 * TILE / 3,
+                fill,
+                stroke,
+                tile.pos
+            );
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    // every format next to each other, e.g. maps/0-1700000000.svg
+    pub fn save(&self, name: &str) -> io::Result<PathBuf> {
+        fs::create_dir_all(MAPS_FOLDER)?;
+        let path = Path::new(MAPS_FOLDER).join(name);
+        for format in MapFormat::ALL {
+            fs::write(path.with_extension(format.extension()), self.render(format))?;
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::amtx;
+    use crate::simulation::char_state::CharState;
+    use crate::simulation::exploration::Exploration;
+    use crate::simulation::maze_export::MapFormat;
+    use crate::simulation::maze_solver::Direction::*;
+    use crate::simulation::maze_solver::{Node, Solver};
+    use crate::simulation::simulation_state::DebugObj;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_export() {
+        // A - B
+        //     |
+        //     C
+        let map = amtx!(HashMap::from([
+            ((0, 0), Node::new(false, HashMap::from([(Right, (1, 0))]))),
+            (
+                (1, 0),
+                Node::new(false, HashMap::from([(Left, (0, 0)), (Down, (1, 1))]))
+            ),
+            ((1, 1), Node::new(false, HashMap::from([(Up, (1, 0))]))),
+        ]));
+        let solver = Solver::with_exploration(
+            Box::new(DebugObj::new(CharState::InDungeon, map, 0.into(), 0.into())),
+            &Exploration::DepthFirst,
+        );
+        // A was left for B
+        solver.explore_step();
+
+        let snapshot = solver.snapshot();
+        assert_eq!(snapshot.current_pos, (1, 0));
+        assert_eq!(snapshot.stack, vec![(0, 0), (1, 0)]);
+        assert_eq!(snapshot.to_ascii().lines().next(), Some("*-@"));
+
+        // B went down to C, the way there is known
+        solver.explore_step();
+        let snapshot = solver.snapshot();
+        assert_eq!(
+            snapshot.to_ascii().lines().take(3).collect::<Vec<_>>(),
+            vec!["*-*", "  |", "  @"]
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&snapshot.render(MapFormat::Json)).unwrap();
+        assert_eq!(json["current_pos"], serde_json::json!([1, 1]));
+        assert_eq!(json["tiles"].as_array().unwrap().len(), 3);
+        let svg = snapshot.render(MapFormat::Svg);
+        assert_eq!(svg.matches("<rect").count(), 3);
+        assert_eq!(svg.matches("<line").count(), 2);
+    }
+}
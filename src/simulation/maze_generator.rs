@@ -0,0 +1,141 @@
+use crate::simulation::exploration::next;
+use crate::simulation::maze_solver::{Direction, Node, Pos};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+// a tiny LCG, so a failing maze can be rebuilt from the seed in the test output
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    // 0..n
+    pub fn below(&mut self, n: usize) -> usize {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) as usize % n
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct MazeSpec {
+    pub width: i32,
+    pub height: i32,
+    // extra openings on top of the tree of corridors
+    pub loops: usize,
+    // tiles nobody can stand on, rooms walled in by them are carved as separate pockets
+    pub obstacles: usize,
+    pub seed: u64,
+}
+
+impl Default for MazeSpec {
+    fn default() -> Self {
+        MazeSpec {
+            width: 8,
+            height: 8,
+            loops: 0,
+            obstacles: 0,
+            seed: 1,
+        }
+    }
+}
+
+impl MazeSpec {
+    // anything from a single corridor to a big maze full of loops and obstacles
+    pub fn random(seed: u64) -> MazeSpec {
+        let mut rng = Rng::new(seed);
+        let (width, height) = (1 + rng.below(12) as i32, 1 + rng.below(12) as i32);
+        let tiles = (width * height) as usize;
+        MazeSpec {
+            width,
+            height,
+            loops: rng.below(tiles / 2 + 1),
+            obstacles: rng.below(tiles / 4 + 1),
+            seed,
+        }
+    }
+}
+
+// corridors carved from (0, 0) by a seeded random walk, the dead ends come from the walk backing up
+pub fn generate_maze(spec: &MazeSpec) -> HashMap<Pos, Node> {
+    let mut rng = Rng::new(spec.seed);
+    let inside = |(x, y): Pos| x >= 0 && y >= 0 && x < spec.width && y < spec.height;
+
+    let mut obstacles = HashSet::new();
+    for _ in 0..spec.obstacles {
+        let pos = (
+            rng.below(spec.width as usize) as i32,
+            rng.below(spec.height as usize) as i32,
+        );
+        if pos != (0, 0) {
+            obstacles.insert(pos);
+        }
+    }
+
+    // the first walk starts at the entrance, the others carve the pockets it could not reach
+    let mut map: HashMap<Pos, Node> = HashMap::new();
+    for y in 0..spec.height {
+        for x in 0..spec.width {
+            let start = (x, y);
+            if obstacles.contains(&start) || map.contains_key(&start) {
+                continue;
+            }
+            map.insert(start, Node::default());
+            let mut stack = vec![start];
+            while let Some(&pos) = stack.last() {
+                let options: Vec<Direction> = Direction::ALL
+                    .into_iter()
+                    .filter(|d| {
+                        let next = next(pos, *d);
+                        inside(next) && !obstacles.contains(&next) && !map.contains_key(&next)
+                    })
+                    .collect();
+                if options.is_empty() {
+                    stack.pop();
+                    continue;
+                }
+                let dir = options[rng.below(options.len())];
+                connect(&mut map, pos, dir);
+                stack.push(next(pos, dir));
+            }
+        }
+    }
+
+    for _ in 0..spec.loops {
+        let pos = (
+            rng.below(spec.width as usize) as i32,
+            rng.below(spec.height as usize) as i32,
+        );
+        let dir = Direction::ALL[rng.below(4)];
+        if map.contains_key(&pos) && map.contains_key(&next(pos, dir)) {
+            connect(&mut map, pos, dir);
+        }
+    }
+    map
+}
+
+fn connect(map: &mut HashMap<Pos, Node>, pos: Pos, dir: Direction) {
+    let next = next(pos, dir);
+    map.entry(pos).or_default().neighbors.insert(dir, next);
+    map.entry(next)
+        .or_default()
+        .neighbors
+        .insert(dir.opposite(), pos);
+}
+
+// every room there is a way to from the given one
+pub fn reachable(map: &HashMap<Pos, Node>, from: Pos) -> HashSet<Pos> {
+    let mut seen = HashSet::from([from]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(pos) = queue.pop_front() {
+        for next in map[&pos].neighbors.values() {
+            if seen.insert(*next) {
+                queue.push_back(*next);
+            }
+        }
+    }
+    seen
+}
@@ -1,7 +1,12 @@
 use crate::amtx;
+use crate::log_info;
+use crate::simulation::exploration::{Exploration, ExplorationStrategy, Maze};
 use crate::simulation::interactor::Interactor;
 use crate::simulation::keys::{Key, WALK_DOWN, WALK_LEFT, WALK_RIGHT, WALK_UP};
+use crate::simulation::maze_export::MazeSnapshot;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 
 pub type Pos = (i32, i32);
@@ -19,7 +24,7 @@ impl Node {
     }
 }
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone, Hash)]
+#[derive(Deserialize, Serialize, Debug, Eq, PartialEq, Copy, Clone, Hash)]
 pub enum Direction {
     Left,
     Right,
@@ -55,105 +60,106 @@ impl Direction {
         }
     }
 
-    const ALL: [Direction; 4] = [Self::Up, Self::Down, Self::Left, Self::Right];
-}
+    pub fn clockwise(self) -> Direction {
+        match self {
+            Direction::Up => Direction::Right,
+            Direction::Right => Direction::Down,
+            Direction::Down => Direction::Left,
+            Direction::Left => Direction::Up,
+        }
+    }
 
-type Stack = Vec<(Pos, Vec<Direction>)>;
+    pub fn counter_clockwise(self) -> Direction {
+        self.clockwise().opposite()
+    }
+
+    pub const ALL: [Direction; 4] = [Self::Up, Self::Down, Self::Left, Self::Right];
+}
 
 pub struct Solver {
     map: Arc<Mutex<HashMap<Pos, Node>>>,
     interactor: Box<dyn Interactor + Send + Sync>,
-    stack: Arc<Mutex<Stack>>,
+    strategy: Mutex<Box<dyn ExplorationStrategy + Send + Sync>>,
     current_pos: Arc<Mutex<Pos>>,
+    last_moves: Arc<Mutex<Vec<Direction>>>,
+    steps: AtomicUsize,
 }
 
 impl Solver {
+    #[cfg(test)]
     pub fn new(interactor: Box<dyn Interactor + Send + Sync>) -> Self {
+        Self::with_exploration(interactor, &Exploration::default())
+    }
+
+    pub fn with_exploration(
+        interactor: Box<dyn Interactor + Send + Sync>,
+        exploration: &Exploration,
+    ) -> Self {
         let start_pos = (0, 0);
         let map = amtx!(HashMap::from([(start_pos, Node::default())]));
+        let strategy = exploration.strategy();
+        log_info!("Exploring with {}", strategy.name());
         Self {
             map,
             interactor,
-            stack: amtx!(vec![(start_pos, Direction::ALL.to_vec())]),
+            strategy: Mutex::new(strategy),
             current_pos: amtx!(start_pos),
+            last_moves: amtx!(vec![]),
+            steps: AtomicUsize::new(0),
         }
     }
 
+    // true when everything is explored
     pub fn explore_step(&self) -> bool {
-        let mut stack = self.stack.lock().unwrap();
         let mut map = self.map.lock().unwrap();
         let mut current_pos = self.current_pos.lock().unwrap();
+        let mut maze = Maze::new(&mut map, *current_pos, self.interactor.as_ref());
+        let done = self.strategy.lock().unwrap().step(&mut maze);
+        *current_pos = maze.pos;
+        self.steps.fetch_add(maze.moves.len(), Ordering::SeqCst);
+        *self.last_moves.lock().unwrap() = maze.moves;
+        done
+    }
 
-        if stack.is_empty() {
-            return true; // Exploration done
-        }
-
-        let (pos, directions) = stack.last_mut().unwrap();
-
-        // Mark current node visited
-        map.entry(*pos).or_default().visited = true;
-
-        while let Some(dir) = directions.pop() {
-            let delta = dir.delta();
-            let next_pos = (pos.0 + delta.0, pos.1 + delta.1);
-
-            if map.get(&next_pos).is_some_and(|n| n.visited) {
-                continue; // Already visited
-            }
-
-            // Try to move in this direction
-            if self.interactor.try_direction(dir) {
-                // Update map with the connection
-                map.get_mut(pos).unwrap().neighbors.insert(dir, next_pos);
-                map.entry(next_pos)
-                    .or_default()
-                    .neighbors
-                    .insert(dir.opposite(), *pos);
-
-                // Perform the move
-                self.interactor.walk(Some(dir));
-                *current_pos = next_pos;
-
-                // Push new node onto the stack with all 4 directions
-                stack.push((next_pos, Direction::ALL.to_vec()));
-
-                return false; // only 1 move per step
-            }
-        }
-
-        // No more directions to try — backtrack
-        stack.pop();
-
-        if let Some((parent_pos, _)) = stack.last() {
-            let back_dir = Direction::ALL.iter().find(|&&d| {
-                let delta = d.delta();
-                let candidate = (current_pos.0 + delta.0, current_pos.1 + delta.1);
-                candidate == *parent_pos
-            });
+    // the tiles walked during the last step
+    pub fn last_moves(&self) -> Vec<Direction> {
+        self.last_moves.lock().unwrap().clone()
+    }
 
-            if let Some(dir) = back_dir {
-                self.interactor.walk(Some(*dir));
-                *current_pos = *parent_pos;
-            }
-        }
+    // tiles walked since the last reset
+    pub fn steps(&self) -> usize {
+        self.steps.load(Ordering::SeqCst)
+    }
 
-        false // Backtracked, still not done
+    pub fn snapshot(&self) -> MazeSnapshot {
+        // the same lock order as explore_step, the API and the hotkeys call this from other threads
+        let map = self.map.lock().unwrap();
+        let current_pos = self.current_pos.lock().unwrap();
+        let strategy = self.strategy.lock().unwrap();
+        MazeSnapshot::new(
+            strategy.name(),
+            &map,
+            *current_pos,
+            strategy.stack(),
+            self.steps(),
+        )
     }
 
     pub fn reset(&self) {
-        println!("Resetting maze solver");
+        log_info!("Resetting maze solver");
         self.interactor.reset_position();
 
-        let mut stack = self.stack.lock().unwrap();
-        let mut current_pos = self.current_pos.lock().unwrap();
         let mut map = self.map.lock().unwrap();
+        let mut current_pos = self.current_pos.lock().unwrap();
 
-        stack.clear();
-        stack.push(((0, 0), Direction::ALL.to_vec()));
+        self.strategy.lock().unwrap().reset();
         *current_pos = (0, 0);
+        self.last_moves.lock().unwrap().clear();
+        self.steps.store(0, Ordering::SeqCst);
 
         map.clear();
-        map.entry((0, 0)).or_default().visited = true;
+        // visited on the next step, some strategies look around first
+        map.insert((0, 0), Node::default());
     }
 }
 
@@ -161,10 +167,13 @@ impl Solver {
 mod tests {
     use crate::amtx;
     use crate::simulation::char_state::CharState;
+    use crate::simulation::exploration::Exploration;
+    use crate::simulation::maze_generator::{MazeSpec, Rng, generate_maze, reachable};
     use crate::simulation::maze_solver::Direction::*;
     use crate::simulation::maze_solver::{Node, Pos, Solver};
     use crate::simulation::simulation_state::DebugObj;
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::Ordering;
     use std::sync::{Arc, Mutex};
 
     #[test]
@@ -217,6 +226,190 @@ mod tests {
         assert!(result_map.iter().all(|item| item.1.visited))
     }
 
+    #[test]
+    fn test_frontier_takes_fewer_steps() {
+        let mut mazes = vec![(generate_test_map(), 8)];
+        for seed in 1..=5 {
+            mazes.push((
+                maze(MazeSpec {
+                    width: 12,
+                    height: 12,
+                    loops: 20,
+                    seed,
+                    ..MazeSpec::default()
+                }),
+                144,
+            ));
+        }
+
+        // a probe takes about as long in the game as walking a tile, so both count
+        let (mut depth_first_total, mut frontier_total) = (0, 0);
+        for (maze, size) in mazes {
+            let (steps, probes, visited) = explore(&maze, &Exploration::DepthFirst, size);
+            assert_eq!(visited, size);
+            let depth_first = steps + probes;
+            let (steps, probes, visited) = explore(&maze, &Exploration::Frontier, size);
+            assert_eq!(visited, size);
+            let frontier = steps + probes;
+            depth_first_total += depth_first;
+            frontier_total += frontier;
+        }
+        assert!(
+            frontier_total < depth_first_total,
+            "frontier {} vs depth-first {} steps and probes",
+            frontier_total,
+            depth_first_total
+        );
+    }
+
+    #[test]
+    fn test_wall_follower() {
+        // without loops there is just one wall to follow
+        for seed in 1..=5 {
+            let maze = maze(MazeSpec {
+                seed,
+                ..MazeSpec::default()
+            });
+            let (steps, _, visited) = explore(&maze, &Exploration::WallFollower, 64);
+            assert_eq!(visited, 64);
+            assert!(steps <= 4 * 64);
+        }
+        // with loops the rooms around the pillars are picked up after the wall
+        let maze = maze(MazeSpec {
+            loops: 20,
+            ..MazeSpec::default()
+        });
+        let (_, _, visited) = explore(&maze, &Exploration::WallFollower, 64);
+        assert_eq!(visited, 64);
+    }
+
+    #[test]
+    fn test_time_boxed() {
+        let maze = maze(MazeSpec {
+            loops: 10,
+            ..MazeSpec::default()
+        });
+        let exploration = Exploration::TimeBoxed {
+            strategy: Box::new(Exploration::Frontier),
+            rooms: 10,
+            timeout_ms: None,
+        };
+        let (_, _, visited) = explore(&maze, &exploration, 64);
+        assert_eq!(visited, 10);
+
+        let exploration = Exploration::TimeBoxed {
+            strategy: Box::new(Exploration::DepthFirst),
+            rooms: 100,
+            timeout_ms: Some(0),
+        };
+        assert_eq!(explore(&maze, &exploration, 64), (0, 0, 0));
+    }
+
+    #[test]
+    fn test_exploration_config() {
+        let exploration: Exploration =
+            toml::from_str::<HashMap<String, Exploration>>("e = { TimeBoxed = { rooms = 30 } }")
+                .unwrap()
+                .remove("e")
+                .unwrap();
+        assert_eq!(
+            exploration,
+            Exploration::TimeBoxed {
+                strategy: Box::new(Exploration::DepthFirst),
+                rooms: 30,
+                timeout_ms: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_strategies_on_random_mazes() {
+        let strategies = [
+            Exploration::DepthFirst,
+            Exploration::Frontier,
+            Exploration::WallFollower,
+            Exploration::TimeBoxed {
+                strategy: Box::new(Exploration::Frontier),
+                rooms: usize::MAX,
+                timeout_ms: None,
+            },
+        ];
+        for seed in 1..=200 {
+            let spec = MazeSpec::random(seed);
+            let maze = generate_maze(&spec);
+            let reachable = reachable(&maze, (0, 0));
+            let maze = amtx!(maze);
+            for exploration in &strategies {
+                // everything there is a way to, and nothing else
+                let (visited, wall_hits) = explore_flaky(&maze, exploration, None);
+                assert_eq!(visited, reachable, "{:?} on {:?}", exploration, spec);
+                assert_eq!(wall_hits, 0, "{:?} on {:?}", exploration, spec);
+
+                // a way that was not found is skipped, but the walls still hold
+                let (visited, wall_hits) = explore_flaky(&maze, exploration, Some(seed));
+                assert!(
+                    visited.is_subset(&reachable),
+                    "{:?} on {:?}",
+                    exploration,
+                    spec
+                );
+                assert_eq!(wall_hits, 0, "{:?} on {:?}", exploration, spec);
+            }
+        }
+    }
+
+    fn maze(spec: MazeSpec) -> Arc<Mutex<HashMap<Pos, Node>>> {
+        amtx!(generate_maze(&spec))
+    }
+
+    // explores until the strategy is done, a third of the probes fail when seeded
+    // returns the visited tiles and how often it walked through a wall
+    fn explore_flaky(
+        maze: &Arc<Mutex<HashMap<Pos, Node>>>,
+        exploration: &Exploration,
+        seed: Option<u64>,
+    ) -> (HashSet<Pos>, usize) {
+        let mut obj = DebugObj::new(CharState::InDungeon, maze.clone(), 0.into(), 0.into());
+        if let Some(seed) = seed {
+            let mut rng = Rng::new(seed);
+            obj = obj.with_probe_failures(move || rng.below(3) == 0);
+        }
+        let wall_hits = obj.wall_hits.clone();
+        let solver = Solver::with_exploration(Box::new(obj), exploration);
+        let size = maze.lock().unwrap().len();
+        let mut counter = 0;
+        while !solver.explore_step() {
+            counter += 1;
+            assert!(counter < 10 * size, "{:?} did not finish", exploration);
+        }
+        let result_map = solver.map.lock().unwrap();
+        let visited = result_map
+            .iter()
+            .filter(|(_, n)| n.visited)
+            .map(|(pos, _)| *pos)
+            .collect();
+        (visited, wall_hits.load(Ordering::SeqCst))
+    }
+
+    // explores until the strategy is done, returns the number of walked tiles, probes and visited tiles
+    fn explore(
+        maze: &Arc<Mutex<HashMap<Pos, Node>>>,
+        exploration: &Exploration,
+        size: usize,
+    ) -> (usize, usize, usize) {
+        let obj = DebugObj::new(CharState::InDungeon, maze.clone(), 0.into(), 0.into());
+        let probes = obj.probes.clone();
+        let solver = Solver::with_exploration(Box::new(obj), exploration);
+        let mut counter = 0;
+        while !solver.explore_step() {
+            counter += 1;
+            assert!(counter < 10 * size, "{:?} did not finish", exploration);
+        }
+        let result_map = solver.map.lock().unwrap();
+        let visited = result_map.values().filter(|n| n.visited).count();
+        (solver.steps(), probes.load(Ordering::SeqCst), visited)
+    }
+
     // MAP: starting top left at (0, 0)
     //
     // A(0,0) - B(1,0) - C(2,0)
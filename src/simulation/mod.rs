@@ -1,19 +1,28 @@
-use std::any::type_name;
-
+pub mod actor;
+pub mod behavior;
+pub mod cancellation;
 pub mod char_state;
-pub mod global_lock;
+pub mod death;
+pub mod exploration;
+pub mod follow;
+pub mod input_scheduler;
 pub mod interactor;
+pub mod inventory;
 pub mod keys;
+pub mod level_up;
 pub mod loot;
+pub mod maze_export;
+#[cfg(test)]
+pub mod maze_generator;
 pub mod maze_solver;
+pub mod readiness;
+pub mod recorder;
 pub mod rotation;
+pub mod script;
 pub mod shared_state;
 pub mod simulation_state;
 pub mod skill;
+pub mod skill_target;
 pub mod skill_tracker;
 pub mod skill_type;
 pub mod state_checker;
-
-pub fn type_of<T>(_: &T) -> &'static str {
-    type_name::<T>()
-}
@@ -0,0 +1,145 @@
+use crate::simulation::char_state::CharState;
+use crate::simulation::shared_state::{MEMBER_HEARTBEAT_TIMEOUT_MS, MemberStatus};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// what the party has to look like before the master takes the next exploration step
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ReadinessConfig {
+    pub enabled: bool,
+    pub min_hp_percent: f32,
+    pub min_resource_percent: f32,
+    // has to be up on any member, e.g. "Clarity"
+    pub required_buffs: Vec<String>,
+    // the master moves on anyway after waiting this long
+    pub timeout_ms: u64,
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        ReadinessConfig {
+            enabled: true,
+            min_hp_percent: 60.0,
+            min_resource_percent: 30.0,
+            required_buffs: vec![],
+            timeout_ms: 30_000,
+        }
+    }
+}
+
+impl ReadinessConfig {
+    // HP and resource are only looked at when the member is not busy, no need to read them otherwise
+    pub fn needs_vitals(&self, state: CharState) -> bool {
+        self.enabled
+            && !matches!(
+                state,
+                CharState::Dead | CharState::Fighting | CharState::Looting
+            )
+    }
+
+    // None when the party is ready, otherwise what we are waiting for
+    pub fn not_ready_reason(&self, members: &[MemberStatus]) -> Option<String> {
+        let max_age = Duration::from_millis(MEMBER_HEARTBEAT_TIMEOUT_MS);
+        // members that stopped publishing are not waited for
+        let members: Vec<&MemberStatus> = members.iter().filter(|m| !m.is_stale(max_age)).collect();
+        for member in &members {
+            let reason = match member.state {
+                CharState::Dead => Some("dead".to_string()),
+                CharState::Fighting => Some("fighting".to_string()),
+                CharState::Looting => Some("looting".to_string()),
+                _ => match (member.hp_percent, member.resource_percent) {
+                    (Some(hp), _) if hp < self.min_hp_percent => Some(format!("on {:.0}% HP", hp)),
+                    (_, Some(resource)) if resource < self.min_resource_percent => {
+                        Some(format!("on {:.0}% resource", resource))
+                    }
+                    _ => None,
+                },
+            };
+            if let Some(reason) = reason {
+                return Some(format!("{} is {}", member, reason));
+            }
+        }
+        self.required_buffs
+            .iter()
+            .find(|buff| !members.iter().any(|m| m.buffs.contains(buff)))
+            .map(|buff| format!("{} is down", buff))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::config::Class;
+    use crate::simulation::char_state::CharState;
+    use crate::simulation::readiness::ReadinessConfig;
+    use crate::simulation::shared_state::{MemberReport, SharedStateHandle};
+
+    fn report(state: CharState, hp: f32, resource: f32, buffs: &[&str]) -> MemberReport {
+        MemberReport {
+            state,
+            hp_percent: Some(hp),
+            resource_percent: Some(resource),
+            buffs: buffs.iter().map(|b| b.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_not_ready_reason() {
+        let state = SharedStateHandle::new(0.0, 0.0);
+        state.register_member(0, Class::Warrior).unwrap();
+        state.register_member(1, Class::Warlock).unwrap();
+        let readiness = ReadinessConfig {
+            required_buffs: vec!["Clarity".to_string()],
+            ..ReadinessConfig::default()
+        };
+
+        state
+            .publish_member(0, report(CharState::InDungeon, 100.0, 100.0, &[]))
+            .unwrap();
+        state
+            .publish_member(1, report(CharState::Looting, 100.0, 100.0, &[]))
+            .unwrap();
+        assert_eq!(
+            readiness.not_ready_reason(&state.get_members().unwrap()),
+            Some("#1 Warlock is looting".to_string())
+        );
+
+        state
+            .publish_member(1, report(CharState::InDungeon, 100.0, 10.0, &[]))
+            .unwrap();
+        assert_eq!(
+            readiness.not_ready_reason(&state.get_members().unwrap()),
+            Some("#1 Warlock is on 10% resource".to_string())
+        );
+
+        state
+            .publish_member(1, report(CharState::InDungeon, 100.0, 100.0, &[]))
+            .unwrap();
+        assert_eq!(
+            readiness.not_ready_reason(&state.get_members().unwrap()),
+            Some("Clarity is down".to_string())
+        );
+
+        state
+            .publish_member(0, report(CharState::InDungeon, 100.0, 100.0, &["Clarity"]))
+            .unwrap();
+        assert_eq!(
+            readiness.not_ready_reason(&state.get_members().unwrap()),
+            None
+        );
+
+        state.stop();
+    }
+
+    #[test]
+    fn test_needs_vitals() {
+        let readiness = ReadinessConfig::default();
+        assert!(readiness.needs_vitals(CharState::InDungeon));
+        assert!(!readiness.needs_vitals(CharState::Fighting));
+        let disabled = ReadinessConfig {
+            enabled: false,
+            ..ReadinessConfig::default()
+        };
+        assert!(!disabled.needs_vitals(CharState::InDungeon));
+    }
+}
@@ -0,0 +1,435 @@
+use crate::configuration::class_config::AutoAttack;
+use crate::log_error;
+use crate::simulation::interactor::{
+    CONFIRM_LEVEL_UP_POSITION, GO_TO_DUNGEON_CLICKS, Interactor, LEAVE_TO_TOWN_CLICKS,
+    get_attribute_position, get_auto_attack_key, get_skill_rank_position, get_vendor_position,
+};
+use crate::simulation::inventory::{Vendor, get_inventory_cell_center};
+use crate::simulation::keys::{
+    AUTO_WALK, CLOSE_WINDOWS, DISCARD, HEALTH_POT, INVENTORY, Key, LOOT_INTERACT, RESOURCE_POT,
+    TARGET_ENEMY,
+};
+use crate::simulation::level_up::Attribute;
+use crate::simulation::maze_solver::Direction;
+use crate::simulation::skill::Skill;
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Error, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::{io, thread};
+
+// one variant per interactor method
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub enum Action {
+    CastSkill(Skill),
+    Loot,
+    Interact,
+    Discard,
+    TargetPlayer(usize),
+    TargetEnemy,
+    AutoAttack(AutoAttack),
+    UseHpPot,
+    UseResourcePot,
+    InventoryToggle,
+    LeaveToTown,
+    GoToDungeon,
+    OpenVendor(Vendor),
+    MoveInventoryItem(usize),
+    CloseWindows,
+    AllocateAttribute(Attribute),
+    AllocateSkillRank(usize),
+    ConfirmLevelUp,
+    TryDirection(Direction),
+    Walk(Option<Direction>),
+    ResetPosition,
+}
+
+// what the window receives
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum Input {
+    Key(Key),
+    Click(i32, i32),
+    RightClick(i32, i32),
+}
+
+impl Action {
+    // the keys and clicks a WindowObj sends for the action
+    pub fn inputs(&self) -> Vec<Input> {
+        let click = |(x, y): (i32, i32)| Input::Click(x, y);
+        match self {
+            Action::CastSkill(skill) => vec![Input::Key(skill.key)],
+            Action::Loot | Action::Interact => vec![Input::Key(LOOT_INTERACT)],
+            Action::Discard => vec![Input::Key(DISCARD)],
+            Action::TargetPlayer(index) => Key::get_party_keys()
+                .get(*index)
+                .map(|key| Input::Key(*key))
+                .into_iter()
+                .collect(),
+            Action::TargetEnemy => vec![Input::Key(TARGET_ENEMY)],
+            Action::AutoAttack(auto_attack) => vec![Input::Key(get_auto_attack_key(*auto_attack))],
+            Action::UseHpPot => vec![Input::Key(HEALTH_POT)],
+            Action::UseResourcePot => vec![Input::Key(RESOURCE_POT)],
+            Action::InventoryToggle => vec![Input::Key(INVENTORY)],
+            Action::LeaveToTown => LEAVE_TO_TOWN_CLICKS.into_iter().map(click).collect(),
+            Action::GoToDungeon => GO_TO_DUNGEON_CLICKS.into_iter().map(click).collect(),
+            Action::OpenVendor(vendor) => vec![click(get_vendor_position(*vendor))],
+            Action::MoveInventoryItem(slot) => {
+                let (x, y) = get_inventory_cell_center(*slot);
+                vec![Input::RightClick(x, y)]
+            }
+            Action::CloseWindows => vec![Input::Key(CLOSE_WINDOWS)],
+            Action::AllocateAttribute(attribute) => vec![click(get_attribute_position(*attribute))],
+            Action::AllocateSkillRank(slot) => vec![click(get_skill_rank_position(*slot))],
+            Action::ConfirmLevelUp => vec![click(CONFIRM_LEVEL_UP_POSITION)],
+            Action::TryDirection(direction) => vec![Input::Key(direction.to_key())],
+            Action::Walk(direction) => direction
+                .map(|d| Input::Key(d.to_key()))
+                .into_iter()
+                .chain([Input::Key(AUTO_WALK)])
+                .collect(),
+            Action::ResetPosition => vec![],
+        }
+    }
+
+    pub fn execute(&self, interactor: &dyn Interactor) -> bool {
+        match self {
+            Action::CastSkill(skill) => interactor.cast_skill(skill),
+            Action::Loot => interactor.loot(),
+            Action::Interact => interactor.interact(),
+            Action::Discard => interactor.discard(),
+            Action::TargetPlayer(index) => interactor.target_player(*index),
+            Action::TargetEnemy => interactor.target_enemy(),
+            Action::AutoAttack(auto_attack) => interactor.auto_attack(*auto_attack),
+            Action::UseHpPot => interactor.use_hp_pot(),
+            Action::UseResourcePot => interactor.use_resource_pot(),
+            Action::InventoryToggle => interactor.inventory_toggle(),
+            Action::LeaveToTown => interactor.leave_to_town(),
+            Action::GoToDungeon => interactor.go_to_dungeon(),
+            Action::OpenVendor(vendor) => interactor.open_vendor(*vendor),
+            Action::MoveInventoryItem(slot) => interactor.move_inventory_item(*slot),
+            Action::CloseWindows => interactor.close_windows(),
+            Action::AllocateAttribute(attribute) => interactor.allocate_attribute(*attribute),
+            Action::AllocateSkillRank(slot) => interactor.allocate_skill_rank(*slot),
+            Action::ConfirmLevelUp => interactor.confirm_level_up(),
+            Action::TryDirection(direction) => interactor.try_direction(*direction),
+            Action::Walk(direction) => interactor.walk(*direction),
+            Action::ResetPosition => {
+                interactor.reset_position();
+                true
+            }
+        }
+    }
+}
+
+// a line of the recording
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct RecordedAction {
+    pub window: usize,
+    pub timestamp_ms: u128,
+    pub action: Action,
+    pub inputs: Vec<Input>,
+    pub result: bool,
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis())
+}
+
+type Listener = Box<dyn Fn(&RecordedAction) + Send + Sync>;
+
+// JSON lines shared by all the recorded windows, listeners get every action too
+#[derive(Default)]
+pub struct Recorder {
+    writer: Mutex<Option<Box<dyn Write + Send>>>,
+    listeners: Mutex<Vec<Listener>>,
+}
+
+impl Recorder {
+    pub fn new(writer: Box<dyn Write + Send>) -> Recorder {
+        Recorder {
+            writer: Mutex::new(Some(writer)),
+            listeners: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn create<P: AsRef<Path>>(file_path: P) -> io::Result<Recorder> {
+        let file = File::create(file_path)?;
+        Ok(Recorder::new(Box::new(BufWriter::new(file))))
+    }
+
+    pub fn subscribe(&self, listener: impl Fn(&RecordedAction) + Send + Sync + 'static) {
+        self.listeners.lock().unwrap().push(Box::new(listener));
+    }
+
+    fn write(&self, record: &RecordedAction) {
+        self.listeners
+            .lock()
+            .unwrap()
+            .iter()
+            .for_each(|listener| listener(record));
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(writer) = writer.as_mut() else {
+            return;
+        };
+        let written = serde_json::to_string(record)
+            .map_err(Error::other)
+            .and_then(|line| writeln!(writer, "{}", line))
+            .and_then(|_| writer.flush());
+        if let Err(e) = written {
+            log_error!("{}", format!("Failed to record an action: {}", e).red());
+        }
+    }
+}
+
+// logs everything the inner interactor does
+pub struct RecordingInteractor {
+    window: usize,
+    inner: Box<dyn Interactor + Send + Sync>,
+    recorder: Arc<Recorder>,
+}
+
+impl RecordingInteractor {
+    pub fn new(
+        window: usize,
+        inner: Box<dyn Interactor + Send + Sync>,
+        recorder: Arc<Recorder>,
+    ) -> RecordingInteractor {
+        RecordingInteractor {
+            window,
+            inner,
+            recorder,
+        }
+    }
+
+    fn record(&self, action: Action) -> bool {
+        let result = action.execute(self.inner.as_ref());
+        self.recorder.write(&RecordedAction {
+            window: self.window,
+            timestamp_ms: now_ms(),
+            inputs: action.inputs(),
+            action,
+            result,
+        });
+        result
+    }
+}
+
+impl Interactor for RecordingInteractor {
+    fn cast_skill(&self, skill: &Skill) -> bool {
+        self.record(Action::CastSkill(skill.clone()))
+    }
+
+    fn loot(&self) -> bool {
+        self.record(Action::Loot)
+    }
+
+    fn interact(&self) -> bool {
+        self.record(Action::Interact)
+    }
+
+    fn discard(&self) -> bool {
+        self.record(Action::Discard)
+    }
+
+    fn target_player(&self, player_index: usize) -> bool {
+        self.record(Action::TargetPlayer(player_index))
+    }
+
+    fn target_enemy(&self) -> bool {
+        self.record(Action::TargetEnemy)
+    }
+
+    fn auto_attack(&self, auto_attack: AutoAttack) -> bool {
+        self.record(Action::AutoAttack(auto_attack))
+    }
+
+    fn use_hp_pot(&self) -> bool {
+        self.record(Action::UseHpPot)
+    }
+
+    fn use_resource_pot(&self) -> bool {
+        self.record(Action::UseResourcePot)
+    }
+
+    fn inventory_toggle(&self) -> bool {
+        self.record(Action::InventoryToggle)
+    }
+
+    fn leave_to_town(&self) -> bool {
+        self.record(Action::LeaveToTown)
+    }
+
+    fn go_to_dungeon(&self) -> bool {
+        self.record(Action::GoToDungeon)
+    }
+
+    fn open_vendor(&self, vendor: Vendor) -> bool {
+        self.record(Action::OpenVendor(vendor))
+    }
+
+    fn move_inventory_item(&self, slot: usize) -> bool {
+        self.record(Action::MoveInventoryItem(slot))
+    }
+
+    fn close_windows(&self) -> bool {
+        self.record(Action::CloseWindows)
+    }
+
+    fn allocate_attribute(&self, attribute: Attribute) -> bool {
+        self.record(Action::AllocateAttribute(attribute))
+    }
+
+    fn allocate_skill_rank(&self, slot: usize) -> bool {
+        self.record(Action::AllocateSkillRank(slot))
+    }
+
+    fn confirm_level_up(&self) -> bool {
+        self.record(Action::ConfirmLevelUp)
+    }
+
+    fn try_direction(&self, direction: Direction) -> bool {
+        self.record(Action::TryDirection(direction))
+    }
+
+    fn walk(&self, direction: Option<Direction>) -> bool {
+        self.record(Action::Walk(direction))
+    }
+
+    fn reset_position(&self) {
+        self.record(Action::ResetPosition);
+    }
+}
+
+pub fn load_recording<P: AsRef<Path>>(file_path: P) -> io::Result<Vec<RecordedAction>> {
+    let file = File::open(file_path)?;
+    BufReader::new(file)
+        .lines()
+        .filter(|line| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+        .map(|line| serde_json::from_str(&line?).map_err(Error::other))
+        .collect()
+}
+
+// re-executes the recording, interactors are indexed by the window; keep_timing waits as long as the original did
+pub fn replay(
+    recording: &[RecordedAction],
+    interactors: &[Box<dyn Interactor + Send + Sync>],
+    keep_timing: bool,
+) -> Vec<RecordedAction> {
+    let mut replayed = vec![];
+    let mut previous_ms = None;
+    for record in recording {
+        let Some(interactor) = interactors.get(record.window) else {
+            log_error!("No interactor for window {}, skipping", record.window);
+            continue;
+        };
+        if keep_timing && let Some(previous_ms) = previous_ms {
+            let gap = record.timestamp_ms.saturating_sub(previous_ms) as u64;
+            thread::sleep(Duration::from_millis(gap));
+        }
+        previous_ms = Some(record.timestamp_ms);
+        let result = record.action.execute(interactor.as_ref());
+        replayed.push(RecordedAction {
+            timestamp_ms: now_ms(),
+            result,
+            ..record.clone()
+        });
+    }
+    replayed
+}
+
+// differences between two recordings, timestamps aside
+pub fn diff(expected: &[RecordedAction], actual: &[RecordedAction]) -> Vec<String> {
+    let mut differences: Vec<String> = expected
+        .iter()
+        .zip(actual)
+        .enumerate()
+        .filter(|(_, (e, a))| {
+            (e.window, &e.action, &e.inputs, e.result) != (a.window, &a.action, &a.inputs, a.result)
+        })
+        .map(|(i, (e, a))| {
+            format!(
+                "#{}: expected window {} {:?} -> {}, got window {} {:?} -> {}",
+                i, e.window, e.action, e.result, a.window, a.action, a.result
+            )
+        })
+        .collect();
+    if expected.len() != actual.len() {
+        differences.push(format!(
+            "expected {} actions, got {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::amtx;
+    use crate::configuration::class_config::AutoAttack;
+    use crate::simulation::char_state::CharState;
+    use crate::simulation::interactor::Interactor;
+    use crate::simulation::inventory::Vendor;
+    use crate::simulation::keys::{AUTO_ATTACK, AUTO_WALK, WALK_UP};
+    use crate::simulation::maze_solver::{Direction, Node};
+    use crate::simulation::recorder::{
+        Action, Input, Recorder, RecordingInteractor, diff, load_recording, replay,
+    };
+    use crate::simulation::simulation_state::DebugObj;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::{env, fs};
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = env::temp_dir().join(format!("boxer_recording_{}.jsonl", std::process::id()));
+        let recorder = Arc::new(Recorder::create(&path).expect("Could not create the recording"));
+        let first = RecordingInteractor::new(0, DebugObj::boxed(), recorder.clone());
+        let second = RecordingInteractor::new(1, DebugObj::boxed(), recorder.clone());
+
+        assert!(first.auto_attack(AutoAttack::Primary));
+        assert!(second.open_vendor(Vendor::Merchant));
+        // nothing to walk into on an empty test map
+        assert!(!first.try_direction(Direction::Up));
+        first.walk(Some(Direction::Up));
+        drop((first, second, recorder));
+
+        let recording = load_recording(&path).expect("Could not load the recording");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(recording.len(), 4);
+        assert_eq!(recording[0].action, Action::AutoAttack(AutoAttack::Primary));
+        assert_eq!(recording[0].inputs, vec![Input::Key(AUTO_ATTACK)]);
+        assert_eq!(recording[1].window, 1);
+        assert!(matches!(recording[1].inputs[..], [Input::Click(_, _)]));
+        assert!(!recording[2].result);
+        assert_eq!(
+            recording[3].inputs,
+            vec![Input::Key(WALK_UP), Input::Key(AUTO_WALK)]
+        );
+
+        let interactors: Vec<Box<dyn Interactor + Send + Sync>> =
+            vec![DebugObj::boxed(), DebugObj::boxed()];
+        let replayed = replay(&recording, &interactors, false);
+        assert!(diff(&recording, &replayed).is_empty());
+
+        // a map with a way up changes the decision
+        let map = amtx!(HashMap::from([(
+            (0, 0),
+            Node::new(false, HashMap::from([(Direction::Up, (0, 1))])),
+        )]));
+        let interactors: Vec<Box<dyn Interactor + Send + Sync>> = vec![
+            Box::new(DebugObj::new(CharState::Fighting, map, 0.into(), 0.into())),
+            DebugObj::boxed(),
+        ];
+        let replayed = replay(&recording, &interactors, false);
+        let differences = diff(&recording, &replayed);
+        assert_eq!(differences.len(), 1);
+        assert!(differences[0].starts_with("#2"));
+        assert_eq!(diff(&recording, &replayed[..3]).len(), 2);
+    }
+}
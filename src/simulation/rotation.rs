@@ -1,4 +1,6 @@
 use crate::configuration::config::Class;
+use crate::log_error;
+use crate::log_info;
 use crate::simulation::skill::Skill;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
@@ -9,17 +11,93 @@ use std::{fs, io};
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rotation {
     pub skills: Vec<Skill>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<RotationRule>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RotationRule {
+    // skip the skill while mana/energy is below the percentage
+    SkipBelowResource(String, f32),
+    // use a resource potion while mana/energy is below the percentage
+    ResourcePotBelow(f32),
+    // skip the skill with fewer enemies engaged, e.g. AoE on single targets
+    SkipBelowEnemies(String, usize),
+    // skip the skill with more enemies engaged, e.g. single target skills on packs
+    SkipAboveEnemies(String, usize),
+    // skip the skill while the target HP is below the percentage, e.g. long debuffs on nearly dead targets
+    SkipBelowTargetHp(String, f32),
+}
+
+// what we know about the fight when deciding about a skill
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RotationContext {
+    pub resource: Option<f32>,
+    pub enemies: Option<usize>,
+    pub target_hp: Option<f32>,
 }
 
 impl Rotation {
+    pub fn needs_resource(&self) -> bool {
+        self.rules.iter().any(|rule| {
+            matches!(
+                rule,
+                RotationRule::SkipBelowResource(..) | RotationRule::ResourcePotBelow(_)
+            )
+        })
+    }
+
+    pub fn needs_enemy_count(&self) -> bool {
+        self.rules.iter().any(|rule| {
+            matches!(
+                rule,
+                RotationRule::SkipBelowEnemies(..) | RotationRule::SkipAboveEnemies(..)
+            )
+        })
+    }
+
+    pub fn needs_target_hp(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| matches!(rule, RotationRule::SkipBelowTargetHp(..)))
+    }
+
+    // unknown values never skip a skill
+    pub fn is_skipped(&self, skill: &Skill, context: &RotationContext) -> bool {
+        self.rules.iter().any(|rule| match rule {
+            RotationRule::SkipBelowResource(name, threshold) => {
+                *name == skill.name && context.resource.is_some_and(|r| r < *threshold)
+            }
+            RotationRule::ResourcePotBelow(_) => false,
+            RotationRule::SkipBelowEnemies(name, count) => {
+                *name == skill.name && context.enemies.is_some_and(|e| e < *count)
+            }
+            RotationRule::SkipAboveEnemies(name, count) => {
+                *name == skill.name && context.enemies.is_some_and(|e| e > *count)
+            }
+            RotationRule::SkipBelowTargetHp(name, threshold) => {
+                *name == skill.name && context.target_hp.is_some_and(|hp| hp < *threshold)
+            }
+        })
+    }
+
+    pub fn needs_resource_pot(&self, context: &RotationContext) -> bool {
+        self.rules.iter().any(|rule| match rule {
+            RotationRule::ResourcePotBelow(threshold) => {
+                context.resource.is_some_and(|r| r < *threshold)
+            }
+            _ => false,
+        })
+    }
+
     fn load<P: AsRef<Path> + Debug>(file_path: P) -> io::Result<Self> {
-        println!("Loading rotation from {:?}", file_path);
+        log_info!("Loading rotation from {:?}", file_path);
         let file_str = fs::read_to_string(file_path)?;
         let result: io::Result<Self> = serde_json::from_str(&file_str).map_err(Error::other);
         match result {
             Ok(r) => Ok(r),
             Err(e) => {
-                eprintln!("{}", e);
+                log_error!("{}", e);
                 Err(e)
             }
         }
@@ -27,14 +105,14 @@ impl Rotation {
 
     #[allow(dead_code)]
     fn save<P: AsRef<Path> + Debug>(&self, file_path: P) -> io::Result<()> {
-        println!("Saving rotation to {:?}", file_path);
+        log_info!("Saving rotation to {:?}", file_path);
         let serialized_r = serde_json::to_string_pretty(self).map_err(Error::other);
         match serialized_r {
             Ok(serialized) => {
                 fs::write(file_path, serialized)?;
             }
             Err(e) => {
-                eprintln!("{}", e);
+                log_error!("{}", e);
                 return Err(e);
             }
         }
@@ -52,12 +130,150 @@ impl Rotation {
         });
     }
 
+    // skills the character has not learned yet are left out
+    pub fn for_level(mut self, level: Option<u32>) -> Rotation {
+        self.skills.retain(|skill| skill.is_unlocked(level));
+        self
+    }
+
     pub fn load_rotation(class: Class) -> Rotation {
+        Rotation::read_rotation(class).unwrap_or_else(|e| {
+            panic!("Loading of {} rotation failed: {}", class, e);
+        })
+    }
+
+    pub fn read_rotation(class: Class) -> io::Result<Rotation> {
         let file_name = format!("{}.json", class);
         let folder_name = "rotations/";
         let path = Path::new(folder_name).join(file_name);
-        Rotation::load(path).unwrap_or_else(|e| {
-            panic!("Loading of {} rotation failed: {}", class, e);
-        })
+        Rotation::load(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::config::Class;
+    use crate::simulation::keys::SKILL_BUTTON_4;
+    use crate::simulation::rotation::{Rotation, RotationContext, RotationRule};
+    use crate::simulation::skill::Skill;
+    use crate::simulation::skill_type::SkillType;
+
+    #[test]
+    fn test_resource_rules() {
+        let venom_bolt = Skill {
+            name: "Venom Bolt".to_string(),
+            key: SKILL_BUTTON_4,
+            cast_time: 3.0,
+            cooldown: 0.0,
+            buff_duration: None,
+            debuff_duration: None,
+            skill_type: SkillType::Attack,
+            target: None,
+            min_level: None,
+        };
+        let rotation = Rotation {
+            skills: vec![venom_bolt.clone()],
+            rules: vec![
+                RotationRule::SkipBelowResource("Venom Bolt".to_string(), 30.0),
+                RotationRule::ResourcePotBelow(20.0),
+            ],
+        };
+
+        let context = |resource| RotationContext {
+            resource,
+            ..Default::default()
+        };
+        assert!(!rotation.is_skipped(&venom_bolt, &context(Some(50.0))));
+        assert!(rotation.is_skipped(&venom_bolt, &context(Some(25.0))));
+        // unknown resource should not block the rotation
+        assert!(!rotation.is_skipped(&venom_bolt, &context(None)));
+
+        assert!(!rotation.needs_resource_pot(&context(Some(25.0))));
+        assert!(rotation.needs_resource_pot(&context(Some(15.0))));
+    }
+
+    #[test]
+    fn test_fight_rules() {
+        let skill = |name: &str| Skill {
+            name: name.to_string(),
+            key: SKILL_BUTTON_4,
+            cast_time: 0.0,
+            cooldown: 0.0,
+            buff_duration: None,
+            debuff_duration: None,
+            skill_type: SkillType::Attack,
+            target: None,
+            min_level: None,
+        };
+        let rotation = Rotation {
+            skills: vec![],
+            rules: vec![
+                RotationRule::SkipBelowEnemies("Furious Cleave".to_string(), 3),
+                RotationRule::SkipAboveEnemies("Double Throw".to_string(), 2),
+                RotationRule::SkipBelowTargetHp("Engulfing Darkness".to_string(), 25.0),
+            ],
+        };
+        assert!(rotation.needs_enemy_count());
+        assert!(rotation.needs_target_hp());
+        assert!(!rotation.needs_resource());
+
+        let pack = RotationContext {
+            resource: None,
+            enemies: Some(4),
+            target_hp: Some(10.0),
+        };
+        assert!(!rotation.is_skipped(&skill("Furious Cleave"), &pack));
+        assert!(rotation.is_skipped(&skill("Double Throw"), &pack));
+        assert!(rotation.is_skipped(&skill("Engulfing Darkness"), &pack));
+
+        let single = RotationContext {
+            resource: None,
+            enemies: Some(1),
+            target_hp: Some(80.0),
+        };
+        assert!(rotation.is_skipped(&skill("Furious Cleave"), &single));
+        assert!(!rotation.is_skipped(&skill("Double Throw"), &single));
+        assert!(!rotation.is_skipped(&skill("Engulfing Darkness"), &single));
+    }
+
+    #[test]
+    fn test_load_rotations() {
+        for class in [Class::Enchanter, Class::Warlock, Class::Warrior, Class::Bard] {
+            let rotation = Rotation::load_rotation(class);
+            assert!(!rotation.skills.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_rotation_for_level() {
+        let skill = |name: &str, min_level| Skill {
+            name: name.to_string(),
+            key: SKILL_BUTTON_4,
+            cast_time: 0.0,
+            cooldown: 0.0,
+            buff_duration: None,
+            debuff_duration: None,
+            skill_type: SkillType::Attack,
+            target: None,
+            min_level,
+        };
+        let rotation = Rotation {
+            skills: vec![
+                skill("Venom Bolt", None),
+                skill("Engulfing Darkness", Some(6)),
+                skill("Icing Death", Some(12)),
+            ],
+            rules: vec![],
+        };
+        let names = |rotation: Rotation| -> Vec<String> {
+            rotation.skills.into_iter().map(|s| s.name).collect()
+        };
+
+        assert_eq!(
+            names(rotation.clone().for_level(Some(6))),
+            vec!["Venom Bolt".to_string(), "Engulfing Darkness".to_string()]
+        );
+        assert_eq!(names(rotation.clone().for_level(Some(1))).len(), 1);
+        assert_eq!(names(rotation.for_level(None)).len(), 3);
     }
 }
@@ -0,0 +1,376 @@
+use crate::configuration::class_config::ClassConfig;
+use crate::log_error;
+use crate::log_info;
+use crate::simulation::cancellation::CancellationToken;
+use crate::simulation::char_state::CharState;
+use crate::simulation::interactor::Interactor;
+use crate::simulation::shared_state::SharedStateHandle;
+use crate::simulation::simulation_state::SimulationState;
+use crate::simulation::skill::Skill;
+use crate::simulation::skill_tracker::SkillTrackerHandle;
+use crate::simulation::state_checker::StateChecker;
+use rhai::{AST, Array, Dynamic, Engine, Scope};
+use std::fmt::Debug;
+use std::io::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::{fs, io};
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Phase {
+    Combat,
+    Looting,
+    Movement,
+}
+
+impl Phase {
+    // name of the script function overriding the phase
+    fn function_name(self) -> &'static str {
+        match self {
+            Phase::Combat => "combat",
+            Phase::Looting => "looting",
+            Phase::Movement => "movement",
+        }
+    }
+}
+
+// what a script can see and do, handed over to every phase function as `api`
+#[derive(Clone)]
+pub struct ScriptApi {
+    interactor: Arc<dyn Interactor + Send + Sync>,
+    state_checker: Arc<dyn StateChecker + Send + Sync>,
+    skill_tracker: SkillTrackerHandle,
+    shared_state: Arc<SharedStateHandle>,
+    window: usize,
+    class_config: ClassConfig,
+    skills: Vec<Skill>,
+    num_players: usize,
+    state: CharState,
+    cancellation: CancellationToken,
+}
+
+impl ScriptApi {
+    pub fn new(sim: &SimulationState, state: CharState) -> ScriptApi {
+        ScriptApi {
+            interactor: sim.interactor.clone(),
+            state_checker: sim.state_checker.clone(),
+            skill_tracker: sim.skill_tracker.clone(),
+            shared_state: sim.shared_state.clone(),
+            window: sim.id,
+            class_config: sim.window_config.class_config.clone(),
+            skills: sim.rotation.read().unwrap().skills.clone(),
+            num_players: sim.num_active_characters,
+            state,
+            cancellation: sim.cancellation.clone(),
+        }
+    }
+
+    fn find_skill(&self, name: &str) -> Option<Skill> {
+        self.skills.iter().find(|skill| skill.name == name).cloned()
+    }
+
+    fn cast(&mut self, name: &str) -> bool {
+        let Some(skill) = self.find_skill(name) else {
+            log_error!("Script tried to cast an unknown skill {}", name);
+            return false;
+        };
+        if !self.interactor.cast_skill(&skill) {
+            log_error!("Couldn't cast skill {}", skill.name);
+            return false;
+        }
+        self.skill_tracker
+            .track_cast(&skill, self.class_config.cd_reductions.as_ref());
+        let ms = skill.get_wait_ms(self.shared_state.clone(), &self.class_config);
+        self.cancellation.sleep_ms(ms);
+        true
+    }
+
+    fn should_cast(&mut self, name: &str) -> bool {
+        self.find_skill(name).is_some_and(|skill| {
+            self.skill_tracker.should_cast(
+                &skill,
+                self.class_config.cd_reductions.as_ref(),
+                self.state,
+            )
+        })
+    }
+}
+
+fn optional_float(value: Option<f32>) -> Dynamic {
+    value.map_or(Dynamic::UNIT, |v| Dynamic::from_float(v as f64))
+}
+
+fn present_names(icons: Vec<(String, bool)>) -> Array {
+    icons
+        .into_iter()
+        .filter(|(_, present)| *present)
+        .map(|(name, _)| Dynamic::from(name))
+        .collect()
+}
+
+// sleeps of the script wake up on quitting like ours
+fn create_engine(cancellation: CancellationToken) -> Engine {
+    let mut engine = Engine::new();
+    engine.register_fn("sleep", move |ms: i64| {
+        cancellation.sleep_ms(ms.max(0) as u64);
+    });
+    engine
+        .register_type_with_name::<ScriptApi>("Api")
+        // state checker
+        .register_fn("state", |api: &mut ScriptApi| format!("{:?}", api.state))
+        .register_fn("party_hp", |api: &mut ScriptApi| -> Array {
+            api.state_checker
+                .get_party_hp(api.num_players)
+                .into_iter()
+                .map(|hp| Dynamic::from_float(hp as f64))
+                .collect()
+        })
+        .register_fn("resource", |api: &mut ScriptApi| {
+            optional_float(api.state_checker.get_resource_percent(api.num_players))
+        })
+        .register_fn("target_hp", |api: &mut ScriptApi| {
+            optional_float(api.state_checker.get_target_hp())
+        })
+        .register_fn("enemies", |api: &mut ScriptApi| {
+            api.state_checker
+                .get_enemy_count()
+                .map_or(Dynamic::UNIT, |count| Dynamic::from_int(count as i64))
+        })
+        .register_fn("buffs", |api: &mut ScriptApi| {
+            present_names(api.state_checker.get_buffs())
+        })
+        .register_fn("target_debuffs", |api: &mut ScriptApi| {
+            present_names(api.state_checker.get_target_debuffs())
+        })
+        .register_fn("is_on_low_hp", |api: &mut ScriptApi| {
+            api.state_checker.is_on_low_hp(api.num_players)
+        })
+        .register_fn("is_inventory_full", |api: &mut ScriptApi| {
+            api.state_checker.is_inventory_full()
+        })
+        // interactor
+        .register_fn("cast", ScriptApi::cast)
+        .register_fn("target_player", |api: &mut ScriptApi, index: i64| {
+            api.interactor.target_player(index.max(0) as usize)
+        })
+        .register_fn("use_hp_pot", |api: &mut ScriptApi| {
+            api.skill_tracker.track_hp_pot();
+            api.interactor.use_hp_pot()
+        })
+        .register_fn("use_resource_pot", |api: &mut ScriptApi| {
+            api.skill_tracker.track_resource_pot();
+            api.interactor.use_resource_pot()
+        })
+        .register_fn("loot", |api: &mut ScriptApi| api.interactor.loot())
+        .register_fn("discard", |api: &mut ScriptApi| api.interactor.discard())
+        .register_fn("interact", |api: &mut ScriptApi| api.interactor.interact())
+        .register_fn("walk", |api: &mut ScriptApi| api.interactor.walk(None))
+        // skill tracker
+        .register_fn("should_cast", ScriptApi::should_cast)
+        .register_fn("is_hp_pot_on_cooldown", |api: &mut ScriptApi| {
+            api.skill_tracker.is_hp_pot_on_cooldown()
+        })
+        .register_fn("is_resource_pot_on_cooldown", |api: &mut ScriptApi| {
+            api.skill_tracker.is_resource_pot_on_cooldown()
+        })
+        // shared state
+        .register_fn("full_inventory", |api: &mut ScriptApi| {
+            api.shared_state.get_full_inventory()
+        })
+        .register_fn("set_full_inventory", |api: &mut ScriptApi, full: bool| {
+            if let Err(e) = api.shared_state.set_inventory_full(api.window, full) {
+                log_error!("Script couldn't set the full inventory: {}", e);
+            }
+        })
+        .register_fn("member_states", |api: &mut ScriptApi| -> Array {
+            api.shared_state
+                .get_member_states()
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(_, state)| Dynamic::from(format!("{:?}", state)))
+                .collect()
+        })
+        .register_fn("party_looting", |api: &mut ScriptApi| {
+            api.shared_state
+                .get_members()
+                .unwrap_or_default()
+                .iter()
+                .any(|m| m.window != api.window && m.is_looting())
+        });
+    engine
+}
+
+pub struct CharacterScript {
+    engine: Engine,
+    ast: AST,
+}
+
+impl CharacterScript {
+    pub fn compile(source: &str, cancellation: CancellationToken) -> io::Result<CharacterScript> {
+        let engine = create_engine(cancellation);
+        let ast = engine.compile(source).map_err(Error::other)?;
+        Ok(CharacterScript { engine, ast })
+    }
+
+    fn load<P: AsRef<Path> + Debug>(
+        file_path: P,
+        cancellation: CancellationToken,
+    ) -> io::Result<CharacterScript> {
+        log_info!("Loading script from {:?}", file_path);
+        let source = fs::read_to_string(file_path)?;
+        CharacterScript::compile(&source, cancellation)
+    }
+
+    pub fn load_script(
+        file_name: &str,
+        cancellation: CancellationToken,
+    ) -> io::Result<CharacterScript> {
+        let folder_name = "scripts/";
+        let path = Path::new(folder_name).join(file_name);
+        CharacterScript::load(path, cancellation)
+    }
+
+    pub fn overrides(&self, phase: Phase) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|f| f.name == phase.function_name() && f.params.len() == 1)
+    }
+
+    // Some(true) if the script took care of the phase, None if the script does not override it
+    pub fn run_phase(&self, phase: Phase, api: ScriptApi) -> Option<bool> {
+        if !self.overrides(phase) {
+            return None;
+        }
+        let mut scope = Scope::new();
+        match self
+            .engine
+            .call_fn::<bool>(&mut scope, &self.ast, phase.function_name(), (api,))
+        {
+            Ok(handled) => Some(handled),
+            Err(e) => {
+                log_error!("Script {:?} phase failed: {}", phase, e);
+                Some(false)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::class_config::{AutoAttack, ClassConfig};
+    use crate::configuration::config::{Class, Config};
+    use crate::simulation::cancellation::CancellationToken;
+    use crate::simulation::char_state::CharState;
+    use crate::simulation::keys::SKILL_BUTTON_1;
+    use crate::simulation::rotation::Rotation;
+    use crate::simulation::script::{CharacterScript, Phase, ScriptApi};
+    use crate::simulation::shared_state::SharedStateHandle;
+    use crate::simulation::simulation_state::SimulationState;
+    use crate::simulation::skill::Skill;
+    use crate::simulation::skill_type::SkillType;
+    use std::fs;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn simulation(shared_state: Arc<SharedStateHandle>) -> SimulationState {
+        let mut window = Config::default().windows.first().unwrap().clone();
+        window.class_config = ClassConfig::new(
+            Class::Warlock,
+            None,
+            Some(vec!["Venom Bolt".to_string()]),
+            None,
+            None,
+            vec![],
+            vec![],
+            AutoAttack::Primary,
+        );
+        let venom_bolt = Skill {
+            name: "Venom Bolt".to_string(),
+            key: SKILL_BUTTON_1,
+            cast_time: 0.0,
+            cooldown: 10.0,
+            buff_duration: None,
+            debuff_duration: None,
+            skill_type: SkillType::Attack,
+            target: None,
+            min_level: None,
+        };
+        let mut sim = SimulationState::for_test(
+            0,
+            window,
+            Rotation {
+                skills: vec![venom_bolt],
+                rules: vec![],
+            },
+            shared_state,
+        );
+        sim.sync_interval_ms = 100;
+        sim
+    }
+
+    #[test]
+    fn test_script_phases() {
+        let shared_state = Arc::new(SharedStateHandle::new(0.0, 0.0));
+        let sim = simulation(shared_state.clone());
+        let script = CharacterScript::compile(
+            r#"
+            fn combat(api) {
+                if api.state() == "Fighting" && api.enemies() == 1 && api.should_cast("Venom Bolt") {
+                    api.cast("Venom Bolt")
+                } else {
+                    false
+                }
+            }
+            "#,
+            sim.cancellation.clone(),
+        )
+        .expect("Could not compile the script");
+
+        assert!(script.overrides(Phase::Combat));
+        assert_eq!(
+            script.run_phase(Phase::Looting, ScriptApi::new(&sim, CharState::Fighting)),
+            None
+        );
+        assert_eq!(
+            script.run_phase(Phase::Combat, ScriptApi::new(&sim, CharState::Fighting)),
+            Some(true)
+        );
+        // now it's on a cooldown
+        assert_eq!(
+            script.run_phase(Phase::Combat, ScriptApi::new(&sim, CharState::Fighting)),
+            Some(false)
+        );
+
+        shared_state.stop();
+    }
+
+    #[test]
+    fn test_load_scripts() {
+        for entry in fs::read_dir("scripts").expect("Missing scripts directory") {
+            let file_name = entry.unwrap().file_name();
+            CharacterScript::load_script(file_name.to_str().unwrap(), CancellationToken::new())
+                .expect("Could not compile the script");
+        }
+    }
+
+    #[test]
+    fn test_sleep_wakes_up_on_stop() {
+        let shared_state = Arc::new(SharedStateHandle::new(0.0, 0.0));
+        let sim = simulation(shared_state.clone());
+        let script = CharacterScript::compile(
+            "fn movement(api) { sleep(600000); true }",
+            sim.cancellation.clone(),
+        )
+        .expect("Could not compile the script");
+
+        sim.stop();
+        let started = Instant::now();
+        assert_eq!(
+            script.run_phase(Phase::Movement, ScriptApi::new(&sim, CharState::InDungeon)),
+            Some(true)
+        );
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        shared_state.stop();
+    }
+}
@@ -1,9 +1,113 @@
+use crate::configuration::config::Class;
+use crate::simulation::actor::{Actor, ActorError, ActorMetrics, ActorRef};
+use crate::simulation::char_state::CharState;
+use crate::simulation::follow::MasterTrail;
+use crate::simulation::maze_solver::Direction;
 use crate::simulation::shared_state::SharedStateMessage::*;
-use crate::simulation::type_of;
-use colored::Colorize;
-use std::sync::mpsc;
-use std::sync::mpsc::{Receiver, Sender};
-use std::thread;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
+
+// a member that has not published for this long is considered gone
+pub const MEMBER_HEARTBEAT_TIMEOUT_MS: u64 = 10_000;
+
+#[derive(Serialize, Debug, Default, Eq, PartialEq, Copy, Clone)]
+pub enum HpBand {
+    #[default]
+    Unknown,
+    Critical,
+    Low,
+    Healthy,
+    Full,
+}
+
+impl HpBand {
+    pub fn from_percent(hp: Option<f32>) -> HpBand {
+        match hp {
+            None => HpBand::Unknown,
+            Some(hp) if hp < 25.0 => HpBand::Critical,
+            Some(hp) if hp < 60.0 => HpBand::Low,
+            Some(hp) if hp < 95.0 => HpBand::Healthy,
+            Some(_) => HpBand::Full,
+        }
+    }
+}
+
+// what every party member publishes about itself, the master reads it to coordinate
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MemberStatus {
+    pub window: usize,
+    // None until the member registers
+    pub class: Option<Class>,
+    pub state: CharState,
+    pub hp: HpBand,
+    pub hp_percent: Option<f32>,
+    // mana, rage... whatever the class uses
+    pub resource_percent: Option<f32>,
+    pub inventory_full: bool,
+    pub buffs: Vec<String>,
+    // finished its business in the current town visit
+    pub town_routine_done: bool,
+    #[serde(skip)]
+    pub last_heartbeat: Instant,
+    #[serde(skip)]
+    pub died_at: Option<Instant>,
+}
+
+impl MemberStatus {
+    fn new(window: usize) -> MemberStatus {
+        MemberStatus {
+            window,
+            class: None,
+            state: CharState::Unknown,
+            hp: HpBand::Unknown,
+            hp_percent: None,
+            resource_percent: None,
+            inventory_full: false,
+            buffs: vec![],
+            town_routine_done: false,
+            last_heartbeat: Instant::now(),
+            died_at: None,
+        }
+    }
+
+    fn set_state(&mut self, state: CharState) {
+        self.died_at = match state {
+            CharState::Dead => self.died_at.or(Some(Instant::now())),
+            _ => None,
+        };
+        self.state = state;
+        self.last_heartbeat = Instant::now();
+    }
+
+    pub fn dead_for(&self) -> Option<Duration> {
+        self.died_at.map(|at| at.elapsed())
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.state != CharState::Dead
+    }
+
+    pub fn is_looting(&self) -> bool {
+        self.state == CharState::Looting
+    }
+
+    // a member that stopped publishing (disabled, stuck or quit) should not hold the party up
+    pub fn is_stale(&self, max_age: Duration) -> bool {
+        self.last_heartbeat.elapsed() > max_age
+    }
+}
+
+impl Display for MemberStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.class {
+            Some(class) => write!(f, "#{} {}", self.window, class),
+            None => write!(f, "#{}", self.window),
+        }
+    }
+}
 
 #[derive(Debug)]
 #[non_exhaustive]
@@ -15,100 +119,164 @@ enum SharedStateMessage {
     GetSkillHastePercent(Sender<f32>),
     GetFrenzyPercent(Sender<f32>),
     GetFullInventory(Sender<bool>),
-    SetFullInventory(bool, Sender<()>),
-    Stop(Sender<()>),
+    SetInventoryFull(usize, bool, Sender<()>),
+    ClearFullInventory(Sender<()>),
+    RegisterMember(usize, Class, Sender<()>),
+    PublishMember(usize, MemberReport, Sender<()>),
+    SetMemberState(usize, CharState, Sender<()>),
+    GetMembers(Sender<Vec<MemberStatus>>),
+    SetTownRoutineDone(usize, bool, Sender<()>),
+    RecordMasterMove(Direction, Sender<()>),
+    ResetMasterTrail(Sender<()>),
+    GetMasterTrail(Sender<MasterTrail>),
 }
 
-#[derive(Debug)]
+// what a member reads about itself on every tick
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberReport {
+    pub state: CharState,
+    pub hp_percent: Option<f32>,
+    pub resource_percent: Option<f32>,
+    pub buffs: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
 struct SharedStateActor {
     skill_haste_buff_applied: bool,
     skill_haste_percent: f32,
     frenzy_buff_applied: bool,
     frenzy_percent: f32,
-    party_has_full_inventory: bool,
-    receiver: Receiver<SharedStateMessage>,
+    members: BTreeMap<usize, MemberStatus>,
+    master_trail: MasterTrail,
 }
 
 impl SharedStateActor {
-    fn new(
-        skill_haste_percent: f32,
-        frenzy_percent: f32,
-        receiver: Receiver<SharedStateMessage>,
-    ) -> SharedStateActor {
+    fn new(skill_haste_percent: f32, frenzy_percent: f32) -> SharedStateActor {
         SharedStateActor {
             skill_haste_buff_applied: false,
             skill_haste_percent,
             frenzy_buff_applied: false,
             frenzy_percent,
-            party_has_full_inventory: false,
-            receiver,
+            members: BTreeMap::new(),
+            master_trail: MasterTrail::default(),
         }
     }
 
-    fn run(mut self) {
-        while let Ok(msg) = self.receiver.recv() {
-            //println!("Received message: {:?}", type_of(&msg));
-            match msg {
-                SetSkillHasteApplied(bool, sender) => {
-                    self.skill_haste_buff_applied = bool;
-                    let _ = sender.send(());
-                }
-                SetFrenzyApplied(bool, sender) => {
-                    self.frenzy_buff_applied = bool;
-                    let _ = sender.send(());
-                }
-                SetFullInventory(bool, sender) => {
-                    self.party_has_full_inventory = bool;
-                    let _ = sender.send(());
-                }
-                GetSkillHasteApplied(sender) => {
-                    let _ = sender.send(self.skill_haste_buff_applied);
-                }
-                GetFrenzyApplied(sender) => {
-                    let _ = sender.send(self.frenzy_buff_applied);
-                }
-                GetSkillHastePercent(sender) => {
-                    let _ = sender.send(self.skill_haste_percent);
-                }
-                GetFrenzyPercent(sender) => {
-                    let _ = sender.send(self.frenzy_percent);
-                }
-                GetFullInventory(sender) => {
-                    let _ = sender.send(self.party_has_full_inventory);
-                }
-                Stop(sender) => {
-                    print!("Shutting down {}", type_of(&self));
-                    let _ = sender.send(());
-                    break;
-                }
+    fn member(&mut self, id: usize) -> &mut MemberStatus {
+        self.members
+            .entry(id)
+            .or_insert_with(|| MemberStatus::new(id))
+    }
+}
+
+impl Actor for SharedStateActor {
+    type Message = SharedStateMessage;
+
+    fn handle(&mut self, message: SharedStateMessage) {
+        match message {
+            SetSkillHasteApplied(bool, sender) => {
+                self.skill_haste_buff_applied = bool;
+                let _ = sender.send(());
+            }
+            SetFrenzyApplied(bool, sender) => {
+                self.frenzy_buff_applied = bool;
+                let _ = sender.send(());
+            }
+            GetSkillHasteApplied(sender) => {
+                let _ = sender.send(self.skill_haste_buff_applied);
+            }
+            GetFrenzyApplied(sender) => {
+                let _ = sender.send(self.frenzy_buff_applied);
+            }
+            GetSkillHastePercent(sender) => {
+                let _ = sender.send(self.skill_haste_percent);
+            }
+            GetFrenzyPercent(sender) => {
+                let _ = sender.send(self.frenzy_percent);
+            }
+            GetFullInventory(sender) => {
+                let _ = sender.send(self.members.values().any(|m| m.inventory_full));
+            }
+            SetInventoryFull(id, full, sender) => {
+                self.member(id).inventory_full = full;
+                let _ = sender.send(());
+            }
+            ClearFullInventory(sender) => {
+                self.members
+                    .values_mut()
+                    .for_each(|m| m.inventory_full = false);
+                let _ = sender.send(());
+            }
+            RegisterMember(id, class, sender) => {
+                self.member(id).class = Some(class);
+                let _ = sender.send(());
+            }
+            PublishMember(id, report, sender) => {
+                let member = self.member(id);
+                member.set_state(report.state);
+                member.hp = HpBand::from_percent(report.hp_percent);
+                member.hp_percent = report.hp_percent;
+                member.resource_percent = report.resource_percent;
+                member.buffs = report.buffs;
+                let _ = sender.send(());
+            }
+            SetMemberState(id, state, sender) => {
+                self.member(id).set_state(state);
+                let _ = sender.send(());
+            }
+            GetMembers(sender) => {
+                let _ = sender.send(self.members.values().cloned().collect());
+            }
+            SetTownRoutineDone(id, done, sender) => {
+                self.member(id).town_routine_done = done;
+                let _ = sender.send(());
+            }
+            RecordMasterMove(direction, sender) => {
+                self.master_trail.moves.push(direction);
+                let _ = sender.send(());
+            }
+            ResetMasterTrail(sender) => {
+                self.master_trail = MasterTrail {
+                    generation: self.master_trail.generation + 1,
+                    moves: vec![],
+                };
+                let _ = sender.send(());
+            }
+            GetMasterTrail(sender) => {
+                let _ = sender.send(self.master_trail.clone());
             }
         }
     }
+
+    fn is_read_only(message: &SharedStateMessage) -> bool {
+        matches!(
+            message,
+            GetSkillHasteApplied(_)
+                | GetFrenzyApplied(_)
+                | GetSkillHastePercent(_)
+                | GetFrenzyPercent(_)
+                | GetFullInventory(_)
+                | GetMembers(_)
+                | GetMasterTrail(_)
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct SharedStateHandle {
-    sender: Sender<SharedStateMessage>,
+    actor: ActorRef<SharedStateActor>,
 }
 
 impl SharedStateHandle {
     pub fn new(skill_haste_percent: f32, frenzy_percent: f32) -> Self {
-        let (tx, rx) = mpsc::channel();
-        let actor = SharedStateActor::new(skill_haste_percent, frenzy_percent, rx);
-        println!("Starting {}", type_of(&actor));
-        thread::spawn(move || {
-            if let Err(e) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| actor.run())) {
-                eprintln!("{}", format!("Actor panicked: {:?}", e).red());
-            }
-        });
-        Self { sender: tx }
+        let actor = SharedStateActor::new(skill_haste_percent, frenzy_percent);
+        Self {
+            actor: ActorRef::spawn(actor, None),
+        }
     }
 
-    fn ask<T>(&self, msg: impl FnOnce(Sender<T>) -> SharedStateMessage) -> T {
-        let (tx, rx) = mpsc::channel();
-        let _ = self.sender.send(msg(tx));
-        rx.recv()
-            .expect("Actor thread died or failed to send reply")
+    fn ask<T: Default>(&self, msg: impl FnOnce(Sender<T>) -> SharedStateMessage) -> T {
+        self.actor.ask_or_default(msg)
     }
 
     pub fn set_skill_haste_applied(&self, state: bool) {
@@ -135,24 +303,90 @@ impl SharedStateHandle {
         self.ask(GetFrenzyPercent)
     }
 
-    pub fn set_full_inventory(&self, state: bool) {
-        self.ask(|tx| SetFullInventory(state, tx));
-    }
-
+    // true when any member has a full inventory
     pub fn get_full_inventory(&self) -> bool {
         self.ask(GetFullInventory)
     }
 
+    // the party coordination relies on the writes from here on, a failed one is not ignored
+    pub fn set_inventory_full(&self, id: usize, full: bool) -> Result<(), ActorError> {
+        self.actor.ask(|tx| SetInventoryFull(id, full, tx))
+    }
+
+    pub fn clear_full_inventory(&self) -> Result<(), ActorError> {
+        self.actor.ask(ClearFullInventory)
+    }
+
+    pub fn register_member(&self, id: usize, class: Class) -> Result<(), ActorError> {
+        self.actor.ask(|tx| RegisterMember(id, class, tx))
+    }
+
+    // the heartbeat of a member
+    pub fn publish_member(&self, id: usize, report: MemberReport) -> Result<(), ActorError> {
+        self.actor.ask(|tx| PublishMember(id, report, tx))
+    }
+
+    pub fn set_member_state(&self, id: usize, state: CharState) -> Result<(), ActorError> {
+        self.actor.ask(|tx| SetMemberState(id, state, tx))
+    }
+
+    // sorted by the window id
+    // an error rather than an empty party, so the coordination can fail closed
+    pub fn get_members(&self) -> Result<Vec<MemberStatus>, ActorError> {
+        self.actor.ask(GetMembers)
+    }
+
+    pub fn get_member_states(&self) -> Result<Vec<(usize, CharState)>, ActorError> {
+        Ok(self
+            .get_members()?
+            .into_iter()
+            .map(|m| (m.window, m.state))
+            .collect())
+    }
+
+    pub fn set_town_routine_done(&self, id: usize, done: bool) -> Result<(), ActorError> {
+        self.actor.ask(|tx| SetTownRoutineDone(id, done, tx))
+    }
+
+    pub fn get_town_routine_done(&self) -> Result<Vec<usize>, ActorError> {
+        Ok(self
+            .get_members()?
+            .into_iter()
+            .filter(|m| m.town_routine_done)
+            .map(|m| m.window)
+            .collect())
+    }
+
+    // the followers walk the same steps
+    pub fn record_master_move(&self, direction: Direction) -> Result<(), ActorError> {
+        self.actor.ask(|tx| RecordMasterMove(direction, tx))
+    }
+
+    pub fn reset_master_trail(&self) -> Result<(), ActorError> {
+        self.actor.ask(ResetMasterTrail)
+    }
+
+    pub fn get_master_trail(&self) -> MasterTrail {
+        self.ask(GetMasterTrail)
+    }
+
+    pub fn metrics(&self) -> ActorMetrics {
+        self.actor.metrics()
+    }
+
     pub fn stop(&self) {
-        let (tx, _) = mpsc::channel();
-        let _ = self.sender.send(Stop(tx));
+        self.actor.stop();
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::simulation::shared_state::SharedStateHandle;
+    use crate::configuration::config::Class;
+    use crate::simulation::char_state::CharState;
+    use crate::simulation::maze_solver::Direction;
+    use crate::simulation::shared_state::{HpBand, MemberReport, SharedStateHandle};
     use std::sync::Arc;
+    use std::time::Duration;
 
     #[test]
     fn test_shared_state_is_singleton() {
@@ -169,4 +403,97 @@ mod tests {
         s1.stop();
         s2.stop();
     }
+
+    #[test]
+    fn test_member_states() {
+        let state = SharedStateHandle::new(1.0, 1.0);
+        state.set_member_state(1, CharState::InTown).unwrap();
+        state.set_member_state(0, CharState::InDungeon).unwrap();
+        state.set_member_state(1, CharState::Fighting).unwrap();
+
+        assert_eq!(
+            state.get_member_states().unwrap(),
+            vec![(0, CharState::InDungeon), (1, CharState::Fighting)]
+        );
+
+        state.stop();
+    }
+
+    #[test]
+    fn test_town_routine_done() {
+        let state = SharedStateHandle::new(1.0, 1.0);
+        state.set_town_routine_done(2, true).unwrap();
+        state.set_town_routine_done(0, true).unwrap();
+        state.set_town_routine_done(1, true).unwrap();
+        state.set_town_routine_done(1, false).unwrap();
+
+        assert_eq!(state.get_town_routine_done(), Ok(vec![0, 2]));
+
+        state.stop();
+    }
+
+    #[test]
+    fn test_master_trail() {
+        let state = SharedStateHandle::new(1.0, 1.0);
+        state.record_master_move(Direction::Up).unwrap();
+        state.record_master_move(Direction::Left).unwrap();
+        assert_eq!(
+            state.get_master_trail().moves,
+            vec![Direction::Up, Direction::Left]
+        );
+
+        state.reset_master_trail().unwrap();
+        let trail = state.get_master_trail();
+        assert_eq!(trail.generation, 1);
+        assert!(trail.moves.is_empty());
+
+        state.stop();
+    }
+
+    #[test]
+    fn test_member_registry() {
+        let state = SharedStateHandle::new(1.0, 1.0);
+        state.register_member(1, Class::Enchanter).unwrap();
+        state
+            .publish_member(
+                1,
+                MemberReport {
+                    state: CharState::Looting,
+                    hp_percent: Some(40.0),
+                    resource_percent: None,
+                    buffs: vec!["Clarity".to_string()],
+                },
+            )
+            .unwrap();
+        state.set_member_state(0, CharState::Dead).unwrap();
+        state.set_inventory_full(1, true).unwrap();
+
+        let members = state.get_members().unwrap();
+        assert_eq!(members.len(), 2);
+        assert!(!members[0].is_alive());
+        assert!(members[0].dead_for().is_some());
+        assert_eq!(members[0].to_string(), "#0");
+        assert_eq!(members[0].class, None);
+        let enchanter = &members[1];
+        assert_eq!(enchanter.class, Some(Class::Enchanter));
+        assert!(enchanter.is_looting());
+        assert_eq!(enchanter.dead_for(), None);
+        assert_eq!(enchanter.to_string(), "#1 Enchanter");
+        assert_eq!(enchanter.hp, HpBand::Low);
+        assert_eq!(enchanter.buffs, vec!["Clarity".to_string()]);
+        assert!(!enchanter.is_stale(Duration::from_secs(5)));
+        assert!(state.get_full_inventory());
+
+        state.clear_full_inventory().unwrap();
+        assert!(!state.get_full_inventory());
+        assert_eq!(HpBand::from_percent(None), HpBand::Unknown);
+        assert_eq!(HpBand::from_percent(Some(100.0)), HpBand::Full);
+
+        state.stop();
+        // a stopped actor is not an empty party
+        assert!(state.get_members().is_err());
+        // and the writes tell that they did not go through
+        assert!(state.set_member_state(0, CharState::InTown).is_err());
+        assert!(state.register_member(2, Class::Warrior).is_err());
+    }
 }
@@ -1,26 +1,53 @@
 use crate::configuration::class_config::LootFilterItem;
-use crate::configuration::config::WindowConfig;
+use crate::configuration::config::{Automation, WindowConfig};
+use crate::frame::Frame;
+use crate::log_error;
+use crate::log_info;
+use crate::log_warn;
+use crate::logger;
+use crate::simulation::behavior::{Flow, StateMachine};
+use crate::simulation::cancellation::CancellationToken;
 use crate::simulation::char_state::CharState;
+use crate::simulation::death::{DeathConfig, Recovery};
+use crate::simulation::follow::Follower;
 use crate::simulation::interactor::Interactor;
+use crate::simulation::inventory::Vendor;
+use crate::simulation::keys::Key;
 use crate::simulation::loot::{LootQuality, LootTier};
 use crate::simulation::maze_solver::{Node, Pos, Solver};
-use crate::simulation::rotation::Rotation;
-use crate::simulation::shared_state::SharedStateHandle;
-use crate::simulation::skill::Skill;
+use crate::simulation::readiness::ReadinessConfig;
+use crate::simulation::rotation::{Rotation, RotationContext};
+use crate::simulation::script::{CharacterScript, Phase, ScriptApi};
+use crate::simulation::shared_state::{MemberReport, SharedStateHandle};
+use crate::simulation::skill::{Skill, ceil_to_two_decimal_places};
+use crate::simulation::skill_target::SkillTarget;
 use crate::simulation::skill_tracker::{DEBUG_COOLDOWNS, SkillTrackerHandle};
 use crate::simulation::state_checker::{StateChecker, get_move_pixel};
+use colored::Colorize;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
-use std::sync::{Arc, Mutex};
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use windows::Win32::Foundation::HWND;
 
+const PARTY_ZONE_IN_TIMEOUT_MS: u64 = 60_000;
+const TOWN_ROUTINE_TIMEOUT_MS: u64 = 120_000;
+// the clicks to town may not register while the death screen fades in
+const REGROUP_RETRY_MS: u64 = 10_000;
+
 pub struct DebugObj {
     pub test_state: CharState,
     pub test_map: Arc<Mutex<HashMap<Pos, Node>>>,
     pub position_x: AtomicI32,
     pub position_y: AtomicI32,
+    // walks that did not follow a connection of the test map
+    pub wall_hits: Arc<AtomicUsize>,
+    // every try_direction, each one takes over a second in the game
+    pub probes: Arc<AtomicUsize>,
+    // decides when a probe for a way that is there does not register, like it sometimes does in the game
+    probe_failures: Mutex<Option<Box<dyn FnMut() -> bool + Send>>>,
 }
 
 impl DebugObj {
@@ -35,80 +62,181 @@ impl DebugObj {
             test_map,
             position_x,
             position_y,
+            wall_hits: Arc::new(AtomicUsize::new(0)),
+            probes: Arc::new(AtomicUsize::new(0)),
+            probe_failures: Mutex::new(None),
         }
     }
+
+    // fighting on an empty map
+    #[cfg(test)]
+    pub fn boxed() -> Box<DebugObj> {
+        Box::new(DebugObj::new(
+            CharState::Fighting,
+            Arc::new(Mutex::new(HashMap::new())),
+            0.into(),
+            0.into(),
+        ))
+    }
+
+    #[cfg(test)]
+    pub fn with_probe_failures(self, fails: impl FnMut() -> bool + Send + 'static) -> DebugObj {
+        *self.probe_failures.lock().unwrap() = Some(Box::new(fails));
+        self
+    }
+
+    pub fn probe_fails(&self) -> bool {
+        self.probe_failures
+            .lock()
+            .unwrap()
+            .as_mut()
+            .is_some_and(|fails| fails())
+    }
 }
 
 pub struct WindowObj {
     pub hwnd: Option<HWND>,
+    // the capture of the current tick, see StateChecker::refresh_frame
+    pub frame: Mutex<Option<Arc<Frame>>>,
+    // the one of the simulation, the waits between the inputs wake up on quitting too
+    pub cancellation: CancellationToken,
 }
 
 unsafe impl Send for WindowObj {}
 unsafe impl Sync for WindowObj {}
 
 impl WindowObj {
-    pub fn new(hwnd: Option<HWND>) -> WindowObj {
-        Self { hwnd }
+    pub fn new(hwnd: Option<HWND>, cancellation: CancellationToken) -> WindowObj {
+        Self {
+            hwnd,
+            frame: Mutex::new(None),
+            cancellation,
+        }
     }
 }
 
 pub struct SimulationState {
+    pub id: usize,
     pub is_running: Arc<AtomicBool>,
+    // wakes up every sleep when quitting
+    pub cancellation: CancellationToken,
     pub is_enabled: Arc<AtomicBool>,
+    // paused between leaving a dungeon and the whole party zoning back in
+    pub is_exploring: Arc<AtomicBool>,
+    // paused by hand, the fighting goes on
+    pub is_exploration_paused: Arc<AtomicBool>,
     pub sync_interval_ms: u64,
     pub cast_leeway_ms: u64,
     pub num_active_characters: usize,
     pub window_config: WindowConfig,
-    pub rotation: Rotation,
-    pub leave_when_full: bool,
-    pub auto_explore: bool,
+    pub rotation: RwLock<Rotation>,
+    pub level: Mutex<Option<u32>>,
+    pub automation: Automation,
+    pub readiness: ReadinessConfig,
+    // since when the master waits for the party before the next step
+    party_not_ready_since: Mutex<Option<Instant>>,
+    pub death: DeathConfig,
+    last_regroup_at: Mutex<Option<Instant>>,
+    follower: Mutex<Follower>,
     pub skill_tracker: SkillTrackerHandle,
-    pub interactor: Box<dyn Interactor + Send + Sync>,
-    pub state_checker: Box<dyn StateChecker + Send + Sync>,
+    pub interactor: Arc<dyn Interactor + Send + Sync>,
+    pub state_checker: Arc<dyn StateChecker + Send + Sync>,
     pub shared_state: Arc<SharedStateHandle>,
     pub maze_solver: Solver,
+    pub script: Option<CharacterScript>,
 }
 
 impl SimulationState {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
+        id: usize,
         sync_interval_ms: u64,
         cast_leeway_ms: u64,
         num_active_characters: usize,
         window_config: WindowConfig,
         rotation: Rotation,
-        leave_when_full: bool,
-        auto_explore: bool,
+        automation: Automation,
+        readiness: ReadinessConfig,
+        death: DeathConfig,
         skill_caster: Box<dyn Interactor + Send + Sync>,
         state_checker: Box<dyn StateChecker + Send + Sync>,
         shared_state: Arc<SharedStateHandle>,
         maze_solver: Solver,
+        cancellation: CancellationToken,
     ) -> Self {
+        if let Err(e) = shared_state.register_member(id, window_config.class_config.class) {
+            log_error!("Couldn't register window {} in the party: {}", id, e);
+        }
         SimulationState {
+            id,
             is_running: Arc::new(AtomicBool::new(false)),
             is_enabled: Arc::new(AtomicBool::new(false)),
+            is_exploring: Arc::new(AtomicBool::new(true)),
+            is_exploration_paused: Arc::new(AtomicBool::new(false)),
             sync_interval_ms,
             cast_leeway_ms,
             num_active_characters,
             window_config: window_config.clone(),
-            rotation,
-            leave_when_full,
-            auto_explore,
-            skill_tracker: SkillTrackerHandle::new(shared_state.clone(), DEBUG_COOLDOWNS),
-            interactor: skill_caster,
-            state_checker,
+            rotation: RwLock::new(rotation),
+            level: Mutex::new(window_config.level),
+            automation,
+            readiness,
+            party_not_ready_since: Mutex::new(None),
+            death,
+            last_regroup_at: Mutex::new(None),
+            follower: Mutex::new(Follower::default()),
+            skill_tracker: SkillTrackerHandle::new(shared_state.clone(), Some(id), DEBUG_COOLDOWNS),
+            interactor: Arc::from(skill_caster),
+            state_checker: Arc::from(state_checker),
             shared_state,
             maze_solver,
+            script: window_config.script.as_ref().and_then(|file_name| {
+                CharacterScript::load_script(file_name, cancellation.clone())
+                    .map_err(|e| {
+                        log_error!(
+                            "{}",
+                            format!("Loading of {} failed: {}", file_name, e).red()
+                        )
+                    })
+                    .ok()
+            }),
+            cancellation,
         }
     }
 
+    // a lone character on debug objects, the tests adjust the pub fields they care about
+    #[cfg(test)]
+    pub fn for_test(
+        id: usize,
+        window_config: WindowConfig,
+        rotation: Rotation,
+        shared_state: Arc<SharedStateHandle>,
+    ) -> Self {
+        SimulationState::new(
+            id,
+            500,
+            0,
+            1,
+            window_config,
+            rotation,
+            Automation::default(),
+            ReadinessConfig::default(),
+            DeathConfig::default(),
+            DebugObj::boxed(),
+            DebugObj::boxed(),
+            shared_state,
+            Solver::new(DebugObj::boxed()),
+            CancellationToken::new(),
+        )
+    }
+
     pub fn debug_checker(&self) {
+        logger::set_window(self.id);
         self.is_running.store(true, Ordering::SeqCst);
-        let is_running = self.is_running.clone();
         let is_enabled = self.is_enabled.clone();
-        while is_running.load(Ordering::SeqCst) {
+        while !self.cancellation.is_cancelled() {
             if !is_enabled.load(Ordering::SeqCst) {
-                thread::sleep(Duration::from_millis(self.sync_interval_ms));
+                self.cancellation.sleep_ms(self.sync_interval_ms);
                 continue;
             }
             let state = self.state_checker.get_state(self.num_active_characters);
@@ -116,7 +244,7 @@ impl SimulationState {
                 _ = self.state_checker.get_loot_quality();
                 _ = self.state_checker.get_loot_tier();
             }
-            thread::sleep(Duration::from_millis(self.sync_interval_ms));
+            self.cancellation.sleep_ms(self.sync_interval_ms);
         }
     }
 
@@ -125,32 +253,35 @@ impl SimulationState {
         self.shared_state.set_skill_haste_applied(false);
         self.shared_state.set_frenzy_applied(false);
         //and non-full inventory -> this should eventually get autocorrected later
-        self.shared_state.set_full_inventory(false);
+        if let Err(e) = self.shared_state.clear_full_inventory() {
+            log_warn!("Couldn't clear the full inventory: {}", e);
+        }
+        self.reset_window();
+    }
+
+    // leaves the party-wide flags alone, the other windows still rely on them
+    pub fn reset_window(&self) {
         self.skill_tracker.reset();
     }
 
     pub fn run(&self) {
+        logger::set_window(self.id);
         self.is_running.store(true, Ordering::SeqCst);
-        let is_running = self.is_running.clone();
         let is_enabled = self.is_enabled.clone();
-        let mut auto_attacking = false;
-        let mut prev_state: CharState = CharState::Unknown;
-        while is_running.load(Ordering::SeqCst) {
+        let mut machine = StateMachine::default();
+        while !self.cancellation.is_cancelled() {
             if !is_enabled.load(Ordering::SeqCst) {
-                thread::sleep(Duration::from_millis(self.sync_interval_ms));
+                self.cancellation.sleep_ms(self.sync_interval_ms);
                 continue;
             }
-            let mut skip_wait = false;
-
+            // one capture for every screen reading of this tick
+            self.state_checker.refresh_frame();
             let state = self.state_checker.get_state(self.num_active_characters);
-            let state_check_at = Instant::now();
-
-            // did we recently die or left town?
-            let need_reset_states = [CharState::InTown, CharState::Dead];
-            if need_reset_states.contains(&prev_state) && !need_reset_states.contains(&state) {
-                self.skill_tracker.reset();
-                self.maze_solver.reset();
-                auto_attacking = false;
+            // let the rest of the party know
+            self.publish_status(state);
+            if self.regroup_after_death(state) {
+                self.cancellation.sleep_ms(self.sync_interval_ms);
+                continue;
             }
 
             // we need to open inventory if it is not opened (it's needed for inventory checks during looting)
@@ -158,106 +289,62 @@ impl SimulationState {
                 self.interactor.inventory_toggle();
             }
 
-            match state {
-                CharState::Unknown | CharState::InTown => {
-                    // do nothing
-                }
-                _ => {
-                    if self.left_combat(prev_state, state) {
-                        //we should track debuffs only during fight, otherwise it would block possible casts
-                        self.skill_tracker.reset_debuffs();
-                    }
-
-                    if state == CharState::InDungeon
-                        && self.state_checker.is_inventory_opened()
-                        && self.state_checker.is_inventory_full()
-                    {
-                        self.shared_state.set_full_inventory(true);
-                    }
-
-                    //leave to town if any of the party has full inventory... no point of farming
-                    if state == CharState::InDungeon
-                        && self.shared_state.get_full_inventory()
-                        //only master can leave dungeon
-                        && self.window_config.master
-                        && self.leave_when_full
-                        && self.interactor.leave_to_town()
-                    {
-                        // let's assume we clear the inventory in a town... so wait and clear shared state
-                        thread::sleep(Duration::from_millis(1000));
-                        self.shared_state.set_full_inventory(false);
-                    }
-
-                    self.loot_shrine(state);
-
-                    if state == CharState::Looting && !self.state_checker.is_inventory_full() {
-                        println!("Initiate looting...");
-                        let mut loot_counter = 0;
-                        loop {
-                            //keep looting until the state changes, or we failed to loot (needs manual intervention)
-                            let looted = self.loot_cycle();
-                            loot_counter += 1;
-                            let new_state =
-                                self.state_checker.get_state(self.num_active_characters);
-                            //let's break if we go over 10 attempts - we might be hung-up because of unknown loot quality check
-                            if !looted
-                                || new_state != CharState::Looting
-                                || self.state_checker.is_inventory_full()
-                                || loot_counter > 10
-                            {
-                                println!("Looting ended");
-                                skip_wait = true;
-                                break;
-                            }
-                        }
-                    }
-
-                    if [CharState::Fighting, CharState::InDungeon].contains(&state) {
-                        if self.entered_combat(prev_state, state) {
-                            //wait if we have just started fighting... otherwise the first cast may not go off
-                            thread::sleep(Duration::from_millis(500));
-                        }
-                        if !auto_attacking && state == CharState::Fighting {
-                            // auto-attack just once
-                            auto_attacking = self
-                                .interactor
-                                .auto_attack(self.window_config.class_config.auto_attack);
-                        }
-
-                        self.do_rotation(state, state_check_at, skip_wait);
-                    }
-
-                    if self.process_movement() {
-                        skip_wait = true;
-                    }
-                }
-            }
-            prev_state = state;
-            if !skip_wait {
-                println!("Sync sleep for {} ms", self.sync_interval_ms);
-                thread::sleep(Duration::from_millis(self.sync_interval_ms));
+            if machine.tick(self, state) == Flow::Sleep {
+                log_info!("Sync sleep for {} ms", self.sync_interval_ms);
+                self.cancellation.sleep_ms(self.sync_interval_ms);
             }
         }
+        if is_enabled.load(Ordering::SeqCst) {
+            self.release_movement();
+        }
+    }
+
+    // auto-walk is a toggle, so a walking master would keep on walking after we quit
+    fn release_movement(&self) {
+        // the cancelled token does not sleep anymore, the two samples would always match
+        if self.window_config.master
+            && self.automation.auto_explore
+            && self.has_moved_during(|| thread::sleep(Duration::from_millis(100)))
+        {
+            log_info!("Stopping auto-walk");
+            self.interactor.walk(None);
+        }
+    }
+
+    // a window script can take over a phase, false means the built-in behavior should run
+    pub fn run_script(&self, phase: Phase, state: CharState) -> bool {
+        self.script
+            .as_ref()
+            .and_then(|script| script.run_phase(phase, ScriptApi::new(self, state)))
+            .unwrap_or(false)
     }
 
-    fn process_movement(&self) -> bool {
+    pub fn process_movement(&self) -> bool {
+        if self.is_following() {
+            return self.follow_step();
+        }
         if self.is_auto_explore_enabled() {
+            if self.window_config.master && (self.has_casualties() || !self.is_party_ready()) {
+                return false;
+            }
             //rotations and looting can take quite some time... lets update the state before moving
             let updated_state = self.state_checker.get_state(self.num_active_characters);
             if self.can_walk(updated_state) {
                 //let's wait a bit in case we have just left the combat or graphics did not load, otherwise the autowalk may not go off
-                thread::sleep(Duration::from_millis(500));
+                self.cancellation.sleep_ms(500);
                 //try to resume walking when in dungeon
                 self.interactor.walk(None);
-                thread::sleep(Duration::from_millis(100));
+                self.cancellation.sleep_ms(100);
                 if self.has_recently_moved() {
                     //let's move until we are stationary
                     loop {
                         if self.is_stationary() {
-                            println!("Just stopped...");
+                            log_info!("Just stopped...");
+                            break;
+                        }
+                        if !self.cancellation.sleep_ms(300) {
                             break;
                         }
-                        thread::sleep(Duration::from_millis(300));
                     }
                 }
             }
@@ -265,22 +352,26 @@ impl SimulationState {
             //rotations and looting can take quite some time... lets update the state before moving
             let updated_state = self.state_checker.get_state(self.num_active_characters);
             if self.can_move_trigger(updated_state) {
-                println!("Trying to auto-explore");
+                log_info!("Trying to auto-explore");
                 // trigger the move step only when stationary
                 let everything_explored = self.maze_solver.explore_step();
+                self.publish_moves();
                 if everything_explored {
-                    println!("Everything explored");
-                    self.interactor.leave_to_town();
+                    log_info!("Everything explored in {} steps", self.maze_solver.steps());
+                    self.export_map();
+                    self.leave_to_town();
                 }
-                thread::sleep(Duration::from_millis(100));
+                self.cancellation.sleep_ms(100);
                 if self.has_recently_moved() {
                     //let's move until we are stationary
                     loop {
                         if self.is_stationary() {
-                            println!("Just stopped...");
+                            log_info!("Just stopped...");
+                            break;
+                        }
+                        if !self.cancellation.sleep_ms(300) {
                             break;
                         }
-                        thread::sleep(Duration::from_millis(300));
                     }
                 }
             }
@@ -289,14 +380,398 @@ impl SimulationState {
         false
     }
 
-    fn loot_shrine(&self, state: CharState) {
+    fn publish_status(&self, state: CharState) {
+        // both come from the frame of this tick, and only if the master is going to check them
+        let (hp_percent, resource_percent) = if self.readiness.needs_vitals(state) {
+            (
+                self.state_checker
+                    .get_party_hp(self.num_active_characters)
+                    .first()
+                    .copied(),
+                self.state_checker
+                    .get_resource_percent(self.num_active_characters),
+            )
+        } else {
+            (None, None)
+        };
+        // same for the buffs, the tracker is not asked unless some buff is required
+        let buffs = if self.readiness.enabled && !self.readiness.required_buffs.is_empty() {
+            let skills = self.rotation.read().unwrap().skills.clone();
+            self.skill_tracker
+                .snapshot(
+                    &skills,
+                    self.window_config.class_config.cd_reductions.as_ref(),
+                )
+                .buffs
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        } else {
+            vec![]
+        };
+        let report = MemberReport {
+            state,
+            hp_percent,
+            resource_percent,
+            buffs,
+        };
+        // until a publish goes through the others go by our last report, then we go stale
+        if let Err(e) = self.shared_state.publish_member(self.id, report) {
+            log_warn!("Couldn't publish the status of window {}: {}", self.id, e);
+        }
+    }
+
+    // for the loops that run longer than a tick, the rest of the report is kept as it was
+    pub fn heartbeat(&self, state: CharState) {
+        if let Err(e) = self.shared_state.set_member_state(self.id, state) {
+            log_warn!("Couldn't publish the state of window {}: {}", self.id, e);
+        }
+    }
+
+    // no exploring with a dead member, no matter how long it takes
+    fn has_casualties(&self) -> bool {
+        let members = match self.shared_state.get_members() {
+            Ok(members) => members,
+            Err(e) => {
+                log_warn!("Exploration paused, the party is unknown: {}", e);
+                return true;
+            }
+        };
+        match DeathConfig::casualties(&members).first() {
+            Some(member) => {
+                log_info!("Exploration paused, {} is dead", member);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // everyone heads to town, dead or alive, the town cycle brings the party back
+    fn regroup_after_death(&self, state: CharState) -> bool {
+        if matches!(state, CharState::InTown | CharState::Unknown)
+            || !self
+                .shared_state
+                .get_members()
+                .is_ok_and(|members| self.death.should_regroup(&members))
+        {
+            return false;
+        }
+        let mut last_regroup_at = self.last_regroup_at.lock().unwrap();
+        if last_regroup_at.is_some_and(|at| at.elapsed() < Duration::from_millis(REGROUP_RETRY_MS))
+        {
+            return true;
+        }
+        *last_regroup_at = Some(Instant::now());
+        log_warn!(
+            "{}",
+            "Party wiped or lost a member, regrouping in town".yellow()
+        );
+        self.leave_to_town();
+        true
+    }
+
+    // out of combat only, bringing somebody back takes a while
+    pub fn try_resurrect(&self) {
+        let (Recovery::Resurrect, Some(name)) = (self.death.recovery, &self.death.resurrect_skill)
+        else {
+            return;
+        };
+        let Some(skill) = self
+            .rotation
+            .read()
+            .unwrap()
+            .skills
+            .iter()
+            .find(|s| &s.name == name)
+            .cloned()
+        else {
+            return;
+        };
+        if !self
+            .shared_state
+            .get_members()
+            .is_ok_and(|members| !DeathConfig::casualties(&members).is_empty())
+            || !self.skill_tracker.should_cast(
+                &skill,
+                self.window_config.class_config.cd_reductions.as_ref(),
+                CharState::InDungeon,
+            )
+        {
+            return;
+        }
+        // the dead show up with an empty HP bar, we are the first one
+        let Some(player_index) = self
+            .state_checker
+            .get_party_hp(self.num_active_characters)
+            .iter()
+            .skip(1)
+            .position(|hp| *hp <= 0.0)
+            .map(|i| i + 1)
+        else {
+            return;
+        };
+        log_info!("Resurrecting player {}", player_index + 1);
+        self.interactor.target_player(player_index);
+        self.cast(&skill);
+        self.skill_tracker.track_cast(
+            &skill,
+            self.window_config.class_config.cd_reductions.as_ref(),
+        );
+        self.interactor.target_player(0);
+    }
+
+    // the master should not walk into the next pack while the party is still busy with the last one
+    fn is_party_ready(&self) -> bool {
+        if !self.readiness.enabled {
+            return true;
+        }
+        let members = match self.shared_state.get_members() {
+            Ok(members) => members,
+            Err(e) => {
+                log_warn!("Waiting, the party is unknown: {}", e);
+                return false;
+            }
+        };
+        let mut since = self.party_not_ready_since.lock().unwrap();
+        let Some(reason) = self.readiness.not_ready_reason(&members) else {
+            *since = None;
+            return true;
+        };
+        if since.get_or_insert_with(Instant::now).elapsed()
+            > Duration::from_millis(self.readiness.timeout_ms)
+        {
+            log_warn!(
+                "{}",
+                format!("Party is still not ready ({}), moving on anyway", reason).yellow()
+            );
+            *since = None;
+            return true;
+        }
+        log_info!("Waiting for the party: {}", reason);
+        false
+    }
+
+    fn publish_moves(&self) {
+        self.maze_solver
+            .last_moves()
+            .into_iter()
+            .try_for_each(|direction| self.shared_state.record_master_move(direction))
+            .unwrap_or_else(|e| log_warn!("Couldn't record the master moves: {}", e));
+    }
+
+    fn is_following(&self) -> bool {
+        !self.window_config.master && self.window_config.follow && !self.is_exploration_paused()
+    }
+
+    // one step of the master's trail at a time, out of combat only
+    fn follow_step(&self) -> bool {
+        if self.state_checker.get_state(self.num_active_characters) != CharState::InDungeon
+            || !self.is_stationary()
+        {
+            return false;
+        }
+        let trail = self.shared_state.get_master_trail();
+        let mut follower = self.follower.lock().unwrap();
+        let Some(direction) = follower.next(&trail) else {
+            return false;
+        };
+        log_info!(
+            "Following the master, {} steps behind",
+            follower.steps_behind(&trail)
+        );
+        self.interactor.walk(Some(direction));
+        self.cancellation.sleep_ms(100);
+        if self.has_recently_moved() {
+            follower.moved();
+            //let's move until we are stationary
+            while !self.is_stationary() {
+                if !self.cancellation.sleep_ms(300) {
+                    break;
+                }
+            }
+        } else if follower.stuck() {
+            log_warn!(
+                "{}",
+                format!(
+                    "Stuck following the master, skipping the step {:?}",
+                    direction
+                )
+                .yellow()
+            );
+        }
+        true
+    }
+
+    pub fn leave_to_town(&self) -> bool {
+        // no exploring until the whole party is back in a dungeon
+        self.is_exploring.store(false, Ordering::SeqCst);
+        self.interactor.leave_to_town()
+    }
+
+    pub fn resume_exploring(&self) {
+        self.is_exploring.store(true, Ordering::SeqCst);
+    }
+
+    pub fn can_run_town_cycle(&self) -> bool {
+        // only master can enter dungeon, the rest of the party follows
+        self.window_config.master && self.automation.auto_town_cycle
+    }
+
+    pub fn town_routine(&self) {
+        log_info!("Starting town routine");
+        // give the town some time to load
+        self.cancellation.sleep_ms(2000);
+        if self.automation.manage_inventory && !self.wait_for_party_town_routines() {
+            log_error!(
+                "{}",
+                "Party did not finish the town routine in time, leaving anyway".red()
+            );
+        }
+        if !self.interactor.go_to_dungeon() {
+            log_error!("Couldn't get back to the dungeon");
+            return;
+        }
+        if self.wait_for_party_in_dungeon() {
+            log_info!("Whole party is in the dungeon");
+        } else {
+            log_error!(
+                "{}",
+                "Party did not zone in in time, resuming exploration anyway".red()
+            );
+        }
+        self.resume_exploring();
+    }
+
+    fn wait_for_party_in_dungeon(&self) -> bool {
+        let started = Instant::now();
+        while Instant::now() - started < Duration::from_millis(PARTY_ZONE_IN_TIMEOUT_MS) {
+            if !self.is_running.load(Ordering::SeqCst) || self.cancellation.is_cancelled() {
+                return false;
+            }
+            // we are stuck in here, so keep publishing our own state
+            let state = self.state_checker.get_state(self.num_active_characters);
+            self.heartbeat(state);
+            let zoned_in = self
+                .shared_state
+                .get_member_states()
+                .unwrap_or_default()
+                .iter()
+                .filter(|(_, s)| ![CharState::InTown, CharState::Unknown].contains(s))
+                .count();
+            if zoned_in >= self.num_active_characters {
+                return true;
+            }
+            self.cancellation.sleep_ms(self.sync_interval_ms);
+        }
+        false
+    }
+
+    // spend the points by the class plan and dismiss the dialog, once per level-up
+    pub fn level_up_routine(&self) {
+        let level = self.level.lock().unwrap().map(|l| l + 1);
+        let plan = &self.window_config.class_config.level_up_plan;
+        for attribute in plan.get_attribute_points() {
+            self.interactor.allocate_attribute(attribute);
+        }
+        let rotation = Rotation::load_rotation(self.window_config.class_config.class);
+        for name in plan.get_skill_ranks(level) {
+            let slot = rotation
+                .skills
+                .iter()
+                .find(|skill| skill.name == name)
+                .and_then(|skill| Key::get_skill_keys().iter().position(|k| *k == skill.key));
+            match slot {
+                Some(slot) => {
+                    self.interactor.allocate_skill_rank(slot);
+                }
+                None => log_error!("{}", format!("Skill {} is not on the hotbar", name).red()),
+            }
+        }
+        if !self.interactor.confirm_level_up() {
+            log_error!("{}", "Couldn't close the level-up dialog".red());
+        }
+    }
+
+    // the dialog is gone, so the level-up went through - pick up newly learned skills
+    pub fn finish_level_up(&self) {
+        let level = {
+            let mut level = self.level.lock().unwrap();
+            *level = level.map(|l| l + 1);
+            *level
+        };
+        match level {
+            Some(level) => log_info!("{}", format!("Reached level {}", level).bright_green()),
+            None => log_info!("{}", "Reached a new level".bright_green()),
+        }
+        let rotation = Rotation::load_rotation(self.window_config.class_config.class);
+        *self.rotation.write().unwrap() = rotation.for_level(level);
+    }
+
+    pub fn is_town_routine_done(&self) -> bool {
+        self.shared_state
+            .get_town_routine_done()
+            .is_ok_and(|done| done.contains(&self.id))
+    }
+
+    // sell, stash and salvage the inventory by the class rules, one vendor at a time
+    pub fn inventory_routine(&self) {
+        log_info!("Managing inventory");
+        if !self.state_checker.is_inventory_opened() {
+            self.interactor.inventory_toggle();
+        }
+        let rules = &self.window_config.class_config.inventory_rules;
+        let items = self.state_checker.get_inventory_items();
+        for vendor in [Vendor::Merchant, Vendor::Bank, Vendor::Blacksmith] {
+            let slots: Vec<usize> = items
+                .iter()
+                .filter(|item| item.get_action(rules).vendor() == Some(vendor))
+                .map(|item| item.slot)
+                .collect();
+            if slots.is_empty() {
+                continue;
+            }
+            if !self.interactor.open_vendor(vendor) || !self.state_checker.is_vendor_opened(vendor)
+            {
+                log_error!("{}", format!("Couldn't open {:?}", vendor).red());
+                continue;
+            }
+            // the remaining items keep their slots, so the slots read up front stay valid
+            for slot in slots {
+                self.interactor.move_inventory_item(slot);
+            }
+            self.interactor.close_windows();
+        }
+    }
+
+    fn wait_for_party_town_routines(&self) -> bool {
+        let started = Instant::now();
+        while Instant::now() - started < Duration::from_millis(TOWN_ROUTINE_TIMEOUT_MS) {
+            if !self.is_running.load(Ordering::SeqCst) || self.cancellation.is_cancelled() {
+                return false;
+            }
+            if self
+                .shared_state
+                .get_town_routine_done()
+                .is_ok_and(|done| done.len() >= self.num_active_characters)
+            {
+                return true;
+            }
+            self.cancellation.sleep_ms(self.sync_interval_ms);
+        }
+        false
+    }
+
+    pub fn loot_shrine(&self, state: CharState) {
         if state == CharState::AtShrine && self.interactor.interact() {
-            println!("Interacted with a shrine");
+            log_info!("Interacted with a shrine");
         }
     }
 
     fn is_auto_explore_enabled(&self) -> bool {
-        self.window_config.master && self.auto_explore
+        self.window_config.master
+            && self.automation.auto_explore
+            && self.is_exploring.load(Ordering::SeqCst)
+            && !self.is_exploration_paused()
     }
 
     fn can_walk(&self, state: CharState) -> bool {
@@ -310,7 +785,7 @@ impl SimulationState {
             && self.is_stationary()
         {
             // let's make really sure we are still in a dungeon and not fighting... this crap is not very reliable and will screw with a maze map
-            thread::sleep(Duration::from_millis(200));
+            self.cancellation.sleep_ms(200);
             let updated_state = self.state_checker.get_state(self.num_active_characters);
             if updated_state == CharState::InDungeon || updated_state == CharState::AtShrine {
                 // let's not forget to loot a shrine if it is there
@@ -322,8 +797,14 @@ impl SimulationState {
     }
 
     fn has_recently_moved(&self) -> bool {
+        self.has_moved_during(|| {
+            self.cancellation.sleep_ms(100);
+        })
+    }
+
+    fn has_moved_during(&self, wait: impl FnOnce()) -> bool {
         let px_before = get_move_pixel(self.window_config.hwnd);
-        thread::sleep(Duration::from_millis(100));
+        wait();
         let px_after = get_move_pixel(self.window_config.hwnd);
         px_after != px_before
     }
@@ -332,22 +813,29 @@ impl SimulationState {
         !self.has_recently_moved()
     }
 
-    fn entered_combat(&self, prev_state: CharState, state: CharState) -> bool {
-        prev_state != CharState::Fighting && state == CharState::Fighting
-    }
+    pub fn do_rotation(&self, state: CharState, state_check_at: Instant, mut skip_wait: bool) {
+        // manual casts, missed key presses or cooldown resets would get our tracker out of sync
+        self.sync_cooldowns();
+        // same for buffs - they can be dispelled, lost on death or overwritten by other members
+        self.sync_buffs(state);
 
-    fn left_combat(&self, prev_state: CharState, state: CharState) -> bool {
-        prev_state == CharState::Fighting && state != CharState::Fighting
-    }
+        let rotation = self.rotation.read().unwrap().clone();
+        // once per rotation, from the same frame as the cooldowns and buffs
+        let context = self.get_rotation_context(&rotation, state);
 
-    fn do_rotation(&self, state: CharState, state_check_at: Instant, mut skip_wait: bool) {
         // try to cast - go through all skills, they are sorted by priority
-        self.rotation.skills.clone().into_iter().for_each(|skill| {
+        rotation.skills.clone().into_iter().for_each(|skill| {
+            // no more key presses once quitting
+            if self.cancellation.is_cancelled() {
+                return;
+            }
             //make sure we did not die inside a long rotation
             let mut updated_state = state;
             if (Instant::now() - state_check_at) > Duration::from_secs(1) {
                 updated_state = self.state_checker.get_state(self.num_active_characters);
             }
+            // a long rotation must not look like a member that stopped publishing
+            self.heartbeat(updated_state);
 
             // we should try to use a potion if on low HP if it is not on a cooldown
             if updated_state == CharState::Fighting
@@ -358,20 +846,50 @@ impl SimulationState {
                 self.skill_tracker.track_hp_pot();
             }
 
+            // same for mana/energy
+            if updated_state == CharState::Fighting
+                && rotation.needs_resource_pot(&context)
+                && !self.skill_tracker.is_resource_pot_on_cooldown()
+            {
+                self.interactor.use_resource_pot();
+                self.skill_tracker.track_resource_pot();
+            }
+
+            // conservation rules, e.g. do not spam expensive skills on low mana
+            if rotation.is_skipped(&skill, &context) {
+                log_info!("Skipping {} because of a rotation rule", skill.name);
+                return;
+            }
+
             // if we can cast (or buff/debuff is down)
             if self.skill_tracker.should_cast(
                 &skill,
                 self.window_config.class_config.cd_reductions.as_ref(),
                 updated_state,
             ) {
-                if let Some(cast_all_skills) = &self.window_config.class_config.cast_all_skills
+                if let Some(SkillTarget::LowestHp(threshold)) = skill.target {
+                    // nobody to heal, let's keep the skill for later
+                    if let Some(player_index) = self.get_lowest_hp_member(threshold) {
+                        log_info!("Targeting {} on player {}", skill.name, player_index + 1);
+                        self.interactor.target_player(player_index);
+                        self.cast(&skill);
+                        self.skill_tracker.track_cast(
+                            &skill,
+                            self.window_config.class_config.cd_reductions.as_ref(),
+                        );
+                        self.restore_target(updated_state);
+                        skip_wait = true;
+                    }
+                } else if let Some(cast_all_skills) =
+                    &self.window_config.class_config.cast_all_skills
                     && cast_all_skills.contains(&skill.name)
                     && self.num_active_characters > 1
                 {
                     // let's buff other players
-                    println!(
+                    log_info!(
                         "Initiating buff sequence for {} in a party of {}",
-                        skill.name, self.num_active_characters
+                        skill.name,
+                        self.num_active_characters
                     );
                     for player_index in 0..self.num_active_characters {
                         self.interactor.target_player(player_index);
@@ -384,8 +902,8 @@ impl SimulationState {
                             );
                         }
                     }
-                    // re-target himself
-                    self.interactor.target_player(0);
+                    self.restore_target(updated_state);
+                    skip_wait = true;
                 } else {
                     // try to cast a single spell
                     self.cast(&skill);
@@ -394,13 +912,82 @@ impl SimulationState {
                         &skill,
                         self.window_config.class_config.cd_reductions.as_ref(),
                     );
+                    skip_wait = true;
                 }
-                skip_wait = true;
             }
         });
     }
 
-    fn loot_cycle(&self) -> bool {
+    // after a party member was targeted, the enemy in a fight, otherwise re-target himself
+    fn restore_target(&self, state: CharState) {
+        if state == CharState::Fighting {
+            self.interactor.target_enemy();
+        } else {
+            self.interactor.target_player(0);
+        }
+    }
+
+    fn get_rotation_context(&self, rotation: &Rotation, state: CharState) -> RotationContext {
+        // let's not read the screen for nothing
+        let resource = if rotation.needs_resource() {
+            self.state_checker
+                .get_resource_percent(self.num_active_characters)
+        } else {
+            None
+        };
+        // enemies and target are only there during a fight
+        let enemies = if state == CharState::Fighting && rotation.needs_enemy_count() {
+            self.state_checker.get_enemy_count()
+        } else {
+            None
+        };
+        let target_hp = if state == CharState::Fighting && rotation.needs_target_hp() {
+            self.state_checker.get_target_hp()
+        } else {
+            None
+        };
+        RotationContext {
+            resource,
+            enemies,
+            target_hp,
+        }
+    }
+
+    fn get_lowest_hp_member(&self, threshold: f32) -> Option<usize> {
+        self.state_checker
+            .get_party_hp(self.num_active_characters)
+            .into_iter()
+            .enumerate()
+            // no point healing the dead
+            .filter(|(_, hp)| *hp > 0.0 && *hp < threshold)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(player_index, _)| player_index)
+    }
+
+    fn sync_cooldowns(&self) {
+        let readings = self.state_checker.get_hotbar_cooldowns();
+        if !readings.is_empty() {
+            self.skill_tracker.sync_cooldowns(
+                &self.rotation.read().unwrap().skills,
+                self.window_config.class_config.cd_reductions.as_ref(),
+                readings,
+            );
+        }
+    }
+
+    fn sync_buffs(&self, state: CharState) {
+        let buffs = self.state_checker.get_buffs();
+        // target debuffs are only visible with a target
+        let debuffs = if state == CharState::Fighting {
+            self.state_checker.get_target_debuffs()
+        } else {
+            vec![]
+        };
+        // empty readings too, old icons must not outlive the tick they were read in
+        self.skill_tracker.sync_buffs(buffs, debuffs);
+    }
+
+    pub fn loot_cycle(&self) -> bool {
         let quality = self.state_checker.get_loot_quality();
         if quality == LootQuality::Unknown {
             //could not figure out quality... cannot loot (needs a manual intervention)
@@ -425,71 +1012,97 @@ impl SimulationState {
         }
     }
 
-    fn ceil_to_two_decimal_places(val: f32) -> f32 {
-        (val * 100.0).ceil() / 100.0
-    }
     fn cast(&self, skill: &Skill) {
         if !self.interactor.cast_skill(skill) {
-            eprintln!("Couldn't cast skill {}", skill.name);
+            log_error!("Couldn't cast skill {}", skill.name);
         }
-        let cast_time = skill.cast_time(
-            self.shared_state.clone(),
-            self.window_config.class_config.class,
-            self.window_config
-                .class_config
-                .cast_time_reductions
-                .as_ref(),
-        );
-        let ms = if cast_time > 0.0 {
-            //let's wait for a cast time duration
-            (Self::ceil_to_two_decimal_places(cast_time) * 1000.0) as u64
-        } else if self
-            .window_config
-            .class_config
-            .no_gcd_skills
-            .clone()
-            .is_some_and(|skills| skills.contains(&skill.name))
-        {
-            //no gcd skill
-            0
-        } else {
-            (skill.get_gcd(
-                self.shared_state.clone(),
-                self.window_config.class_config.class,
-            ) * 1000.0) as u64
-        };
-        thread::sleep(Duration::from_millis(ms + self.cast_leeway_ms));
-        println!(
-            " and it took {}s",
-            Self::ceil_to_two_decimal_places(ms as f32 / 1000.0)
+        let ms = skill.get_wait_ms(self.shared_state.clone(), &self.window_config.class_config);
+        self.cancellation.sleep_ms(ms + self.cast_leeway_ms);
+        log_info!(
+            "{} took {}s",
+            skill.name,
+            ceil_to_two_decimal_places(ms as f32 / 1000.0)
         );
     }
 
     pub fn enable_toggle(&self) {
         let prev = self.is_enabled.fetch_xor(true, Ordering::SeqCst);
-        println!(
+        log_info!(
             "{:?} enabled: {}",
-            self.window_config.class_config.class, !prev
+            self.window_config.class_config.class,
+            !prev
         );
     }
 
+    pub fn set_enabled(&self, enabled: bool) {
+        self.is_enabled.store(enabled, Ordering::SeqCst);
+        log_info!(
+            "{:?} enabled: {}",
+            self.window_config.class_config.class,
+            enabled
+        );
+    }
+
+    // picks up edits of the rotation file without a restart
+    pub fn reload_rotation(&self) -> io::Result<()> {
+        let class = self.window_config.class_config.class;
+        let level = *self.level.lock().unwrap();
+        let rotation = Rotation::read_rotation(class)?.for_level(level);
+        *self.rotation.write().unwrap() = rotation;
+        log_info!("Reloaded {:?} rotation", class);
+        Ok(())
+    }
+
+    pub fn toggle_exploration_pause(&self) {
+        let prev = self.is_exploration_paused.fetch_xor(true, Ordering::SeqCst);
+        log_info!(
+            "{:?} exploration paused: {}",
+            self.window_config.class_config.class,
+            !prev
+        );
+    }
+
+    // what the solver thinks the dungeon looks like, to compare it with the in-game map
+    pub fn export_map(&self) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        match self
+            .maze_solver
+            .snapshot()
+            .save(&format!("{}-{}", self.id, timestamp))
+        {
+            Ok(path) => log_info!("Exported the map to {}.{{txt,json,svg}}", path.display()),
+            Err(e) => log_error!("{}", format!("Map export failed: {}", e).red()),
+        }
+    }
+
+    pub fn is_exploration_paused(&self) -> bool {
+        self.is_exploration_paused.load(Ordering::SeqCst)
+    }
+
     pub fn stop(&self) {
         self.is_running.store(false, Ordering::SeqCst);
-        println!("Stopping {:?} ", self.window_config.class_config.class);
+        self.cancellation.cancel();
+        log_info!("Stopping {:?} ", self.window_config.class_config.class);
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::amtx;
     use crate::configuration::config::{Class, Config};
-    use crate::simulation::char_state::CharState::Fighting;
-    use crate::simulation::maze_solver::Solver;
+    use crate::simulation::behavior::StateMachine;
+    use crate::simulation::cancellation::join_all;
+    use crate::simulation::char_state::CharState;
+    use crate::simulation::recorder::{Action, Recorder, RecordingInteractor};
     use crate::simulation::rotation::Rotation;
     use crate::simulation::shared_state::SharedStateHandle;
     use crate::simulation::simulation_state::{DebugObj, SimulationState};
-    use std::collections::HashMap;
-    use std::sync::Arc;
+    use std::io;
+    use std::sync::atomic::Ordering;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
 
     #[test]
     #[ignore]
@@ -498,39 +1111,112 @@ mod tests {
 
         let rotation = Rotation::load_rotation(Class::Enchanter);
 
-        let simulation = SimulationState::new(
-            cfg.sync_interval_ms,
+        let simulation = SimulationState::for_test(
             0,
-            1,
             cfg.windows.first().unwrap().clone(),
             rotation,
-            false,
-            false,
-            Box::new(DebugObj::new(
-                Fighting,
-                amtx!(HashMap::new()),
-                0.into(),
-                0.into(),
-            )),
-            Box::new(DebugObj::new(
-                Fighting,
-                amtx!(HashMap::new()),
-                0.into(),
-                0.into(),
-            )),
             Arc::new(SharedStateHandle::new(
                 cfg.skill_haste_percent,
                 cfg.frenzy_haste_percent,
             )),
-            Solver::new(Box::new(DebugObj::new(
-                Fighting,
-                amtx!(HashMap::new()),
-                0.into(),
-                0.into(),
-            ))),
         );
 
         simulation.enable_toggle();
         simulation.run();
     }
+
+    #[test]
+    fn test_stop_wakes_up_a_running_simulation() {
+        let cfg = Config::default();
+        let shared_state = Arc::new(SharedStateHandle::new(0.0, 0.0));
+        let mut simulation = SimulationState::for_test(
+            0,
+            cfg.windows.first().unwrap().clone(),
+            Rotation::load_rotation(Class::Enchanter),
+            shared_state.clone(),
+        );
+        // casts would take ages without the cancellation
+        simulation.sync_interval_ms = 10_000;
+        simulation.cast_leeway_ms = 10_000;
+        let simulation = Arc::new(simulation);
+        simulation.enable_toggle();
+        let handle = thread::spawn({
+            let simulation = simulation.clone();
+            move || simulation.run()
+        });
+        thread::sleep(Duration::from_millis(200));
+
+        simulation.stop();
+        assert_eq!(join_all(vec![handle], Duration::from_secs(2)), 0);
+        simulation.skill_tracker.stop();
+        shared_state.stop();
+    }
+
+    #[test]
+    fn test_exploring_resumes_after_regroup() {
+        let cfg = Config::default();
+        let shared_state = Arc::new(SharedStateHandle::new(0.0, 0.0));
+        let mut window = cfg.windows.first().unwrap().clone();
+        window.master = true;
+        let mut simulation = SimulationState::for_test(
+            0,
+            window,
+            Rotation {
+                skills: vec![],
+                rules: vec![],
+            },
+            shared_state.clone(),
+        );
+        simulation.automation.auto_explore = true;
+        let mut machine = StateMachine::default();
+        let exploring = || simulation.is_exploring.load(Ordering::SeqCst);
+
+        machine.tick(&simulation, CharState::InDungeon);
+        assert!(exploring());
+        // the whole party is dead
+        shared_state.set_member_state(0, CharState::Dead).unwrap();
+        assert!(simulation.regroup_after_death(CharState::Dead));
+        assert!(!exploring());
+        shared_state.set_member_state(0, CharState::InTown).unwrap();
+        machine.tick(&simulation, CharState::InTown);
+        machine.tick(&simulation, CharState::InDungeon);
+        assert!(exploring());
+
+        simulation.skill_tracker.stop();
+        shared_state.stop();
+    }
+
+    #[test]
+    fn test_restore_target() {
+        let cfg = Config::default();
+        let recorder = Arc::new(Recorder::new(Box::new(io::sink())));
+        let actions = Arc::new(Mutex::new(vec![]));
+        recorder.subscribe({
+            let actions = actions.clone();
+            move |record| actions.lock().unwrap().push(record.action.clone())
+        });
+        let shared_state = Arc::new(SharedStateHandle::new(0.0, 0.0));
+        let mut simulation = SimulationState::for_test(
+            0,
+            cfg.windows.first().unwrap().clone(),
+            Rotation {
+                skills: vec![],
+                rules: vec![],
+            },
+            shared_state.clone(),
+        );
+        simulation.num_active_characters = 2;
+        simulation.interactor = Arc::new(RecordingInteractor::new(0, DebugObj::boxed(), recorder));
+
+        // a heal in a fight must not leave us without the enemy
+        simulation.restore_target(CharState::Fighting);
+        simulation.restore_target(CharState::InDungeon);
+        assert_eq!(
+            *actions.lock().unwrap(),
+            vec![Action::TargetEnemy, Action::TargetPlayer(0)]
+        );
+
+        simulation.skill_tracker.stop();
+        shared_state.stop();
+    }
 }
@@ -1,12 +1,14 @@
+use crate::configuration::class_config::ClassConfig;
 use crate::configuration::config::Class;
 use crate::simulation::char_state::CharState;
 use crate::simulation::keys::Key;
 use crate::simulation::shared_state::SharedStateHandle;
+use crate::simulation::skill_target::SkillTarget;
 use crate::simulation::skill_type::SkillType;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Skill {
     pub name: String,
     pub key: Key,
@@ -15,6 +17,11 @@ pub struct Skill {
     pub buff_duration: Option<f32>,
     pub debuff_duration: Option<f32>,
     pub skill_type: SkillType,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<SkillTarget>,
+    // the skill is not learned before reaching the level
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_level: Option<u32>,
 }
 const GCD: f32 = 2.5;
 
@@ -30,12 +37,22 @@ impl Skill {
 
     pub fn can_cast(&self, state: CharState) -> bool {
         match state {
-            CharState::InTown | CharState::Dead | CharState::Unknown => false,
-            CharState::InDungeon | CharState::AtShrine => self.skill_type == SkillType::Buff,
+            CharState::InTown | CharState::Dead | CharState::LevelUp | CharState::Unknown => false,
+            CharState::InDungeon | CharState::AtShrine => {
+                self.skill_type == SkillType::Buff || self.skill_type == SkillType::Heal
+            }
             CharState::Fighting | CharState::Looting => true,
         }
     }
 
+    // unknown level means we trust the rotation
+    pub fn is_unlocked(&self, level: Option<u32>) -> bool {
+        match (self.min_level, level) {
+            (Some(min_level), Some(level)) => level >= min_level,
+            _ => true,
+        }
+    }
+
     pub fn cast_time(
         &self,
         shared_state: Arc<SharedStateHandle>,
@@ -50,6 +67,32 @@ impl Skill {
         }
     }
 
+    // how long a key press keeps us busy: the cast time, or a GCD unless the skill is off it
+    pub fn get_wait_ms(
+        &self,
+        shared_state: Arc<SharedStateHandle>,
+        class_config: &ClassConfig,
+    ) -> u64 {
+        let cast_time = self.cast_time(
+            shared_state.clone(),
+            class_config.class,
+            class_config.cast_time_reductions.as_ref(),
+        );
+        if cast_time > 0.0 {
+            //let's wait for a cast time duration
+            (ceil_to_two_decimal_places(cast_time) * 1000.0) as u64
+        } else if class_config
+            .no_gcd_skills
+            .as_ref()
+            .is_some_and(|skills| skills.contains(&self.name))
+        {
+            //no gcd skill
+            0
+        } else {
+            (self.get_gcd(shared_state, class_config.class) * 1000.0) as u64
+        }
+    }
+
     fn calculate_reduction_coef(&self, reductions: Option<&Vec<(String, f32)>>) -> f32 {
         if let Some(reductions) = reductions {
             reductions
@@ -74,13 +117,19 @@ impl Skill {
     }
 }
 
+pub fn ceil_to_two_decimal_places(val: f32) -> f32 {
+    (val * 100.0).ceil() / 100.0
+}
+
 #[cfg(test)]
 mod tests {
     use crate::configuration::class_config::{AutoAttack, ClassConfig};
     use crate::configuration::config::Class;
+    use crate::simulation::char_state::CharState;
     use crate::simulation::keys::{SKILL_BUTTON_1, SKILL_BUTTON_4};
     use crate::simulation::shared_state::SharedStateHandle;
     use crate::simulation::skill::Skill;
+    use crate::simulation::skill_target::SkillTarget;
     use crate::simulation::skill_type::SkillType;
     use std::sync::Arc;
 
@@ -205,6 +254,8 @@ mod tests {
             buff_duration: None,
             debuff_duration: Some(18.0),
             skill_type: SkillType::Debuff,
+            target: None,
+            min_level: None,
         };
 
         let mut class_config = ClassConfig::new(
@@ -214,6 +265,7 @@ mod tests {
             Some(vec![("Engulfing Darkness".to_string(), 49.0)]),
             Some(vec![("Engulfing Darkness".to_string(), 50.0)]),
             vec![],
+            vec![],
             AutoAttack::Primary,
         );
 
@@ -238,6 +290,7 @@ mod tests {
             None,
             None,
             vec![],
+            vec![],
             AutoAttack::Primary,
         );
 
@@ -258,6 +311,18 @@ mod tests {
         shared_state.stop();
     }
 
+    #[test]
+    fn test_skill_target_serde() {
+        let json = r#"{"name":"Mend","key":49,"cast_time":2.0,"cooldown":0.0,"buff_duration":null,"debuff_duration":null,"skill_type":"Heal"}"#;
+        let skill: Skill = serde_json::from_str(json).unwrap();
+        assert_eq!(skill.target, None);
+
+        let json = r#"{"name":"Mend","key":49,"cast_time":2.0,"cooldown":0.0,"buff_duration":null,"debuff_duration":null,"skill_type":"Heal","target":{"LowestHp":60.0}}"#;
+        let skill: Skill = serde_json::from_str(json).unwrap();
+        assert_eq!(skill.target, Some(SkillTarget::LowestHp(60.0)));
+        assert!(skill.can_cast(CharState::InDungeon));
+    }
+
     fn assert(a: f32, b: f32) {
         let epsilon = 1e-5;
         assert!((a - b).abs() < epsilon, "a: {}, b: {}", a, b);
@@ -276,6 +341,8 @@ mod tests {
             buff_duration: None,
             debuff_duration: Some(18.0),
             skill_type: SkillType::Debuff,
+            target: None,
+            min_level: None,
         };
         let state = SharedStateHandle::new(skill_haste, frenzy_haste);
         state.set_skill_haste_applied(enable_haste);
@@ -297,6 +364,8 @@ mod tests {
             buff_duration: None,
             debuff_duration: None,
             skill_type: SkillType::Attack,
+            target: None,
+            min_level: None,
         };
         let state = SharedStateHandle::new(skill_haste, frenzy_haste);
         state.set_skill_haste_applied(enable_haste);
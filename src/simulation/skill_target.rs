@@ -0,0 +1,7 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
+pub enum SkillTarget {
+    // party member with the lowest HP, but only if below the given percentage
+    LowestHp(f32),
+}
@@ -1,24 +1,39 @@
+use crate::log_info;
+use crate::log_warn;
+use crate::simulation::actor::{Actor, ActorMetrics, ActorRef};
 use crate::simulation::char_state::CharState;
+use crate::simulation::keys::Key;
 use crate::simulation::shared_state::SharedStateHandle;
 use crate::simulation::skill::Skill;
 use crate::simulation::skill_tracker::SkillTrackerMessage::*;
 use crate::simulation::skill_type::SkillType;
-use crate::simulation::type_of;
 use colored::Colorize;
+use serde::Serialize;
 use std::collections::HashMap;
 use std::string::ToString;
-use std::sync::mpsc::{Receiver, Sender};
-use std::sync::{Arc, mpsc};
-use std::thread;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
 use std::time::Instant;
 
 const HP_POT_COOLDOWN: f32 = 24.0;
 const HP_POT_KEY: &str = "hp-potion";
+const RESOURCE_POT_COOLDOWN: f32 = 24.0;
+const RESOURCE_POT_KEY: &str = "resource-potion";
 // we want to reapply buffs/debuffs before they drop down
 const BUFF_DURATION_TOLERANCE_SEC: f32 = 3.0;
 const DEBUFF_DURATION_TOLERANCE_SEC: f32 = 1.0;
 pub const DEBUG_COOLDOWNS: bool = false;
 
+// what the tracker believes right now, for the status API and the TUI
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct TrackerSnapshot {
+    // remaining seconds
+    pub cooldowns: Vec<(String, f32)>,
+    // remaining seconds, None when only the buff bar shows it
+    pub buffs: Vec<(String, Option<f32>)>,
+    pub debuffs: Vec<(String, Option<f32>)>,
+}
+
 #[derive(Debug)]
 #[allow(dead_code)]
 #[non_exhaustive]
@@ -31,96 +46,57 @@ enum SkillTrackerMessage {
     HasDebuffApplied(Skill, Sender<bool>),
     TrackHpPot(Sender<()>),
     IsHpPotOnCooldown(Sender<bool>),
+    TrackResourcePot(Sender<()>),
+    IsResourcePotOnCooldown(Sender<bool>),
+    SyncCooldowns(
+        Vec<Skill>,
+        Option<Vec<(String, f32)>>,
+        Vec<(Key, bool)>,
+        Sender<()>,
+    ),
+    SyncBuffs(Vec<(String, bool)>, Vec<(String, bool)>, Sender<()>),
+    Snapshot(
+        Vec<Skill>,
+        Option<Vec<(String, f32)>>,
+        Sender<TrackerSnapshot>,
+    ),
     ResetDebuffs(Sender<()>),
     Reset(Sender<()>),
-    Stop(Sender<()>),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct SkillTrackerActor {
     last_cast: HashMap<String, Instant>,
     buff_tracker: HashMap<String, Instant>,
     debuff_tracker: HashMap<String, Instant>,
+    // what the buff bars showed on the last reading, takes precedence over the timers
+    observed_buffs: HashMap<String, bool>,
+    observed_debuffs: HashMap<String, bool>,
     potion_tracker: HashMap<String, Instant>,
     shared_state: Arc<SharedStateHandle>,
-    receiver: Receiver<SkillTrackerMessage>,
     debug: bool,
 }
 
 impl SkillTrackerActor {
-    pub fn new(
-        shared_state: Arc<SharedStateHandle>,
-        receiver: Receiver<SkillTrackerMessage>,
-        debug: bool,
-    ) -> SkillTrackerActor {
+    pub fn new(shared_state: Arc<SharedStateHandle>, debug: bool) -> SkillTrackerActor {
         SkillTrackerActor {
             last_cast: HashMap::new(),
             buff_tracker: HashMap::new(),
             debuff_tracker: HashMap::new(),
+            observed_buffs: HashMap::new(),
+            observed_debuffs: HashMap::new(),
             potion_tracker: HashMap::new(),
             shared_state,
-            receiver,
             debug,
         }
     }
 
-    fn run(mut self) {
-        while let Ok(msg) = self.receiver.recv() {
-            //println!("Received message: {:?}", type_of(&msg));
-            match msg {
-                TrackCast(skill, reductions, sender) => {
-                    self.track_skill(&skill, reductions.as_ref());
-                    let _ = sender.send(());
-                }
-                IsOnCooldown(skill, reductions, sender) => {
-                    let _ = sender.send(self.is_on_cooldown(&skill, reductions.as_ref()));
-                }
-                CanCast(skill, reductions, state, sender) => {
-                    let _ = sender.send(self.can_cast(&skill, reductions.as_ref(), state));
-                }
-                ShouldCast(skill, reductions, state, sender) => {
-                    let _ = sender.send(self.should_cast(&skill, reductions.as_ref(), state));
-                }
-                HasBuffApplied(skill, sender) => {
-                    let _ = sender.send(self.has_buff_applied(&skill));
-                }
-                HasDebuffApplied(skill, sender) => {
-                    let _ = sender.send(self.has_debuff_applied(&skill));
-                }
-                TrackHpPot(sender) => {
-                    self.track_hp_pot();
-                    let _ = sender.send(());
-                }
-                IsHpPotOnCooldown(sender) => {
-                    let _ = sender.send(self.is_hp_pot_on_cooldown());
-                }
-                Stop(sender) => {
-                    print!("Shutting down {}", type_of(&self));
-                    let _ = sender.send(());
-                    break;
-                }
-                ResetDebuffs(sender) => {
-                    self.debuff_tracker.clear();
-                    let _ = sender.send(());
-                }
-                Reset(sender) => {
-                    println!("Resetting skill tracker");
-                    self.last_cast.clear();
-                    self.buff_tracker.clear();
-                    self.debuff_tracker.clear();
-                    self.potion_tracker.clear();
-                    let _ = sender.send(());
-                }
-            }
-        }
-    }
-
     fn track_skill(&mut self, skill: &Skill, reductions: Option<&Vec<(String, f32)>>) {
         let now = Instant::now();
         if let Some(last_cast) = self.last_cast.get(&skill.name) {
             let diff = now - *last_cast;
             if diff.as_secs_f32() < skill.get_cooldown(reductions) {
-                println!(
+                log_warn!(
                     "{}",
                     format!(
                         "WARN: trying to cast {} which should still be on a cooldown",
@@ -132,7 +108,7 @@ impl SkillTrackerActor {
             }
         }
         if self.debug {
-            println!("Tracking skill: {}", skill.name);
+            log_info!("Tracking skill: {}", skill.name);
         }
         self.last_cast.insert(skill.name.clone(), now);
         match skill.skill_type {
@@ -143,9 +119,12 @@ impl SkillTrackerActor {
                     self.shared_state.set_frenzy_applied(true);
                 }
                 self.buff_tracker.insert(skill.name.clone(), now);
+                // until the next buff bar reading proves otherwise
+                self.observed_buffs.insert(skill.name.clone(), true);
             }
             SkillType::Debuff => {
                 self.debuff_tracker.insert(skill.name.clone(), now);
+                self.observed_debuffs.insert(skill.name.clone(), true);
             }
             _ => {}
         }
@@ -162,10 +141,75 @@ impl SkillTrackerActor {
         }
     }
 
+    // hotbar overlay is the ground truth, our timestamps are just a guess
+    fn sync_cooldowns(
+        &mut self,
+        skills: &[Skill],
+        reductions: Option<&Vec<(String, f32)>>,
+        readings: &[(Key, bool)],
+    ) {
+        // every slot greyed out at once is just a global cooldown, not a real one
+        let gcd_active = !readings.is_empty() && readings.iter().all(|(_, on_cd)| *on_cd);
+        for skill in skills {
+            let Some((_, on_cooldown_in_game)) = readings.iter().find(|(key, _)| *key == skill.key)
+            else {
+                continue;
+            };
+            let on_cooldown_tracked = self.is_on_cooldown(skill, reductions);
+            if on_cooldown_tracked && !*on_cooldown_in_game {
+                // missed key press or a cooldown reset proc
+                log_warn!(
+                    "{}",
+                    format!(
+                        "WARN: {} is tracked on a cooldown, but it is ready in game. Resyncing.",
+                        skill.name
+                    )
+                    .yellow()
+                );
+                self.last_cast.remove(&skill.name);
+            } else if !on_cooldown_tracked
+                && *on_cooldown_in_game
+                && !gcd_active
+                && skill.get_cooldown(reductions) > 0.0
+            {
+                // manual cast - we do not know when exactly, so let's assume just now
+                log_warn!(
+                    "{}",
+                    format!(
+                        "WARN: {} is on a cooldown in game, but it is tracked as ready. Resyncing.",
+                        skill.name
+                    )
+                    .yellow()
+                );
+                self.last_cast.insert(skill.name.clone(), Instant::now());
+            }
+        }
+    }
+
+    // only the latest reading counts, an unreadable bar leaves it to the timers
+    fn sync_buffs(&mut self, buffs: Vec<(String, bool)>, debuffs: Vec<(String, bool)>) {
+        let buffs: HashMap<String, bool> = buffs.into_iter().collect();
+        let debuffs: HashMap<String, bool> = debuffs.into_iter().collect();
+        if self.debug {
+            for (name, applied) in &buffs {
+                if self.observed_buffs.get(name) != Some(applied) {
+                    log_info!("Buff bar shows {}: {}", name, applied);
+                }
+            }
+            for (name, applied) in &debuffs {
+                if self.observed_debuffs.get(name) != Some(applied) {
+                    log_info!("Target debuff bar shows {}: {}", name, applied);
+                }
+            }
+        }
+        self.observed_buffs = buffs;
+        self.observed_debuffs = debuffs;
+    }
+
     fn track_hp_pot(&mut self) {
         let now = Instant::now();
         if self.is_hp_pot_on_cooldown() {
-            println!(
+            log_warn!(
                 "{}",
                 "WARN: trying to use a health potion which should still be on a cooldown".red()
             );
@@ -174,6 +218,19 @@ impl SkillTrackerActor {
         }
     }
 
+    fn track_resource_pot(&mut self) {
+        let now = Instant::now();
+        if self.is_resource_pot_on_cooldown() {
+            log_warn!(
+                "{}",
+                "WARN: trying to use a resource potion which should still be on a cooldown".red()
+            );
+        } else {
+            self.potion_tracker
+                .insert(RESOURCE_POT_KEY.to_string(), now);
+        }
+    }
+
     fn can_cast(
         &self,
         skill: &Skill,
@@ -184,7 +241,7 @@ impl SkillTrackerActor {
         let can_cast = skill.can_cast(state);
         let result = !is_on_cooldown && can_cast;
         if self.debug {
-            println!(
+            log_info!(
                 "Checking ability: {}. Is on cooldown: {}. Can cast: {}. Result: {}.",
                 skill.name,
                 if is_on_cooldown {
@@ -223,10 +280,10 @@ impl SkillTrackerActor {
                         self.shared_state.set_frenzy_applied(false);
                     }
                     if self.debug {
-                        println!("{}", format!("Buff {} expired", skill.name).yellow());
+                        log_info!("{}", format!("Buff {} expired", skill.name).yellow());
                     }
                 } else if self.debug {
-                    println!(
+                    log_info!(
                         "{}",
                         format!("Buff {} is still applied", skill.name).bright_green()
                     );
@@ -237,46 +294,94 @@ impl SkillTrackerActor {
                 let result = !self.has_debuff_applied(skill);
                 if result {
                     if self.debug {
-                        println!("{}", format!("Debuff {} expired", skill.name).yellow());
+                        log_info!("{}", format!("Debuff {} expired", skill.name).yellow());
                     }
                 } else if self.debug {
-                    println!(
+                    log_info!(
                         "{}",
                         format!("Debuff {} is still applied", skill.name).bright_green()
                     );
                 }
                 result
             }
-            SkillType::Attack => true,
+            // heal targets are picked during the rotation
+            SkillType::Attack | SkillType::Heal | SkillType::Interrupt => true,
         };
         self.can_cast(skill, reductions, state) && should_attack
     }
 
     fn has_buff_applied(&self, skill: &Skill) -> bool {
+        // the icon wins over the timer both ways, e.g. dispelled or applied by someone else
+        if let Some(applied) = self.observed_buffs.get(&skill.name) {
+            return *applied;
+        }
         let now = Instant::now();
-        if let Some(last_cast) = self.buff_tracker.get(&skill.name) {
-            let diff = now - *last_cast;
-            if let Some(buff_duration) = skill.buff_duration {
-                diff.as_secs_f32() < (buff_duration - BUFF_DURATION_TOLERANCE_SEC)
-            } else {
-                false
+        match (self.buff_tracker.get(&skill.name), skill.buff_duration) {
+            (Some(last_cast), Some(buff_duration)) => {
+                (now - *last_cast).as_secs_f32() < (buff_duration - BUFF_DURATION_TOLERANCE_SEC)
             }
-        } else {
-            false
+            _ => false,
         }
     }
 
     fn has_debuff_applied(&self, skill: &Skill) -> bool {
+        if let Some(applied) = self.observed_debuffs.get(&skill.name) {
+            return *applied;
+        }
         let now = Instant::now();
-        if let Some(last_cast) = self.debuff_tracker.get(&skill.name) {
-            let diff = now - *last_cast;
-            if let Some(debuff_duration) = skill.debuff_duration {
-                diff.as_secs_f32() < (debuff_duration - DEBUFF_DURATION_TOLERANCE_SEC)
-            } else {
-                false
+        match (self.debuff_tracker.get(&skill.name), skill.debuff_duration) {
+            (Some(last_cast), Some(debuff_duration)) => {
+                (now - *last_cast).as_secs_f32() < (debuff_duration - DEBUFF_DURATION_TOLERANCE_SEC)
             }
-        } else {
-            false
+            _ => false,
+        }
+    }
+
+    fn snapshot(
+        &self,
+        skills: &[Skill],
+        reductions: Option<&Vec<(String, f32)>>,
+    ) -> TrackerSnapshot {
+        let now = Instant::now();
+        let cooldowns = skills
+            .iter()
+            .filter_map(|skill| {
+                let last_cast = self.last_cast.get(&skill.name)?;
+                let remaining = skill.get_cooldown(reductions) - (now - *last_cast).as_secs_f32();
+                (remaining > 0.0).then(|| (skill.name.clone(), remaining))
+            })
+            .collect();
+        let applied = |skill_type: SkillType,
+                       tracker: &HashMap<String, Instant>,
+                       duration: fn(&Skill) -> Option<f32>,
+                       has_applied: &dyn Fn(&Skill) -> bool| {
+            skills
+                .iter()
+                .filter(|skill| skill.skill_type == skill_type && has_applied(skill))
+                .map(|skill| {
+                    let remaining = tracker.get(&skill.name).zip(duration(skill)).map(
+                        |(last_cast, duration)| {
+                            (duration - (now - *last_cast).as_secs_f32()).max(0.0)
+                        },
+                    );
+                    (skill.name.clone(), remaining)
+                })
+                .collect()
+        };
+        TrackerSnapshot {
+            cooldowns,
+            buffs: applied(
+                SkillType::Buff,
+                &self.buff_tracker,
+                |skill| skill.buff_duration,
+                &|skill| self.has_buff_applied(skill),
+            ),
+            debuffs: applied(
+                SkillType::Debuff,
+                &self.debuff_tracker,
+                |skill| skill.debuff_duration,
+                &|skill| self.has_debuff_applied(skill),
+            ),
         }
     }
 
@@ -290,31 +395,121 @@ impl SkillTrackerActor {
             }
         }
     }
+
+    fn is_resource_pot_on_cooldown(&self) -> bool {
+        match self.potion_tracker.get(RESOURCE_POT_KEY) {
+            None => false,
+            Some(last_cast) => {
+                let now = Instant::now();
+                let diff = now - *last_cast;
+                diff.as_secs_f32() < RESOURCE_POT_COOLDOWN
+            }
+        }
+    }
+}
+
+impl Actor for SkillTrackerActor {
+    type Message = SkillTrackerMessage;
+
+    fn handle(&mut self, message: SkillTrackerMessage) {
+        match message {
+            TrackCast(skill, reductions, sender) => {
+                self.track_skill(&skill, reductions.as_ref());
+                let _ = sender.send(());
+            }
+            IsOnCooldown(skill, reductions, sender) => {
+                let _ = sender.send(self.is_on_cooldown(&skill, reductions.as_ref()));
+            }
+            CanCast(skill, reductions, state, sender) => {
+                let _ = sender.send(self.can_cast(&skill, reductions.as_ref(), state));
+            }
+            ShouldCast(skill, reductions, state, sender) => {
+                let _ = sender.send(self.should_cast(&skill, reductions.as_ref(), state));
+            }
+            HasBuffApplied(skill, sender) => {
+                let _ = sender.send(self.has_buff_applied(&skill));
+            }
+            HasDebuffApplied(skill, sender) => {
+                let _ = sender.send(self.has_debuff_applied(&skill));
+            }
+            TrackHpPot(sender) => {
+                self.track_hp_pot();
+                let _ = sender.send(());
+            }
+            IsHpPotOnCooldown(sender) => {
+                let _ = sender.send(self.is_hp_pot_on_cooldown());
+            }
+            TrackResourcePot(sender) => {
+                self.track_resource_pot();
+                let _ = sender.send(());
+            }
+            IsResourcePotOnCooldown(sender) => {
+                let _ = sender.send(self.is_resource_pot_on_cooldown());
+            }
+            SyncCooldowns(skills, reductions, readings, sender) => {
+                self.sync_cooldowns(&skills, reductions.as_ref(), &readings);
+                let _ = sender.send(());
+            }
+            SyncBuffs(buffs, debuffs, sender) => {
+                self.sync_buffs(buffs, debuffs);
+                let _ = sender.send(());
+            }
+            Snapshot(skills, reductions, sender) => {
+                let _ = sender.send(self.snapshot(&skills, reductions.as_ref()));
+            }
+            ResetDebuffs(sender) => {
+                self.debuff_tracker.clear();
+                self.observed_debuffs.clear();
+                let _ = sender.send(());
+            }
+            Reset(sender) => {
+                log_info!("Resetting skill tracker");
+                self.last_cast.clear();
+                self.buff_tracker.clear();
+                self.debuff_tracker.clear();
+                self.observed_buffs.clear();
+                self.observed_debuffs.clear();
+                self.potion_tracker.clear();
+                let _ = sender.send(());
+            }
+        }
+    }
+
+    fn is_read_only(message: &SkillTrackerMessage) -> bool {
+        matches!(
+            message,
+            IsOnCooldown(..)
+                | CanCast(..)
+                | ShouldCast(..)
+                | HasBuffApplied(..)
+                | HasDebuffApplied(..)
+                | IsHpPotOnCooldown(_)
+                | IsResourcePotOnCooldown(_)
+                | Snapshot(..)
+        )
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct SkillTrackerHandle {
-    sender: Sender<SkillTrackerMessage>,
+    actor: ActorRef<SkillTrackerActor>,
 }
 
 impl SkillTrackerHandle {
-    pub fn new(shared_state_handle: Arc<SharedStateHandle>, debug: bool) -> Self {
-        let (tx, rx) = mpsc::channel();
-        let actor = SkillTrackerActor::new(shared_state_handle, rx, debug);
-        println!("Starting {}", type_of(&actor));
-        thread::spawn(move || {
-            if let Err(e) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| actor.run())) {
-                eprintln!("{}", format!("Actor panicked: {:?}", e).red());
-            }
-        });
-        Self { sender: tx }
+    // window tags the log lines of the actor
+    pub fn new(
+        shared_state_handle: Arc<SharedStateHandle>,
+        window: Option<usize>,
+        debug: bool,
+    ) -> Self {
+        let actor = SkillTrackerActor::new(shared_state_handle, debug);
+        Self {
+            actor: ActorRef::spawn(actor, window),
+        }
     }
 
-    fn ask<T>(&self, msg: impl FnOnce(Sender<T>) -> SkillTrackerMessage) -> T {
-        let (tx, rx) = mpsc::channel();
-        let _ = self.sender.send(msg(tx));
-        rx.recv()
-            .expect("Actor thread died or failed to send reply")
+    fn ask<T: Default>(&self, msg: impl FnOnce(Sender<T>) -> SkillTrackerMessage) -> T {
+        self.actor.ask_or_default(msg)
     }
 
     pub fn reset(&self) {
@@ -329,8 +524,17 @@ impl SkillTrackerHandle {
         self.ask(TrackHpPot);
     }
 
+    // an unanswered query keeps us from drinking or casting blindly
     pub fn is_hp_pot_on_cooldown(&self) -> bool {
-        self.ask(IsHpPotOnCooldown)
+        self.actor.ask_or(IsHpPotOnCooldown, true)
+    }
+
+    pub fn track_resource_pot(&self) {
+        self.ask(TrackResourcePot);
+    }
+
+    pub fn is_resource_pot_on_cooldown(&self) -> bool {
+        self.actor.ask_or(IsResourcePotOnCooldown, true)
     }
 
     pub fn should_cast(
@@ -339,28 +543,59 @@ impl SkillTrackerHandle {
         reductions: Option<&Vec<(String, f32)>>,
         state: CharState,
     ) -> bool {
-        self.ask(|tx| ShouldCast(skill.clone(), reductions.cloned(), state, tx))
+        self.actor.ask_or(
+            |tx| ShouldCast(skill.clone(), reductions.cloned(), state, tx),
+            false,
+        )
     }
 
     pub fn track_cast(&self, skill: &Skill, reductions: Option<&Vec<(String, f32)>>) {
         self.ask(|tx| TrackCast(skill.clone(), reductions.cloned(), tx))
     }
 
+    pub fn sync_cooldowns(
+        &self,
+        skills: &[Skill],
+        reductions: Option<&Vec<(String, f32)>>,
+        readings: Vec<(Key, bool)>,
+    ) {
+        self.ask(|tx| SyncCooldowns(skills.to_vec(), reductions.cloned(), readings, tx))
+    }
+
+    pub fn sync_buffs(&self, buffs: Vec<(String, bool)>, debuffs: Vec<(String, bool)>) {
+        self.ask(|tx| SyncBuffs(buffs, debuffs, tx))
+    }
+
+    pub fn snapshot(
+        &self,
+        skills: &[Skill],
+        reductions: Option<&Vec<(String, f32)>>,
+    ) -> TrackerSnapshot {
+        self.ask(|tx| Snapshot(skills.to_vec(), reductions.cloned(), tx))
+    }
+
     #[cfg(test)]
     pub fn is_on_cooldown(&self, skill: &Skill, reductions: Option<&Vec<(String, f32)>>) -> bool {
-        self.ask(|tx| IsOnCooldown(skill.clone(), reductions.cloned(), tx))
+        self.actor.ask_or(
+            |tx| IsOnCooldown(skill.clone(), reductions.cloned(), tx),
+            true,
+        )
+    }
+
+    pub fn metrics(&self) -> ActorMetrics {
+        self.actor.metrics()
     }
 
-    #[cfg(test)]
     pub fn stop(&self) {
-        let (tx, _) = mpsc::channel();
-        let _ = self.sender.send(Stop(tx));
+        self.actor.stop();
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::simulation::keys::SKILL_BUTTON_2;
+    use crate::log_error;
+    use crate::simulation::char_state::CharState;
+    use crate::simulation::keys::{SKILL_BUTTON_1, SKILL_BUTTON_2, SKILL_BUTTON_12};
     use crate::simulation::shared_state::SharedStateHandle;
     use crate::simulation::skill::Skill;
     use crate::simulation::skill_tracker::SkillTrackerHandle;
@@ -371,12 +606,12 @@ mod tests {
     #[test]
     fn test_skill_tracker_is_transient() {
         std::panic::set_hook(Box::new(|panic_info| {
-            println!("{}", format!("Panic occurred: {:?}", panic_info).red());
+            log_error!("{}", format!("Panic occurred: {:?}", panic_info).red());
         }));
 
         let shared_state = Arc::new(SharedStateHandle::new(1.0, 1.0));
-        let h1 = SkillTrackerHandle::new(shared_state.clone(), true);
-        let h2 = SkillTrackerHandle::new(shared_state.clone(), true);
+        let h1 = SkillTrackerHandle::new(shared_state.clone(), None, true);
+        let h2 = SkillTrackerHandle::new(shared_state.clone(), None, true);
         let skill = Skill {
             name: "Color Shift".to_string(),
             key: SKILL_BUTTON_2,
@@ -385,6 +620,8 @@ mod tests {
             buff_duration: None,
             debuff_duration: None,
             skill_type: SkillType::Attack,
+            target: None,
+            min_level: None,
         };
 
         h1.track_cast(&skill, None);
@@ -398,4 +635,111 @@ mod tests {
         h2.stop();
         shared_state.stop();
     }
+
+    #[test]
+    fn test_skill_tracker_sync_cooldowns() {
+        let shared_state = Arc::new(SharedStateHandle::new(1.0, 1.0));
+        let tracker = SkillTrackerHandle::new(shared_state.clone(), None, true);
+        let missed = Skill {
+            name: "Color Shift".to_string(),
+            key: SKILL_BUTTON_2,
+            cast_time: 1.5,
+            cooldown: 30.0,
+            buff_duration: None,
+            debuff_duration: None,
+            skill_type: SkillType::Attack,
+            target: None,
+            min_level: None,
+        };
+        let manual = Skill {
+            name: "Mind Blitz".to_string(),
+            key: SKILL_BUTTON_1,
+            cast_time: 2.5,
+            cooldown: 12.0,
+            buff_duration: None,
+            debuff_duration: None,
+            skill_type: SkillType::Attack,
+            target: None,
+            min_level: None,
+        };
+        let skills = vec![missed.clone(), manual.clone()];
+
+        // we think we cast it, but the key press did not register
+        tracker.track_cast(&missed, None);
+        assert!(tracker.is_on_cooldown(&missed, None));
+
+        // all slots on cooldown is just a GCD, nothing should change
+        tracker.sync_cooldowns(
+            &skills,
+            None,
+            vec![(SKILL_BUTTON_1, true), (SKILL_BUTTON_2, true)],
+        );
+        assert!(tracker.is_on_cooldown(&missed, None));
+        assert!(!tracker.is_on_cooldown(&manual, None));
+
+        tracker.sync_cooldowns(
+            &skills,
+            None,
+            vec![(SKILL_BUTTON_1, true), (SKILL_BUTTON_2, false)],
+        );
+        assert!(!tracker.is_on_cooldown(&missed, None));
+        assert!(tracker.is_on_cooldown(&manual, None));
+        let snapshot = tracker.snapshot(&skills, None);
+        assert_eq!(snapshot.cooldowns.len(), 1);
+        assert_eq!(snapshot.cooldowns[0].0, "Mind Blitz");
+
+        tracker.stop();
+        shared_state.stop();
+    }
+
+    #[test]
+    fn test_skill_tracker_sync_buffs() {
+        let shared_state = Arc::new(SharedStateHandle::new(1.0, 1.0));
+        let tracker = SkillTrackerHandle::new(shared_state.clone(), None, true);
+        let lich_form = Skill {
+            name: "Lich Form".to_string(),
+            key: SKILL_BUTTON_12,
+            cast_time: 3.0,
+            cooldown: 0.0,
+            buff_duration: Some(720.0),
+            debuff_duration: None,
+            skill_type: SkillType::Buff,
+            target: None,
+            min_level: None,
+        };
+
+        // e.g. after zoning we still have it, but we did not cast it
+        tracker.sync_buffs(vec![("Lich Form".to_string(), true)], vec![]);
+        assert!(!tracker.should_cast(&lich_form, None, CharState::InDungeon));
+        assert_eq!(
+            tracker
+                .snapshot(std::slice::from_ref(&lich_form), None)
+                .buffs,
+            vec![("Lich Form".to_string(), None)]
+        );
+
+        // e.g. after death the timer would still claim it is applied
+        tracker.track_cast(&lich_form, None);
+        assert!(!tracker.should_cast(&lich_form, None, CharState::InDungeon));
+        tracker.sync_buffs(vec![("Lich Form".to_string(), false)], vec![]);
+        assert!(tracker.should_cast(&lich_form, None, CharState::InDungeon));
+        // the bar could not be read, back to the timer
+        tracker.sync_buffs(vec![], vec![]);
+        assert!(!tracker.should_cast(&lich_form, None, CharState::InDungeon));
+
+        // still shown after the timer ran out, e.g. refreshed by someone else
+        let short = Skill {
+            name: "Profane Spirit".to_string(),
+            buff_duration: Some(2.0),
+            ..lich_form.clone()
+        };
+        tracker.track_cast(&short, None);
+        tracker.sync_buffs(vec![], vec![]);
+        assert!(tracker.should_cast(&short, None, CharState::InDungeon));
+        tracker.sync_buffs(vec![("Profane Spirit".to_string(), true)], vec![]);
+        assert!(!tracker.should_cast(&short, None, CharState::InDungeon));
+
+        tracker.stop();
+        shared_state.stop();
+    }
 }
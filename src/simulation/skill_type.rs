@@ -5,4 +5,7 @@ pub enum SkillType {
     Attack,
     Buff,
     Debuff,
+    Heal,
+    // cast as soon as ready during a fight, ahead of the other casts of the party
+    Interrupt,
 }
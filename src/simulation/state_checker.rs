@@ -1,11 +1,27 @@
+use crate::frame::Frame;
+use crate::log_error;
+use crate::log_info;
 use crate::simulation::char_state::CharState;
-use crate::simulation::global_lock::CRITICAL_SECTION;
+use crate::simulation::input_scheduler::InputPriority;
+use crate::simulation::inventory::{
+    INVENTORY_COLUMNS, INVENTORY_ROWS, InventoryItem, Vendor, get_inventory_cell,
+    get_inventory_cell_center, get_inventory_cell_size,
+};
+use crate::simulation::keys::{
+    Key, SKILL_BUTTON_1, SKILL_BUTTON_2, SKILL_BUTTON_3, SKILL_BUTTON_4, SKILL_BUTTON_5,
+    SKILL_BUTTON_6, SKILL_BUTTON_7, SKILL_BUTTON_8, SKILL_BUTTON_9, SKILL_BUTTON_10,
+    SKILL_BUTTON_11, SKILL_BUTTON_12,
+};
 use crate::simulation::loot::{LootQuality, LootTier};
 use crate::simulation::simulation_state::{DebugObj, WindowObj};
-use crate::win_util::{PixelColor, debug_screen, focus_window, get_pixel_color_local, scan_line};
-use crate::with_critical_section;
+use crate::win_util::{
+    PixelColor, capture_frame, debug_screen, focus_window, get_pixel_color_local, join_colored,
+    scan_line,
+};
+use crate::with_input;
 use colored::Colorize;
 use std::collections::HashMap;
+use std::sync::Arc;
 use windows::Win32::Foundation::HWND;
 
 const DEBUG_LOCATION_COLOR: bool = false;
@@ -19,24 +35,43 @@ pub trait StateChecker {
     fn is_inventory_full(&self) -> bool;
     fn is_inventory_opened(&self) -> bool;
     fn is_on_low_hp(&self, number_of_players: usize) -> bool;
+    // HP percentage of every party frame, in the same order as the party target keys
+    fn get_party_hp(&self, number_of_players: usize) -> Vec<f32>;
+    // mana/energy percentage of the character (None if the bar could not be read)
+    fn get_resource_percent(&self, number_of_players: usize) -> Option<f32>;
+    // number of enemies engaged in the fight (None if it could not be read)
+    fn get_enemy_count(&self) -> Option<usize>;
+    // HP percentage of the current target (None if it could not be read)
+    fn get_target_hp(&self) -> Option<f32>;
+    // hotbar key -> cooldown overlay shown (empty if the hotbar could not be read)
+    fn get_hotbar_cooldowns(&self) -> Vec<(Key, bool)>;
+    // buff name -> icon shown on the character's buff bar (empty if the bar could not be read)
+    fn get_buffs(&self) -> Vec<(String, bool)>;
+    // debuff name -> icon shown on the current target (empty if the bar could not be read)
+    fn get_target_debuffs(&self) -> Vec<(String, bool)>;
+    // recognized items of an opened inventory (empty if the inventory could not be read)
+    fn get_inventory_items(&self) -> Vec<InventoryItem>;
+    fn is_vendor_opened(&self, vendor: Vendor) -> bool;
+    // the HP, resource, enemy, hotbar and icon readers share one capture until the next refresh
+    fn refresh_frame(&self);
 }
 
 impl StateChecker for DebugObj {
     fn get_state(&self, _: usize) -> CharState {
         let state = self.test_state;
-        println!("State: {:?}", state);
+        log_info!("State: {:?}", state);
         state
     }
 
     fn get_loot_quality(&self) -> LootQuality {
         let quality = LootQuality::Epic;
-        println!("Loot quality: {:?}", quality);
+        log_info!("Loot quality: {:?}", quality);
         quality
     }
 
     fn get_loot_tier(&self) -> LootTier {
         let tier = LootTier::Normal;
-        println!("Loot tier: {:?}", tier);
+        log_info!("Loot tier: {:?}", tier);
         tier
     }
 
@@ -51,13 +86,59 @@ impl StateChecker for DebugObj {
     fn is_on_low_hp(&self, _: usize) -> bool {
         false
     }
+
+    fn get_party_hp(&self, number_of_players: usize) -> Vec<f32> {
+        vec![100.0; number_of_players]
+    }
+
+    fn get_resource_percent(&self, _: usize) -> Option<f32> {
+        Some(100.0)
+    }
+
+    fn get_enemy_count(&self) -> Option<usize> {
+        Some(1)
+    }
+
+    fn get_target_hp(&self) -> Option<f32> {
+        Some(100.0)
+    }
+
+    fn get_hotbar_cooldowns(&self) -> Vec<(Key, bool)> {
+        vec![]
+    }
+
+    fn get_buffs(&self) -> Vec<(String, bool)> {
+        vec![]
+    }
+
+    fn get_target_debuffs(&self) -> Vec<(String, bool)> {
+        vec![]
+    }
+
+    fn get_inventory_items(&self) -> Vec<InventoryItem> {
+        vec![]
+    }
+
+    fn is_vendor_opened(&self, _: Vendor) -> bool {
+        true
+    }
+
+    fn refresh_frame(&self) {}
 }
 
 impl StateChecker for WindowObj {
     fn get_state(&self, number_of_players: usize) -> CharState {
         let mut state = CharState::Unknown;
 
+        // the level-up dialog covers the rest of the screen
         if let Some(s) = check_location(
+            self.hwnd,
+            get_level_up_marker(),
+            CharState::LevelUp,
+            DEBUG_LOCATION_COLOR,
+        ) {
+            state = s;
+        } else if let Some(s) = check_location(
             self.hwnd,
             get_loot_marker(),
             CharState::Looting,
@@ -129,7 +210,7 @@ impl StateChecker for WindowObj {
             state = s;
         }
 
-        println!("State: {}", format!("{:?}", state).cyan());
+        log_info!("State: {}", format!("{:?}", state).cyan());
         state
     }
 
@@ -149,7 +230,7 @@ impl StateChecker for WindowObj {
                 .cloned()
                 .map(|loc| check_line(self.hwnd, loc, true, true));
         }
-        println!("Loot quality: {:?}", quality);
+        log_info!("Loot quality: {:?}", quality);
         quality
     }
 
@@ -172,7 +253,7 @@ impl StateChecker for WindowObj {
             //and flush bmp
             _ = debug_screen(self.hwnd, "loot_tier.bmp");
         }
-        println!("Loot tier: {:?}", tier);
+        log_info!("Loot tier: {:?}", tier);
         tier
     }
 
@@ -185,7 +266,7 @@ impl StateChecker for WindowObj {
         )
         .is_none();
         if result {
-            println!("{}", "Inventory full".red());
+            log_info!("{}", "Inventory full".red());
         }
         result
     }
@@ -209,6 +290,255 @@ impl StateChecker for WindowObj {
         )
         .is_some()
     }
+
+    fn get_party_hp(&self, number_of_players: usize) -> Vec<f32> {
+        match self.shared_frame() {
+            Ok(frame) => {
+                let hp = read_party_hp(&frame, number_of_players);
+                if DEBUG_LOCATION_COLOR {
+                    log_info!("Party HP: {:?}", hp);
+                }
+                hp
+            }
+            Err(e) => {
+                log_error!("Failed to read party HP: {:?}", e);
+                vec![]
+            }
+        }
+    }
+
+    fn get_resource_percent(&self, number_of_players: usize) -> Option<f32> {
+        match self.shared_frame() {
+            Ok(frame) => {
+                let resource = read_bar_percent(&frame, get_resource_bar(number_of_players));
+                if DEBUG_LOCATION_COLOR {
+                    log_info!("Resource: {}%", resource);
+                }
+                Some(resource)
+            }
+            Err(e) => {
+                log_error!("Failed to read the resource bar: {:?}", e);
+                None
+            }
+        }
+    }
+
+    fn get_enemy_count(&self) -> Option<usize> {
+        match self.shared_frame() {
+            Ok(frame) => {
+                let count = read_enemy_count(&frame);
+                if DEBUG_LOCATION_COLOR {
+                    log_info!("Enemies: {}", count);
+                }
+                Some(count)
+            }
+            Err(e) => {
+                log_error!("Failed to read enemies: {:?}", e);
+                None
+            }
+        }
+    }
+
+    fn get_target_hp(&self) -> Option<f32> {
+        match self.shared_frame() {
+            Ok(frame) => {
+                let hp = read_bar_percent(&frame, get_target_hp_bar());
+                if DEBUG_LOCATION_COLOR {
+                    log_info!("Target HP: {}%", hp);
+                }
+                Some(hp)
+            }
+            Err(e) => {
+                log_error!("Failed to read target HP: {:?}", e);
+                None
+            }
+        }
+    }
+
+    fn get_hotbar_cooldowns(&self) -> Vec<(Key, bool)> {
+        match self.shared_frame() {
+            Ok(frame) => read_hotbar_cooldowns(&frame),
+            Err(e) => {
+                log_error!("Failed to read the hotbar: {:?}", e);
+                vec![]
+            }
+        }
+    }
+
+    fn get_buffs(&self) -> Vec<(String, bool)> {
+        match self.shared_frame() {
+            Ok(frame) => read_icons(&frame, get_buff_bar_slots(), get_buff_icons()),
+            Err(e) => {
+                log_error!("Failed to read the buff bar: {:?}", e);
+                vec![]
+            }
+        }
+    }
+
+    fn get_target_debuffs(&self) -> Vec<(String, bool)> {
+        match self.shared_frame() {
+            Ok(frame) => read_icons(&frame, get_target_debuff_bar_slots(), get_debuff_icons()),
+            Err(e) => {
+                log_error!("Failed to read the target debuff bar: {:?}", e);
+                vec![]
+            }
+        }
+    }
+
+    fn get_inventory_items(&self) -> Vec<InventoryItem> {
+        match grab_frame(self.hwnd) {
+            Ok(frame) => {
+                let items = read_inventory(&frame);
+                if DEBUG_LOCATION_COLOR {
+                    log_info!("Inventory: {:?}", items);
+                }
+                items
+            }
+            Err(e) => {
+                log_error!("Failed to read the inventory: {:?}", e);
+                vec![]
+            }
+        }
+    }
+
+    fn is_vendor_opened(&self, vendor: Vendor) -> bool {
+        check_location(
+            self.hwnd,
+            get_vendor_marker(vendor),
+            true,
+            DEBUG_LOCATION_COLOR,
+        )
+        .is_some()
+    }
+
+    fn refresh_frame(&self) {
+        *self.frame.lock().unwrap() = None;
+    }
+}
+
+impl WindowObj {
+    // captured on the first read after a refresh, the next readers get the same screen
+    fn shared_frame(&self) -> windows::core::Result<Arc<Frame>> {
+        let mut frame = self.frame.lock().unwrap();
+        if let Some(frame) = frame.as_ref() {
+            return Ok(frame.clone());
+        }
+        let captured = Arc::new(grab_frame(self.hwnd)?);
+        *frame = Some(captured.clone());
+        Ok(captured)
+    }
+}
+
+// every reference icon is checked against every slot, icons are not kept in a cast order
+fn read_icons(frame: &Frame, slots: Vec<(i32, i32)>, icons: Vec<Icon>) -> Vec<(String, bool)> {
+    icons
+        .into_iter()
+        .map(|icon| {
+            let present = slots.iter().any(|(x, y)| {
+                icon.1.iter().all(|loc| {
+                    let loc = Location(x + loc.0, y + loc.1, loc.2.clone());
+                    check_location_in_frame(frame, loc, true, DEBUG_LOCATION_COLOR).is_some()
+                })
+            });
+            if present && DEBUG_LOCATION_COLOR {
+                log_info!("Found icon: {}", icon.0);
+            }
+            (icon.0, present)
+        })
+        .collect()
+}
+
+// the tier shows in the cell corners like on the loot window, the quality as the cell underline
+fn read_inventory(frame: &Frame) -> Vec<InventoryItem> {
+    let size = get_inventory_cell_size();
+    (0..INVENTORY_COLUMNS * INVENTORY_ROWS)
+        .filter_map(|slot| {
+            let (cx, cy) = get_inventory_cell_center(slot);
+            let empty = Location(cx, cy, get_empty_inventory_cell_colors());
+            if check_location_in_frame(frame, empty, true, DEBUG_LOCATION_COLOR).is_some() {
+                return None;
+            }
+            let (x, y) = get_inventory_cell(slot);
+            let quality = get_quality_colors()
+                .into_iter()
+                .find(|(_, colors)| {
+                    let line = LineLocation(x + 2, x + size - 3, y + size - 2, colors.clone());
+                    check_line_in_frame(frame, line)
+                })
+                .map_or(LootQuality::Unknown, |(quality, _)| quality);
+            let corners = [
+                (x + 1, y + 1),
+                (x + size - 2, y + 1),
+                (x + 1, y + size - 2),
+                (x + size - 2, y + size - 2),
+            ];
+            let tier = get_tier_colors()
+                .into_iter()
+                .find(|(_, colors)| {
+                    corners.iter().any(|(x, y)| {
+                        let loc = Location(*x, *y, colors.clone());
+                        check_location_in_frame(frame, loc, true, DEBUG_LOCATION_COLOR).is_some()
+                    })
+                })
+                .map_or(LootTier::Unknown, |(tier, _)| tier);
+            Some(InventoryItem {
+                slot,
+                quality,
+                tier,
+            })
+        })
+        .collect()
+}
+
+fn read_party_hp(frame: &Frame, number_of_players: usize) -> Vec<f32> {
+    (0..number_of_players)
+        .map(|slot| read_bar_percent(frame, get_party_hp_bar(number_of_players, slot)))
+        .collect()
+}
+
+// bars are filled from the left, the colors of a location are the colors of an empty bar
+fn read_bar_percent(frame: &Frame, location: LineLocation) -> f32 {
+    let line = frame.line(location.0, location.1, location.2);
+    if line.is_empty() {
+        return 0.0;
+    }
+    let filled = line
+        .iter()
+        .take_while(|px| {
+            !location
+                .3
+                .iter()
+                .any(|c| c.is_similar_to(**px, COLOR_DISTANCE_TOLERANCE))
+        })
+        .count();
+    filled as f32 / line.len() as f32 * 100.0
+}
+
+fn read_enemy_count(frame: &Frame) -> usize {
+    get_enemy_markers()
+        .into_iter()
+        .filter(|loc| {
+            check_location_in_frame(frame, loc.clone(), true, DEBUG_LOCATION_COLOR).is_some()
+        })
+        .count()
+}
+
+fn read_hotbar_cooldowns(frame: &Frame) -> Vec<(Key, bool)> {
+    get_hotbar_cooldown_markers()
+        .into_iter()
+        .map(|(key, loc)| {
+            let on_cooldown =
+                check_location_in_frame(frame, loc, true, DEBUG_LOCATION_COLOR).is_some();
+            (key, on_cooldown)
+        })
+        .collect()
+}
+
+fn grab_frame(hwnd: Option<HWND>) -> windows::core::Result<Frame> {
+    with_input!(hwnd, InputPriority::Observe, 0, {
+        _ = focus_window(hwnd).as_bool();
+        capture_frame(hwnd)
+    })
 }
 
 fn check_line(
@@ -217,7 +547,7 @@ fn check_line(
     debug_color: bool,
     debug_bmp: bool,
 ) -> bool {
-    with_critical_section!(0, {
+    with_input!(hwnd, InputPriority::Observe, 0, {
         _ = focus_window(hwnd).as_bool();
         check_line_no_focus(hwnd, location, debug_color, debug_bmp)
     })
@@ -236,18 +566,21 @@ fn check_line_no_focus(
                 .any(|l| l.is_similar_to(*color, COLOR_DISTANCE_TOLERANCE))
         });
         if !found && debug_color {
-            print!("Colors: ");
-            for color in line {
-                color.print();
-                print!(" ");
-            }
-            println!();
+            log_info!("Colors: {}", join_colored(&line));
         }
         return found;
     }
     false
 }
 
+fn check_line_in_frame(frame: &Frame, location: LineLocation) -> bool {
+    let line = frame.line(location.0, location.1, location.2);
+    location.3.iter().any(|color| {
+        line.iter()
+            .any(|l| l.is_similar_to(*color, COLOR_DISTANCE_TOLERANCE))
+    })
+}
+
 fn check_location_no_focus<T>(
     hwnd: Option<HWND>,
     location: Location,
@@ -256,8 +589,28 @@ fn check_location_no_focus<T>(
 ) -> Option<T> {
     if let Ok(color) = get_pixel_color_local(hwnd, location.0, location.1) {
         if debug_color {
-            print!("Color: ");
-            color.println();
+            log_info!("Color: {}", color.colored());
+        }
+        if location
+            .2
+            .iter()
+            .any(|c| c.is_similar_to(color, COLOR_DISTANCE_TOLERANCE))
+        {
+            return Some(result_state);
+        }
+    }
+    None
+}
+
+fn check_location_in_frame<T>(
+    frame: &Frame,
+    location: Location,
+    result_state: T,
+    debug_color: bool,
+) -> Option<T> {
+    if let Some(color) = frame.pixel(location.0, location.1) {
+        if debug_color {
+            log_info!("Color: {}", color.colored());
         }
         if location
             .2
@@ -276,7 +629,7 @@ fn check_location<T>(
     result_state: T,
     debug_color: bool,
 ) -> Option<T> {
-    with_critical_section!(0, {
+    with_input!(hwnd, InputPriority::Observe, 0, {
         _ = focus_window(hwnd).as_bool();
         check_location_no_focus(hwnd, location, result_state, debug_color)
     })
@@ -290,68 +643,90 @@ struct Location(i32, i32, Vec<PixelColor>);
 #[derive(Debug, Eq, PartialEq, Clone, Hash)]
 struct LineLocation(i32, i32, i32, Vec<PixelColor>);
 
+//name, locations relative to the icon slot (and)
+#[derive(Debug, Eq, PartialEq, Clone, Hash)]
+struct Icon(String, Vec<Location>);
+
+impl Icon {
+    fn new(name: &str, center: PixelColor, corner: PixelColor) -> Icon {
+        Icon(
+            name.to_string(),
+            vec![Location(11, 11, vec![center]), Location(3, 3, vec![corner])],
+        )
+    }
+}
+
 fn get_tier_markers() -> HashMap<Location, LootTier> {
     let mut hm: HashMap<Location, LootTier> = HashMap::new();
     // let's take all four corners of the item as any corner could be obscured by item graphics
     let coords = vec![(488, 475), (515, 475), (488, 503), (515, 503)];
     for (x, y) in coords {
-        hm.insert(
-            Location(
-                x,
-                y,
-                vec![
-                    PixelColor(0x3A75EC),
-                    PixelColor(0x0077FF),
-                    PixelColor(0x1D69C9),
-                ],
-            ),
+        for (tier, colors) in get_tier_colors() {
+            hm.insert(Location(x, y, colors), tier);
+        }
+    }
+    hm
+}
+
+// item frame colors, shared by the loot window and the inventory
+fn get_tier_colors() -> Vec<(LootTier, Vec<PixelColor>)> {
+    vec![
+        (
             LootTier::Elite,
-        );
-        hm.insert(
-            Location(
-                x,
-                y,
-                vec![
-                    PixelColor(0x70A1B5),
-                    PixelColor(0x8F94B3),
-                    PixelColor(0x74A1AF),
-                    PixelColor(0x6392A1),
-                    PixelColor(0x74A2B5),
-                    PixelColor(0x838DA2),
-                    PixelColor(0x5DA796),
-                ],
-            ),
+            vec![
+                PixelColor(0x3A75EC),
+                PixelColor(0x0077FF),
+                PixelColor(0x1D69C9),
+            ],
+        ),
+        (
             LootTier::Exceptional,
-        );
-        hm.insert(
-            Location(
-                x,
-                y,
-                vec![
-                    PixelColor(0x131215),
-                    PixelColor(0x1C303A),
-                    PixelColor(0x121719),
-                    PixelColor(0x472637),
-                    PixelColor(0x9F9F9F),
-                    PixelColor(0x103011),
-                ],
-            ),
+            vec![
+                PixelColor(0x70A1B5),
+                PixelColor(0x8F94B3),
+                PixelColor(0x74A1AF),
+                PixelColor(0x6392A1),
+                PixelColor(0x74A2B5),
+                PixelColor(0x838DA2),
+                PixelColor(0x5DA796),
+            ],
+        ),
+        (
             LootTier::Normal,
-        );
-    }
-    hm
+            vec![
+                PixelColor(0x131215),
+                PixelColor(0x1C303A),
+                PixelColor(0x121719),
+                PixelColor(0x472637),
+                PixelColor(0x9F9F9F),
+                PixelColor(0x103011),
+            ],
+        ),
+    ]
 }
 
 fn get_loot_line_locations() -> HashMap<LineLocation, LootQuality> {
-    let mut hm: HashMap<LineLocation, LootQuality> = HashMap::new();
     let x1 = 585;
     let x2 = 722;
     let y = 497;
-    hm.insert(
-        LineLocation(
-            x1,
-            x2,
-            490,
+    get_quality_colors()
+        .into_iter()
+        .map(|(quality, colors)| {
+            let location = match quality {
+                LootQuality::Normal | LootQuality::Socketed => LineLocation(x1, x2, 490, colors),
+                LootQuality::Rune => LineLocation(600, 800, 488, colors),
+                _ => LineLocation(x1, x2, y, colors),
+            };
+            (location, quality)
+        })
+        .collect()
+}
+
+// item name colors, shared by the loot window and the inventory
+fn get_quality_colors() -> Vec<(LootQuality, Vec<PixelColor>)> {
+    vec![
+        (
+            LootQuality::Normal,
             vec![
                 PixelColor(0xFFFFFF),
                 PixelColor(0xFCFCFC),
@@ -360,64 +735,35 @@ fn get_loot_line_locations() -> HashMap<LineLocation, LootQuality> {
                 PixelColor(0xF2F2F2),
             ],
         ),
-        LootQuality::Normal,
-    );
-    hm.insert(
-        LineLocation(
-            x1,
-            x2,
-            490,
+        (
+            LootQuality::Socketed,
             vec![
                 PixelColor(0x706F6F),
                 PixelColor(0x676767),
                 PixelColor(0x686868),
             ],
         ),
-        LootQuality::Socketed,
-    );
-    hm.insert(
-        LineLocation(x1, x2, y, vec![PixelColor(0xD07E22)]),
-        LootQuality::Magic,
-    );
-    hm.insert(
-        LineLocation(x1, x2, y, vec![PixelColor(0x00E4E4)]),
-        LootQuality::Rare,
-    );
-    hm.insert(
-        LineLocation(
-            x1,
-            x2,
-            y,
+        (LootQuality::Magic, vec![PixelColor(0xD07E22)]),
+        (LootQuality::Rare, vec![PixelColor(0x00E4E4)]),
+        (
+            LootQuality::Epic,
             vec![
                 PixelColor(0xE35F9E),
                 PixelColor(0xCB558E),
                 PixelColor(0xC05185),
             ],
         ),
-        LootQuality::Epic,
-    );
-    hm.insert(
-        LineLocation(x1, x2, y, vec![PixelColor(0x00B200)]),
-        LootQuality::Set,
-    );
-    hm.insert(
-        LineLocation(x1, x2, y, vec![PixelColor(0x0158BB)]),
-        LootQuality::Legendary,
-    );
-    hm.insert(
-        LineLocation(
-            600,
-            800,
-            488,
+        (LootQuality::Set, vec![PixelColor(0x00B200)]),
+        (LootQuality::Legendary, vec![PixelColor(0x0158BB)]),
+        (
+            LootQuality::Rune,
             vec![
                 PixelColor(0x0158BB),
                 PixelColor(0x0091CB),
                 PixelColor(0x047099),
             ],
         ),
-        LootQuality::Rune,
-    );
-    hm
+    ]
 }
 
 fn get_town_marker() -> Location {
@@ -459,6 +805,10 @@ fn get_shrine5_marker() -> Location {
     Location(591, 471, vec![PixelColor(0x091E4F), PixelColor(0x042268)])
 }
 
+fn get_level_up_marker() -> Location {
+    Location(640, 142, vec![PixelColor(0xF2C14E), PixelColor(0xE0B244)])
+}
+
 fn get_loot_marker() -> Location {
     Location(446, 507, vec![PixelColor(0x6E969A), PixelColor(0x85D3DB)])
 }
@@ -488,6 +838,58 @@ fn get_low_hp_marker(number_of_players: usize) -> Location {
     )
 }
 
+const PARTY_FRAME_SPACING: i32 = 94;
+const HP_BAR_WIDTH: i32 = 80;
+
+// party frames are centered, the first one (the character itself) is where the dead marker is
+fn get_party_hp_bar(number_of_players: usize, slot: usize) -> LineLocation {
+    let first = get_dead_marker(number_of_players);
+    let x = first.0 + slot as i32 * PARTY_FRAME_SPACING;
+    LineLocation(
+        x,
+        x + HP_BAR_WIDTH - 1,
+        first.1,
+        vec![
+            PixelColor(0x313131),
+            PixelColor(0x414141),
+            PixelColor(0x434343),
+        ],
+    )
+}
+
+// mana/energy bar right below the HP bar of the character's own frame
+fn get_resource_bar(number_of_players: usize) -> LineLocation {
+    let hp_bar = get_party_hp_bar(number_of_players, 0);
+    LineLocation(
+        hp_bar.0,
+        hp_bar.1,
+        hp_bar.2 + 8,
+        vec![PixelColor(0x414141), PixelColor(0x434343)],
+    )
+}
+
+// frame border of every enemy slot, the slots are filled from the left
+fn get_enemy_markers() -> Vec<Location> {
+    (0..6)
+        .map(|i| {
+            Location(
+                352 + i * 96,
+                118,
+                vec![PixelColor(0x2B3A4E), PixelColor(0x324459)],
+            )
+        })
+        .collect()
+}
+
+fn get_target_hp_bar() -> LineLocation {
+    LineLocation(
+        512,
+        767,
+        50,
+        vec![PixelColor(0x414141), PixelColor(0x434343)],
+    )
+}
+
 fn get_inventory_full_marker() -> Location {
     Location(145, 422, vec![PixelColor(0x1B1B1B), PixelColor(0x070707)])
 }
@@ -496,10 +898,313 @@ fn get_inventory_opened_marker() -> Location {
     Location(68, 473, vec![PixelColor(0x455D7D), PixelColor(0x45566C)])
 }
 
+fn get_empty_inventory_cell_colors() -> Vec<PixelColor> {
+    vec![
+        PixelColor(0x0E0E0E),
+        PixelColor(0x111111),
+        PixelColor(0x141414),
+    ]
+}
+
+// the title bar of each town window
+fn get_vendor_marker(vendor: Vendor) -> Location {
+    match vendor {
+        Vendor::Merchant => Location(640, 96, vec![PixelColor(0xC9A45A), PixelColor(0xB8954F)]),
+        Vendor::Bank => Location(640, 96, vec![PixelColor(0x5A8CC9), PixelColor(0x4F7EB8)]),
+        Vendor::Blacksmith => Location(640, 96, vec![PixelColor(0xC96A3A), PixelColor(0xB85E33)]),
+    }
+}
+
+// the cooldown sweep darkens the top left corner of a slot first and leaves it last
+fn get_hotbar_cooldown_markers() -> Vec<(Key, Location)> {
+    let keys = [
+        SKILL_BUTTON_1,
+        SKILL_BUTTON_2,
+        SKILL_BUTTON_3,
+        SKILL_BUTTON_4,
+        SKILL_BUTTON_5,
+        SKILL_BUTTON_6,
+        SKILL_BUTTON_7,
+        SKILL_BUTTON_8,
+        SKILL_BUTTON_9,
+        SKILL_BUTTON_10,
+        SKILL_BUTTON_11,
+        SKILL_BUTTON_12,
+    ];
+    let x = 424;
+    let slot_width = 38;
+    let y = 684;
+    keys.into_iter()
+        .enumerate()
+        .map(|(i, key)| {
+            (
+                key,
+                Location(
+                    x + i as i32 * slot_width,
+                    y,
+                    vec![
+                        PixelColor(0x0D0D0D),
+                        PixelColor(0x151515),
+                        PixelColor(0x1E1E1E),
+                    ],
+                ),
+            )
+        })
+        .collect()
+}
+
+// top left corners of the character's buff icons
+fn get_buff_bar_slots() -> Vec<(i32, i32)> {
+    (0..12).map(|i| (10 + i * 26, 10)).collect()
+}
+
+// top left corners of the target's debuff icons
+fn get_target_debuff_bar_slots() -> Vec<(i32, i32)> {
+    (0..10).map(|i| (512 + i * 26, 62)).collect()
+}
+
+fn get_buff_icons() -> Vec<Icon> {
+    vec![
+        // Enchanter
+        Icon::new("Augmentation", PixelColor(0xE8C25A), PixelColor(0x4B2D12)),
+        Icon::new("Phase Blade", PixelColor(0xF2E6A1), PixelColor(0x5A3E8C)),
+        Icon::new("Clarity", PixelColor(0xF5D9A8), PixelColor(0x8A3C1E)),
+        // Warlock
+        Icon::new("Lich Form", PixelColor(0x9CE07A), PixelColor(0x1E3A14)),
+        Icon::new("Profane Spirit", PixelColor(0x6B2F8E), PixelColor(0x1A0C24)),
+        // Warrior
+        Icon::new("Intrepid Shout", PixelColor(0x2F6FD8), PixelColor(0x14203A)),
+        Icon::new("Frenzy", PixelColor(0x1C28C4), PixelColor(0x0A0E3A)),
+        Icon::new("Bulwark", PixelColor(0xA8A8A8), PixelColor(0x3C3C3C)),
+        // Bard
+        Icon::new(
+            "Militant Cadence",
+            PixelColor(0x3A8AE0),
+            PixelColor(0x22324A),
+        ),
+        Icon::new("Battle Hymn", PixelColor(0x2A4AD0), PixelColor(0x301818)),
+        Icon::new(
+            "Chromatic Sonata",
+            PixelColor(0xD06AE0),
+            PixelColor(0x2A1A3A),
+        ),
+        Icon::new("Melody of Mana", PixelColor(0xE0A040), PixelColor(0x40200A)),
+        Icon::new("Litany of Life", PixelColor(0x60D060), PixelColor(0x123012)),
+    ]
+}
+
+fn get_debuff_icons() -> Vec<Icon> {
+    vec![
+        // Enchanter
+        Icon::new(
+            "Static Suffocation",
+            PixelColor(0xE0E070),
+            PixelColor(0x30301A),
+        ),
+        // Warlock
+        Icon::new(
+            "Engulfing Darkness",
+            PixelColor(0x401040),
+            PixelColor(0x0C040C),
+        ),
+        Icon::new(
+            "Explosive Plaque",
+            PixelColor(0x30B0A0),
+            PixelColor(0x0C2A24),
+        ),
+        // Warrior
+        Icon::new("Rupture", PixelColor(0x1010B0), PixelColor(0x08082A)),
+        // Bard
+        Icon::new(
+            "Righteous Rhapsody",
+            PixelColor(0x80E0F0),
+            PixelColor(0x2A3A40),
+        ),
+        Icon::new(
+            "Subverted Symphony",
+            PixelColor(0xB02080),
+            PixelColor(0x2A0820),
+        ),
+    ]
+}
+
 // used to detect movement
 pub fn get_move_pixel(hwnd: Option<HWND>) -> PixelColor {
-    with_critical_section!(0, {
+    with_input!(hwnd, InputPriority::Observe, 0, {
         _ = focus_window(hwnd).as_bool();
         get_pixel_color_local(hwnd, 54, 50).unwrap()
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::frame::Frame;
+    use crate::simulation::inventory::{
+        InventoryItem, Vendor, get_inventory_cell, get_inventory_cell_size,
+    };
+    use crate::simulation::keys::{SKILL_BUTTON_1, SKILL_BUTTON_3};
+    use crate::simulation::loot::{LootQuality, LootTier};
+    use crate::simulation::state_checker::{
+        check_location_in_frame, get_buff_bar_slots, get_buff_icons, get_enemy_markers,
+        get_hotbar_cooldown_markers, get_party_hp_bar, get_target_hp_bar, get_vendor_marker,
+        read_bar_percent, read_enemy_count, read_hotbar_cooldowns, read_icons, read_inventory,
+        read_party_hp,
+    };
+    use crate::win_util::PixelColor;
+
+    fn load_fixture(name: &str) -> Frame {
+        let path = format!("{}/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        Frame::load_bmp(&path).unwrap_or_else(|e| panic!("Could not load {}: {}", path, e))
+    }
+
+    #[test]
+    fn test_read_hotbar_cooldowns() {
+        let mut frame = Frame::filled(1280, 720, PixelColor(0x8A6F3C));
+        let (_, loc) = get_hotbar_cooldown_markers()
+            .into_iter()
+            .find(|(key, _)| *key == SKILL_BUTTON_3)
+            .unwrap();
+        frame.set_pixel(loc.0, loc.1, PixelColor(0x151515));
+
+        let readings = read_hotbar_cooldowns(&frame);
+        assert_eq!(readings.len(), 12);
+        assert!(readings.contains(&(SKILL_BUTTON_3, true)));
+        assert!(readings.contains(&(SKILL_BUTTON_1, false)));
+        assert_eq!(readings.iter().filter(|(_, on_cd)| *on_cd).count(), 1);
+    }
+
+    #[test]
+    fn test_read_icons() {
+        let mut frame = Frame::filled(1280, 720, PixelColor(0x202020));
+        let lich_form = get_buff_icons()
+            .into_iter()
+            .find(|icon| icon.0 == "Lich Form")
+            .unwrap();
+        // the icon does not have to be in the first slot
+        let (x, y) = get_buff_bar_slots()[2];
+        for loc in &lich_form.1 {
+            frame.set_pixel(x + loc.0, y + loc.1, loc.2[0]);
+        }
+
+        let buffs = read_icons(&frame, get_buff_bar_slots(), get_buff_icons());
+        assert!(buffs.contains(&("Lich Form".to_string(), true)));
+        assert!(buffs.contains(&("Profane Spirit".to_string(), false)));
+        assert_eq!(buffs.iter().filter(|(_, present)| *present).count(), 1);
+    }
+
+    #[test]
+    fn test_read_party_hp() {
+        let mut frame = Frame::filled(1280, 720, PixelColor(0x2020C0));
+        // second member at a quarter of HP
+        let bar = get_party_hp_bar(3, 1);
+        for x in (bar.0 + 20)..=bar.1 {
+            frame.set_pixel(x, bar.2, PixelColor(0x414141));
+        }
+        // third member is dead
+        let bar = get_party_hp_bar(3, 2);
+        for x in bar.0..=bar.1 {
+            frame.set_pixel(x, bar.2, PixelColor(0x313131));
+        }
+
+        let hp = read_party_hp(&frame, 3);
+        assert_eq!(hp, vec![100.0, 25.0, 0.0]);
+    }
+
+    #[test]
+    fn test_read_enemies_and_target_hp() {
+        let mut frame = Frame::filled(1280, 720, PixelColor(0x2020C0));
+        for loc in get_enemy_markers().iter().take(3) {
+            frame.set_pixel(loc.0, loc.1, loc.2[0]);
+        }
+        let bar = get_target_hp_bar();
+        for x in (bar.0 + 64)..=bar.1 {
+            frame.set_pixel(x, bar.2, PixelColor(0x434343));
+        }
+
+        assert_eq!(read_enemy_count(&frame), 3);
+        assert_eq!(read_bar_percent(&frame, bar), 25.0);
+    }
+
+    #[test]
+    fn test_read_inventory() {
+        let mut frame = Frame::filled(1280, 720, PixelColor(0x111111));
+        let size = get_inventory_cell_size();
+        let mut put_item = |slot, quality: PixelColor, tier: Option<PixelColor>| {
+            let (x, y) = get_inventory_cell(slot);
+            for py in y..y + size {
+                for px in x..x + size {
+                    frame.set_pixel(px, py, PixelColor(0x2020C0));
+                }
+            }
+            frame.set_pixel(x + size / 2, y + size - 2, quality);
+            if let Some(tier) = tier {
+                frame.set_pixel(x + size - 2, y + 1, tier);
+            }
+        };
+        put_item(0, PixelColor(0xD07E22), Some(PixelColor(0x0077FF)));
+        put_item(9, PixelColor(0x00E4E4), None);
+
+        let items = read_inventory(&frame);
+        assert_eq!(
+            items,
+            vec![
+                InventoryItem {
+                    slot: 0,
+                    quality: LootQuality::Magic,
+                    tier: LootTier::Elite,
+                },
+                InventoryItem {
+                    slot: 9,
+                    quality: LootQuality::Rare,
+                    tier: LootTier::Unknown,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_inventory_fixture() {
+        // cropped to the top left corner of the window, the coordinates are the same
+        let frame = load_fixture("inventory.bmp");
+        let items = read_inventory(&frame);
+        assert_eq!(
+            items,
+            vec![
+                InventoryItem {
+                    slot: 0,
+                    quality: LootQuality::Magic,
+                    tier: LootTier::Elite,
+                },
+                InventoryItem {
+                    slot: 3,
+                    quality: LootQuality::Epic,
+                    tier: LootTier::Exceptional,
+                },
+                InventoryItem {
+                    slot: 12,
+                    quality: LootQuality::Normal,
+                    tier: LootTier::Normal,
+                },
+                InventoryItem {
+                    slot: 21,
+                    quality: LootQuality::Set,
+                    tier: LootTier::Unknown,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_vendor_opened_fixture() {
+        let is_opened = |frame: &Frame, vendor| {
+            check_location_in_frame(frame, get_vendor_marker(vendor), true, false).is_some()
+        };
+        let merchant = load_fixture("merchant.bmp");
+        assert!(is_opened(&merchant, Vendor::Merchant));
+        assert!(!is_opened(&merchant, Vendor::Bank));
+        assert!(!is_opened(&merchant, Vendor::Blacksmith));
+        // no town window in the inventory screenshot
+        let inventory = load_fixture("inventory.bmp");
+        assert!(!is_opened(&inventory, Vendor::Merchant));
+    }
+}
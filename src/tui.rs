@@ -0,0 +1,368 @@
+use crate::events::{EVENTS, Event};
+use crate::log_error;
+use crate::logger::Level;
+use crate::simulation::char_state::CharState;
+use crate::simulation::recorder::Action;
+use crate::simulation::shared_state::SharedStateHandle;
+use crate::simulation::simulation_state::SimulationState;
+use ratatui::Frame;
+use ratatui::crossterm::event;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Gauge, Paragraph};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+const REFRESH_MS: u64 = 250;
+const MAX_WARNINGS: usize = 5;
+
+// what the event stream told us about a character
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Panel {
+    pub action: Option<String>,
+    pub looted: u32,
+    pub discarded: u32,
+    pub warnings: VecDeque<String>,
+}
+
+fn push_warning(warnings: &mut VecDeque<String>, message: &str) {
+    if warnings.len() == MAX_WARNINGS {
+        warnings.pop_front();
+    }
+    warnings.push_back(message.to_string());
+}
+
+#[derive(Debug, Default)]
+pub struct Model {
+    pub panels: HashMap<usize, Panel>,
+    // warnings of the shared actors
+    pub warnings: VecDeque<String>,
+}
+
+impl Model {
+    pub fn apply(&mut self, event: &Event) {
+        match event {
+            Event::Action(record) => {
+                let panel = self.panels.entry(record.window).or_default();
+                match &record.action {
+                    Action::Loot if record.result => panel.looted += 1,
+                    Action::Discard if record.result => panel.discarded += 1,
+                    _ => {}
+                }
+                panel.action = Some(match &record.action {
+                    Action::CastSkill(skill) => format!("Casting {}", skill.name),
+                    action => format!("{:?}", action),
+                });
+            }
+            Event::Log {
+                window,
+                level: Level::Warn | Level::Error,
+                message,
+            } => match window {
+                Some(window) => push_warning(
+                    &mut self.panels.entry(*window).or_default().warnings,
+                    message,
+                ),
+                None => push_warning(&mut self.warnings, message),
+            },
+            _ => {}
+        }
+    }
+}
+
+// raw mode swallows Ctrl+C, so the TUI has to quit on it itself
+fn is_quit(key: &KeyEvent) -> bool {
+    key.kind == KeyEventKind::Press
+        && match key.code {
+            KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+            KeyCode::Char('q') => true,
+            _ => false,
+        }
+}
+
+pub struct Tui {
+    running: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl Tui {
+    // takes over the terminal until stopped
+    pub fn start(
+        simulations: Vec<Arc<SimulationState>>,
+        shared_state: Arc<SharedStateHandle>,
+        on_quit: Box<dyn Fn() + Send + Sync>,
+    ) -> Tui {
+        let running = Arc::new(AtomicBool::new(true));
+        let model = Arc::new(Mutex::new(Model::default()));
+        let events = EVENTS.subscribe();
+        thread::spawn({
+            let model = model.clone();
+            move || listen(events, model)
+        });
+        let handle = thread::spawn({
+            let running = running.clone();
+            move || {
+                let mut terminal = ratatui::init();
+                while running.load(Ordering::SeqCst) {
+                    let model = model.lock().unwrap();
+                    if let Err(e) =
+                        terminal.draw(|frame| draw(frame, &simulations, &shared_state, &model))
+                    {
+                        log_error!("TUI failed: {}", e);
+                        break;
+                    }
+                    drop(model);
+                    // waits for a key at most until the next refresh
+                    if event::poll(Duration::from_millis(REFRESH_MS)).unwrap_or(false)
+                        && let Ok(event::Event::Key(key)) = event::read()
+                        && is_quit(&key)
+                    {
+                        on_quit();
+                    }
+                }
+                ratatui::restore();
+            }
+        });
+        Tui { running, handle }
+    }
+
+    pub fn stop(self) {
+        self.running.store(false, Ordering::SeqCst);
+        let _ = self.handle.join();
+    }
+}
+
+fn listen(events: Receiver<Event>, model: Arc<Mutex<Model>>) {
+    for event in events {
+        model.lock().unwrap().apply(&event);
+    }
+}
+
+fn state_color(state: CharState) -> Color {
+    match state {
+        CharState::Fighting => Color::Red,
+        CharState::Looting | CharState::AtShrine => Color::Yellow,
+        CharState::Dead => Color::DarkGray,
+        CharState::InTown | CharState::LevelUp => Color::Cyan,
+        CharState::InDungeon => Color::Green,
+        CharState::Unknown => Color::Gray,
+    }
+}
+
+fn format_timers(timers: &[(String, Option<f32>)]) -> String {
+    if timers.is_empty() {
+        return "-".to_string();
+    }
+    timers
+        .iter()
+        .map(|(name, remaining)| match remaining {
+            Some(remaining) => format!("{} {:.0}s", name, remaining),
+            None => name.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn draw(
+    frame: &mut Frame,
+    simulations: &[Arc<SimulationState>],
+    shared_state: &SharedStateHandle,
+    model: &Model,
+) {
+    let [panels, footer] = Layout::vertical([
+        Constraint::Min(0),
+        Constraint::Length(MAX_WARNINGS as u16 + 2),
+    ])
+    .areas(frame.area());
+    let states: HashMap<usize, CharState> = shared_state
+        .get_member_states()
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let columns = Layout::horizontal(
+        simulations
+            .iter()
+            .map(|_| Constraint::Ratio(1, simulations.len() as u32)),
+    )
+    .split(panels);
+    for (sim, area) in simulations.iter().zip(columns.iter()) {
+        let state = states.get(&sim.id).copied().unwrap_or(CharState::Unknown);
+        let panel = model.panels.get(&sim.id).cloned().unwrap_or_default();
+        draw_panel(frame, *area, sim, state, &panel);
+    }
+
+    let members = shared_state.get_members().unwrap_or_default();
+    let mut lines = vec![Line::from(format!(
+        "Skill haste: {}  Frenzy: {}  Full inventory: {}  Alive: {}/{}",
+        shared_state.get_skill_haste_applied(),
+        shared_state.get_frenzy_applied(),
+        shared_state.get_full_inventory(),
+        members.iter().filter(|m| m.is_alive()).count(),
+        members.len()
+    ))];
+    lines.extend(model.warnings.iter().map(|w| Line::from(w.as_str())));
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title("Party")),
+        footer,
+    );
+}
+
+fn draw_panel(
+    frame: &mut Frame,
+    area: Rect,
+    sim: &SimulationState,
+    state: CharState,
+    panel: &Panel,
+) {
+    let class_config = &sim.window_config.class_config;
+    let enabled = sim.is_enabled.load(Ordering::SeqCst);
+    let block = Block::bordered()
+        .title(format!("#{} {:?}", sim.id, class_config.class))
+        .border_style(Style::default().fg(if enabled {
+            Color::Green
+        } else {
+            Color::DarkGray
+        }));
+    let inner = block.inner(area);
+    frame.render_widget(block, area);
+
+    let skills = sim.rotation.read().unwrap().skills.clone();
+    let snapshot = sim
+        .skill_tracker
+        .snapshot(&skills, class_config.cd_reductions.as_ref());
+
+    let [info, cooldowns, rest] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Length(snapshot.cooldowns.len() as u16),
+        Constraint::Min(0),
+    ])
+    .areas(inner);
+
+    let info_lines = vec![
+        Line::styled(
+            format!("{:?}", state),
+            Style::default().fg(state_color(state)),
+        ),
+        Line::from(if enabled { "enabled" } else { "disabled" }),
+        Line::from(format!(
+            "Action: {}",
+            panel.action.as_deref().unwrap_or("-")
+        )),
+        Line::from(format!(
+            "Looted: {}  Discarded: {}",
+            panel.looted, panel.discarded
+        )),
+    ];
+    frame.render_widget(Paragraph::new(info_lines), info);
+
+    let rows =
+        Layout::vertical(snapshot.cooldowns.iter().map(|_| Constraint::Length(1))).split(cooldowns);
+    for ((name, remaining), row) in snapshot.cooldowns.iter().zip(rows.iter()) {
+        let cooldown = skills
+            .iter()
+            .find(|skill| skill.name == *name)
+            .map_or(*remaining, |skill| {
+                skill.get_cooldown(class_config.cd_reductions.as_ref())
+            });
+        let ratio = (remaining / cooldown.max(f32::EPSILON)).clamp(0.0, 1.0) as f64;
+        frame.render_widget(
+            Gauge::default()
+                .gauge_style(Style::default().fg(Color::Blue))
+                .ratio(ratio)
+                .label(format!("{} {:.1}s", name, remaining)),
+            *row,
+        );
+    }
+
+    let mut lines = vec![
+        Line::from(format!("Buffs: {}", format_timers(&snapshot.buffs))),
+        Line::from(format!("Debuffs: {}", format_timers(&snapshot.debuffs))),
+    ];
+    lines.extend(
+        panel
+            .warnings
+            .iter()
+            .map(|w| Line::styled(w.as_str(), Style::default().fg(Color::Yellow))),
+    );
+    frame.render_widget(Paragraph::new(lines), rest);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::events::Event;
+    use crate::logger::Level;
+    use crate::simulation::recorder::{Action, RecordedAction};
+    use crate::tui::{MAX_WARNINGS, Model, is_quit};
+    use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+
+    fn action(window: usize, action: Action, result: bool) -> Event {
+        Event::Action(RecordedAction {
+            window,
+            timestamp_ms: 0,
+            action,
+            inputs: vec![],
+            result,
+        })
+    }
+
+    #[test]
+    fn test_model() {
+        let mut model = Model::default();
+        model.apply(&action(0, Action::Loot, true));
+        model.apply(&action(0, Action::Loot, false));
+        model.apply(&action(0, Action::Discard, true));
+        model.apply(&action(1, Action::UseHpPot, true));
+        for i in 0..MAX_WARNINGS + 1 {
+            model.apply(&Event::Log {
+                window: Some(1),
+                level: Level::Warn,
+                message: format!("warning {}", i),
+            });
+        }
+        model.apply(&Event::Log {
+            window: Some(1),
+            level: Level::Info,
+            message: "just info".to_string(),
+        });
+        model.apply(&Event::Log {
+            window: None,
+            level: Level::Error,
+            message: "shared".to_string(),
+        });
+
+        let first = &model.panels[&0];
+        assert_eq!((first.looted, first.discarded), (1, 1));
+        assert_eq!(first.action.as_deref(), Some("Discard"));
+        let second = &model.panels[&1];
+        assert_eq!(second.action.as_deref(), Some("UseHpPot"));
+        assert_eq!(second.warnings.len(), MAX_WARNINGS);
+        assert_eq!(second.warnings.front().unwrap(), "warning 1");
+        assert_eq!(model.warnings, vec!["shared".to_string()]);
+    }
+
+    #[test]
+    fn test_is_quit() {
+        assert!(is_quit(&KeyEvent::new(
+            KeyCode::Char('c'),
+            KeyModifiers::CONTROL
+        )));
+        assert!(is_quit(&KeyEvent::new(
+            KeyCode::Char('q'),
+            KeyModifiers::NONE
+        )));
+        assert!(!is_quit(&KeyEvent::new(
+            KeyCode::Char('c'),
+            KeyModifiers::NONE
+        )));
+        let mut release = KeyEvent::new(KeyCode::Char('q'), KeyModifiers::NONE);
+        release.kind = KeyEventKind::Release;
+        assert!(!is_quit(&release));
+    }
+}
@@ -1,3 +1,6 @@
+use crate::frame::Frame;
+use crate::log_error;
+use crate::log_info;
 use crate::simulation::keys::Key;
 use colored::*;
 use std::ffi::{OsStr, OsString};
@@ -13,8 +16,8 @@ use windows::Win32::Foundation::{
 use windows::Win32::Graphics::Gdi::{
     BI_RGB, BITMAPINFO, BITMAPINFOHEADER, BitBlt, ClientToScreen, CreateCompatibleBitmap,
     CreateCompatibleDC, CreatePen, DIB_RGB_COLORS, DeleteDC, DeleteObject, GetDC, GetDIBits,
-    GetPixel, GetStockObject, HGDIOBJ, NULL_BRUSH, PS_SOLID, Rectangle, ReleaseDC, SRCCOPY,
-    ScreenToClient, SelectObject, SetPixel,
+    GetPixel, GetStockObject, HBITMAP, HDC, HGDIOBJ, NULL_BRUSH, PS_SOLID, Rectangle, ReleaseDC,
+    SRCCOPY, ScreenToClient, SelectObject, SetPixel,
 };
 use windows::Win32::System::Threading::{AttachThreadInput, GetCurrentThreadId};
 use windows::Win32::UI::HiDpi::{PROCESS_PER_MONITOR_DPI_AWARE, SetProcessDpiAwareness};
@@ -157,7 +160,7 @@ unsafe extern "system" fn enum_windows_proc(hwnd: HWND, _: LPARAM) -> windows::c
             buffer.truncate(copied as usize);
             let title = OsString::from_wide(&buffer).to_string_lossy().into_owned();
 
-            println!("HWND: {:?}, Title: {}", hwnd, title);
+            log_info!("HWND: {:?}, Title: {}", hwnd, title);
         }
         true.into()
     }
@@ -187,6 +190,10 @@ pub fn focus_window(hwnd_opt: Option<HWND>) -> windows::core::BOOL {
     }
 }
 
+pub fn get_foreground_window() -> HWND {
+    unsafe { GetForegroundWindow() }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 pub struct PixelColor(pub u32);
 
@@ -211,18 +218,9 @@ impl PixelColor {
         (self.r(), self.g(), self.b())
     }
 
-    pub fn println(&self) {
-        println!(
-            "{}",
-            format!("{}", self).truecolor(self.r(), self.g(), self.b())
-        );
-    }
-
-    pub fn print(&self) {
-        print!(
-            "{}",
-            format!("{}", self).truecolor(self.r(), self.g(), self.b())
-        );
+    // the hex code in its own color, for the debug logs
+    pub fn colored(&self) -> ColoredString {
+        format!("{}", self).truecolor(self.r(), self.g(), self.b())
     }
 
     pub fn is_similar_to(&self, other: PixelColor, tolerance: u8) -> bool {
@@ -335,7 +333,7 @@ fn save_bmp_file<T: AsRef<str>>(
         )
     })?;
     file.write_all(pixels)?;
-    println!("Saved the bmp file to {}", filename.as_ref());
+    log_info!("Saved the bmp file to {}", filename.as_ref());
     Ok(())
 }
 
@@ -343,94 +341,101 @@ pub fn debug_screen<T: AsRef<str>>(
     hwnd_opt: Option<HWND>,
     file_name: T,
 ) -> windows::core::Result<()> {
-    unsafe {
-        let hwnd = hwnd_opt.ok_or_else(|| Error::from(ERROR_INVALID_WINDOW_HANDLE))?;
-        let hdc_window = GetDC(None);
-        if hdc_window.0.is_null() {
-            return Err(Error::from(GetLastError()));
-        }
-
-        let hdc_mem = CreateCompatibleDC(Some(hdc_window));
-        if hdc_mem.0.is_null() {
-            return Err(Error::from(GetLastError()));
-        }
-
-        let mut rect = RECT::default();
-        GetClientRect(hwnd, &mut rect).map_err(|_| Error::from(GetLastError()))?;
+    let (width, height, buffer) = capture_bgra(hwnd_opt)?;
+    save_bmp_file(file_name.as_ref(), width, height, &buffer)?;
+    Ok(())
+}
 
-        let width = rect.right - rect.left;
-        let height = rect.bottom - rect.top;
+pub fn scan_line(
+    hwnd_opt: Option<HWND>,
+    x1: i32,
+    x2: i32,
+    y: i32,
+    debug: bool,
+) -> windows::core::Result<Vec<PixelColor>> {
+    let (width, height, mut buffer) = capture_bgra(hwnd_opt)?;
+    let row_stride = (width * 4) as usize;
+
+    let px1 = x1.clamp(0, width - 1) as usize;
+    let px2 = x2.clamp(0, width - 1) as usize;
+    let py = y.clamp(0, height - 1) as usize;
+
+    let index_from = py * row_stride + px1 * 4;
+    let index_to = py * row_stride + px2 * 4;
+
+    // //DEBUG: write a bmp with line outline
+    if debug {
+        let blue = 0x000000FF;
+        let top_idx_from = index_from - row_stride;
+        let top_idx_to = index_to - row_stride;
+        for idx in (top_idx_from..=top_idx_to).step_by(4) {
+            buffer[idx] = blue;
+        }
+        let bot_idx_from = index_from + row_stride;
+        let bot_idx_to = index_to + row_stride;
+        for idx in (bot_idx_from..=bot_idx_to).step_by(4) {
+            buffer[idx] = blue;
+        }
+        save_bmp_file("test.bmp", width, height, &buffer)?;
+    }
 
-        let hbitmap = CreateCompatibleBitmap(hdc_window, width, height);
-        let old_obj = SelectObject(hdc_mem, HGDIOBJ(hbitmap.0));
+    let mut result: Vec<PixelColor> = vec![];
+    for idx in (index_from..=index_to).step_by(4) {
+        let blue = buffer[idx];
+        let green = buffer[idx + 1];
+        let red = buffer[idx + 2];
 
-        let mut point = POINT { x: 0, y: 0 };
-        ClientToScreen(hwnd, &mut point).as_bool();
+        let color = (red as u32) | ((green as u32) << 8) | ((blue as u32) << 16);
+        result.push(PixelColor(color));
+    }
+    Ok(result)
+}
 
-        BitBlt(
-            hdc_mem,
-            0,
-            0,
-            width,
-            height,
-            Some(hdc_window),
-            point.x,
-            point.y,
-            SRCCOPY,
-        )
-        .map_err(|_| Error::from(GetLastError()))?;
+// grabs the whole client area at once, so multiple markers can be checked against the same frame
+pub fn capture_frame(hwnd_opt: Option<HWND>) -> windows::core::Result<Frame> {
+    let (width, height, buffer) = capture_bgra(hwnd_opt)?;
+    Ok(Frame::from_bgra(width, height, &buffer))
+}
 
-        let mut bmi: BITMAPINFO = zeroed();
-        bmi.bmiHeader.biSize = size_of::<BITMAPINFOHEADER>() as u32;
-        bmi.bmiHeader.biWidth = width;
-        bmi.bmiHeader.biHeight = -height; // negative to indicate top-down DIB
-        bmi.bmiHeader.biPlanes = 1;
-        bmi.bmiHeader.biBitCount = 32; // We want BGRA (4 bytes per pixel)
-        bmi.bmiHeader.biCompression = BI_RGB.0;
+// the GDI objects below are released on every return path, the early ones included
+struct ScreenDc(HDC);
 
-        let row_stride = (width * 4) as usize;
-        let image_size = row_stride * (height as usize);
-        let mut buffer = vec![0u8; image_size];
+impl Drop for ScreenDc {
+    fn drop(&mut self) {
+        unsafe {
+            _ = ReleaseDC(None, self.0);
+        }
+    }
+}
 
-        let res = GetDIBits(
-            hdc_mem,
-            hbitmap,
-            0,
-            height as u32,
-            Some(buffer.as_mut_ptr() as *mut _),
-            &mut bmi,
-            DIB_RGB_COLORS,
-        );
+struct MemoryDc(HDC);
 
-        if res == 0 {
-            return Err(Error::from(GetLastError()));
+impl Drop for MemoryDc {
+    fn drop(&mut self) {
+        unsafe {
+            _ = DeleteDC(self.0).as_bool();
         }
+    }
+}
 
-        save_bmp_file(file_name.as_ref(), width, height, &buffer)?;
+// selected into the memory DC until dropped
+struct SelectedBitmap {
+    hdc: HDC,
+    hbitmap: HBITMAP,
+    old_obj: HGDIOBJ,
+}
 
-        if SelectObject(hdc_mem, old_obj).0.is_null() {
-            return Err(Error::from(GetLastError()));
-        }
-        if !DeleteObject(HGDIOBJ(hbitmap.0)).as_bool() {
-            return Err(Error::from(GetLastError()));
-        }
-        if !DeleteDC(hdc_mem).as_bool() {
-            return Err(Error::from(GetLastError()));
+impl Drop for SelectedBitmap {
+    fn drop(&mut self) {
+        unsafe {
+            SelectObject(self.hdc, self.old_obj);
+            _ = DeleteObject(HGDIOBJ(self.hbitmap.0)).as_bool();
         }
-        if ReleaseDC(None, hdc_window) == 0 {
-            return Err(Error::from(GetLastError()));
-        }
-        Ok(())
     }
 }
 
-pub fn scan_line(
-    hwnd_opt: Option<HWND>,
-    x1: i32,
-    x2: i32,
-    y: i32,
-    debug: bool,
-) -> windows::core::Result<Vec<PixelColor>> {
+// width, height and the top-down BGRA pixels of the client area
+fn capture_bgra(hwnd_opt: Option<HWND>) -> windows::core::Result<(i32, i32, Vec<u8>)> {
     unsafe {
         let hwnd = hwnd_opt.ok_or_else(|| Error::from(ERROR_INVALID_WINDOW_HANDLE))?;
 
@@ -438,11 +443,13 @@ pub fn scan_line(
         if hdc_window.0.is_null() {
             return Err(Error::from(GetLastError()));
         }
+        let hdc_window = ScreenDc(hdc_window);
 
-        let hdc_mem = CreateCompatibleDC(Some(hdc_window));
+        let hdc_mem = CreateCompatibleDC(Some(hdc_window.0));
         if hdc_mem.0.is_null() {
             return Err(Error::from(GetLastError()));
         }
+        let hdc_mem = MemoryDc(hdc_mem);
 
         // Get window size
         let mut rect = RECT::default();
@@ -451,20 +458,27 @@ pub fn scan_line(
         let width = rect.right - rect.left;
         let height = rect.bottom - rect.top;
 
-        let hbitmap = CreateCompatibleBitmap(hdc_window, width, height);
-        let old_obj = SelectObject(hdc_mem, HGDIOBJ(hbitmap.0));
+        let hbitmap = CreateCompatibleBitmap(hdc_window.0, width, height);
+        if hbitmap.0.is_null() {
+            return Err(Error::from(GetLastError()));
+        }
+        let bitmap = SelectedBitmap {
+            hdc: hdc_mem.0,
+            hbitmap,
+            old_obj: SelectObject(hdc_mem.0, HGDIOBJ(hbitmap.0)),
+        };
 
         let mut point = POINT { x: 0, y: 0 };
         ClientToScreen(hwnd, &mut point).as_bool();
 
         //copy to mem device context
         BitBlt(
-            hdc_mem,
+            hdc_mem.0,
             0,
             0,
             width,
             height,
-            Some(hdc_window),
+            Some(hdc_window.0),
             point.x,
             point.y,
             SRCCOPY,
@@ -485,8 +499,8 @@ pub fn scan_line(
         let mut buffer = vec![0u8; image_size];
 
         let res = GetDIBits(
-            hdc_mem,
-            hbitmap,
+            hdc_mem.0,
+            bitmap.hbitmap,
             0,
             height as u32,
             Some(buffer.as_mut_ptr() as *mut _),
@@ -497,57 +511,19 @@ pub fn scan_line(
         if res == 0 {
             return Err(Error::from(GetLastError()));
         }
-
-        let px1 = x1.clamp(0, width - 1) as usize;
-        let px2 = x2.clamp(0, width - 1) as usize;
-        let py = y.clamp(0, height - 1) as usize;
-
-        let index_from = py * row_stride + px1 * 4;
-        let index_to = py * row_stride + px2 * 4;
-
-        // //DEBUG: write a bmp with line outline
-        if debug {
-            let blue = 0x000000FF;
-            let top_idx_from = index_from - row_stride;
-            let top_idx_to = index_to - row_stride;
-            for idx in (top_idx_from..=top_idx_to).step_by(4) {
-                buffer[idx] = blue;
-            }
-            let bot_idx_from = index_from + row_stride;
-            let bot_idx_to = index_to + row_stride;
-            for idx in (bot_idx_from..=bot_idx_to).step_by(4) {
-                buffer[idx] = blue;
-            }
-            save_bmp_file("test.bmp", width, height, &buffer)?;
-        }
-
-        let mut result: Vec<PixelColor> = vec![];
-        for idx in (index_from..=index_to).step_by(4) {
-            let blue = buffer[idx];
-            let green = buffer[idx + 1];
-            let red = buffer[idx + 2];
-
-            let color = (red as u32) | ((green as u32) << 8) | ((blue as u32) << 16);
-            result.push(PixelColor(color));
-        }
-
-        // Clean up
-        if SelectObject(hdc_mem, old_obj).0.is_null() {
-            return Err(Error::from(GetLastError()));
-        }
-        if !DeleteObject(HGDIOBJ(hbitmap.0)).as_bool() {
-            return Err(Error::from(GetLastError()));
-        }
-        if !DeleteDC(hdc_mem).as_bool() {
-            return Err(Error::from(GetLastError()));
-        }
-        if ReleaseDC(None, hdc_window) == 0 {
-            return Err(Error::from(GetLastError()));
-        }
-        Ok(result)
+        Ok((width, height, buffer))
     }
 }
 
+// e.g. a scanned line, space separated
+pub fn join_colored(colors: &[PixelColor]) -> String {
+    colors
+        .iter()
+        .map(|color| color.colored().to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 pub fn debug_mouse_color(_hwnd: HWND) {
     unsafe {
         let mut pt = POINT::default();
@@ -555,21 +531,16 @@ pub fn debug_mouse_color(_hwnd: HWND) {
             return;
         }
         match get_pixel_color_screen(pt.x, pt.y) {
-            Ok(color) => {
-                print!("Color: ");
-                color.println()
-            }
-            Err(e) => {
-                eprintln!("Failed to get color at [{}, {}]: {:?}", pt.x, pt.y, e);
-            }
+            Ok(color) => log_info!("Color: {}", color.colored()),
+            Err(e) => log_error!("Failed to get color at [{}, {}]: {:?}", pt.x, pt.y, e),
         }
 
         if DEBUG_RECTANGLE && debug_rectangle(pt.x - 5, pt.y - 5, pt.x + 5, pt.y + 5).is_err() {
-            eprintln!("Failed to draw a rectangle");
+            log_error!("Failed to draw a rectangle");
         }
 
         if DEBUG_DOT && debug_dot(pt.x, pt.y).is_err() {
-            eprintln!("Failed to draw a dot");
+            log_error!("Failed to draw a dot");
         }
     }
 }
@@ -589,20 +560,35 @@ pub fn set_mouse(hwnd_opt: Option<HWND>, x: i32, y: i32, click: bool) -> bool {
 
         if click {
             return if send_mouse_click(0, 0, true).is_err() {
-                eprintln!(
+                log_error!(
                     "{}",
                     format!("Failed to mouse click: {:?}", pt).bright_magenta()
                 );
-                true
-            } else {
-                println!("{}", format!("Clicked mouse: {:?}", pt).bright_magenta());
                 false
+            } else {
+                log_info!("{}", format!("Clicked mouse: {:?}", pt).bright_magenta());
+                true
             };
         }
         true
     }
 }
 
+// moves the mouse over the window client coordinates and right clicks there
+pub fn right_click(hwnd_opt: Option<HWND>, x: i32, y: i32) -> bool {
+    if !set_mouse(hwnd_opt, x, y, false) {
+        return false;
+    }
+    if let Err(e) = send_mouse_click(0, 0, false) {
+        log_error!(
+            "{}",
+            format!("Failed to right click: {:?}", e).bright_magenta()
+        );
+        return false;
+    }
+    true
+}
+
 pub fn debug_scanline(hwnd: HWND, len: i32) {
     unsafe {
         let mut pt = POINT::default();
@@ -613,15 +599,8 @@ pub fn debug_scanline(hwnd: HWND, len: i32) {
             return;
         }
         match scan_line(Some(hwnd), pt.x - len, pt.x + len, pt.y, false) {
-            Ok(colors) => {
-                print!("Colors: ");
-                for color in colors {
-                    color.print();
-                    print!(" ");
-                }
-                println!();
-            }
-            Err(e) => eprintln!(
+            Ok(colors) => log_info!("Colors: {}", join_colored(&colors)),
+            Err(e) => log_error!(
                 "Failed to get color at [{}-{}, {}]: {:?}",
                 pt.x - len,
                 pt.x + len,
@@ -643,9 +622,12 @@ pub fn debug_mouse(hwnd: HWND) {
         if !ScreenToClient(hwnd, &mut pt).as_bool() {
             return;
         }
-        print!(
-            "Mouse at: screen[{}, {}] window[{}, {}]\t",
-            abs_x, abs_y, pt.x, pt.y
+        log_info!(
+            "Mouse at: screen[{}, {}] window[{}, {}]",
+            abs_x,
+            abs_y,
+            pt.x,
+            pt.y
         );
     }
 }
@@ -690,7 +672,7 @@ pub fn debug_rectangle(left: i32, top: i32, right: i32, bottom: i32) -> windows:
         SelectObject(hdc, old_pen);
         SelectObject(hdc, old_brush);
         if !DeleteObject(HGDIOBJ(hpen.0)).as_bool() {
-            eprintln!("Could not delete pen object");
+            log_error!("Could not delete pen object");
         }
         ReleaseDC(hwnd, hdc);
 